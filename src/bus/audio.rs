@@ -1,103 +0,0 @@
-use super::Memory;
-
-#[derive(Default, Debug, Clone, Copy)]
-pub struct AudioRegisters {
-    pub nr52: u8,
-    pub nr51: u8,
-    pub nr50: u8,
-    pub nr10: u8,
-    pub nr11: u8,
-    pub nr12: u8,
-    pub nr13: u8,
-    pub nr14: u8,
-    pub nr21: u8,
-    pub nr22: u8,
-    pub nr23: u8,
-    pub nr24: u8,
-    pub nr30: u8,
-    pub nr31: u8,
-    pub nr32: u8,
-    pub nr33: u8,
-    pub nr34: u8,
-    pub nr41: u8,
-    pub nr42: u8,
-    pub nr43: u8,
-    pub nr44: u8,
-}
-
-impl Memory for AudioRegisters {
-    fn read_byte(&self, address: u16) -> u8 {
-        match address {
-            0xFF10 => self.nr10,
-            0xFF11 => self.nr11,
-            0xFF12 => self.nr12,
-            0xFF13 => self.nr13,
-            0xFF14 => self.nr14,
-            0xFF16 => self.nr21,
-            0xFF17 => self.nr22,
-            0xFF18 => self.nr23,
-            0xFF19 => self.nr24,
-            0xFF1A => self.nr30,
-            0xFF1B => self.nr31,
-            0xFF1C => self.nr32,
-            0xFF1D => self.nr33,
-            0xFF1E => self.nr34,
-            0xFF20 => self.nr41,
-            0xFF21 => self.nr42,
-            0xFF22 => self.nr43,
-            0xFF23 => self.nr44,
-            0xFF24 => self.nr50,
-            0xFF25 => self.nr51,
-            0xFF26 => self.nr52,
-            _ => 0,
-        }
-    }
-
-    fn write_byte(&mut self, address: u16, value: u8) {
-        match address {
-            0xFF10 => self.nr10 = value,
-            0xFF11 => self.nr11 = value,
-            0xFF12 => self.nr12 = value,
-            0xFF13 => self.nr13 = value,
-            0xFF14 => self.nr14 = value,
-            0xFF16 => self.nr21 = value,
-            0xFF17 => self.nr22 = value,
-            0xFF18 => self.nr23 = value,
-            0xFF19 => self.nr24 = value,
-            0xFF1A => self.nr30 = value,
-            0xFF1B => self.nr31 = value,
-            0xFF1C => self.nr32 = value,
-            0xFF1D => self.nr33 = value,
-            0xFF1E => self.nr34 = value,
-            0xFF20 => self.nr41 = value,
-            0xFF21 => self.nr42 = value,
-            0xFF22 => self.nr43 = value,
-            0xFF23 => self.nr44 = value,
-            0xFF24 => self.nr50 = value,
-            0xFF25 => self.nr51 = value,
-            0xFF26 => self.nr52 = value,
-            _ => panic!("Invalid write to AudioRegisters address: {:04X}", address),
-        }
-    }
-}
-
-#[derive(Default, Debug, Clone, Copy)]
-pub struct WavePattern {
-    pub wave_pattern: [u8; 0x10],
-}
-
-impl Memory for WavePattern {
-    fn read_byte(&self, address: u16) -> u8 {
-        match address {
-            0xFF30..=0xFF3F => self.wave_pattern[(address - 0xFF30) as usize],
-            _ => 0,
-        }
-    }
-
-    fn write_byte(&mut self, address: u16, value: u8) {
-        match address {
-            0xFF30..=0xFF3F => self.wave_pattern[(address - 0xFF30) as usize] = value,
-            _ => panic!("Invalid write to WavePattern address: {:04X}", address),
-        }
-    }
-}
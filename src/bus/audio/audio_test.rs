@@ -0,0 +1,348 @@
+#[cfg(test)]
+mod audio_tests {
+    use crate::bus::{Bus, HardwareMode, Memory};
+
+    fn powered_bus() -> Bus {
+        let mut bus = Bus::default();
+        bus.write_byte(0xFF26, 0x80);
+        bus
+    }
+
+    // The frame sequencer steps every 8192 clocks, 2048 M-cycles
+    fn run_frame_sequencer(bus: &mut Bus, steps: usize) {
+        for _ in 0..steps * 2048 {
+            bus.tick();
+        }
+    }
+
+    #[test]
+    fn nr52_reports_power_and_active_channels() {
+        let mut bus = Bus::default();
+        assert_eq!(bus.read_byte(0xFF26), 0x70);
+        bus.write_byte(0xFF26, 0x80);
+        assert_eq!(bus.read_byte(0xFF26), 0xF0);
+
+        bus.write_byte(0xFF12, 0xF0);
+        bus.write_byte(0xFF14, 0x80);
+        assert_eq!(bus.read_byte(0xFF26), 0xF1);
+        // Channel 2 DAC is off, the trigger doesn't start it
+        bus.write_byte(0xFF19, 0x80);
+        assert_eq!(bus.read_byte(0xFF26), 0xF1);
+        bus.write_byte(0xFF17, 0x80);
+        bus.write_byte(0xFF19, 0x80);
+        assert_eq!(bus.read_byte(0xFF26), 0xF3);
+        // Turning the DAC off stops the channel
+        bus.write_byte(0xFF12, 0x00);
+        assert_eq!(bus.read_byte(0xFF26), 0xF2);
+
+        // Powering off clears the registers and ignores writes
+        bus.write_byte(0xFF26, 0x00);
+        assert_eq!(bus.read_byte(0xFF26), 0x70);
+        bus.write_byte(0xFF17, 0xF0);
+        assert_eq!(bus.read_byte(0xFF17), 0x00);
+        assert_eq!(bus.read_byte(0xFF11), 0x3F);
+    }
+
+    #[test]
+    fn length_counter_stops_the_channel() {
+        let mut bus = powered_bus();
+        bus.write_byte(0xFF11, 0x3E);
+        bus.write_byte(0xFF12, 0xF0);
+        bus.write_byte(0xFF14, 0xC0);
+        assert_eq!(bus.read_byte(0xFF14), 0xFF);
+
+        // Lengths are clocked on steps 0 and 2
+        run_frame_sequencer(&mut bus, 2);
+        assert!(bus.io.audio.square1.enabled);
+        run_frame_sequencer(&mut bus, 1);
+        assert!(!bus.io.audio.square1.enabled);
+        assert_eq!(bus.read_byte(0xFF26), 0xF0);
+    }
+
+    #[test]
+    fn envelope_changes_volume_at_64_hz() {
+        let mut bus = powered_bus();
+        bus.write_byte(0xFF17, 0x19);
+        bus.write_byte(0xFF19, 0x80);
+        assert_eq!(bus.io.audio.square2.envelope.volume, 1);
+
+        run_frame_sequencer(&mut bus, 7);
+        assert_eq!(bus.io.audio.square2.envelope.volume, 1);
+        run_frame_sequencer(&mut bus, 1);
+        assert_eq!(bus.io.audio.square2.envelope.volume, 2);
+        run_frame_sequencer(&mut bus, 8);
+        assert_eq!(bus.io.audio.square2.envelope.volume, 3);
+    }
+
+    #[test]
+    fn sweep_overflow_disables_channel_1() {
+        let mut bus = powered_bus();
+        bus.write_byte(0xFF12, 0xF0);
+
+        // Checked on trigger when the shift isn't 0
+        bus.write_byte(0xFF10, 0x01);
+        bus.write_byte(0xFF13, 0xFF);
+        bus.write_byte(0xFF14, 0x87);
+        assert!(!bus.io.audio.square1.enabled);
+
+        // The sweep updates the frequency on step 2, then the next one overflows
+        bus.write_byte(0xFF10, 0x11);
+        bus.write_byte(0xFF13, 0x00);
+        bus.write_byte(0xFF14, 0x85);
+        assert!(bus.io.audio.square1.enabled);
+        run_frame_sequencer(&mut bus, 3);
+        assert_eq!(bus.io.audio.square1.frequency, 0x780);
+        assert!(!bus.io.audio.square1.enabled);
+
+        // Clearing negate after a subtraction stops the channel
+        bus.write_byte(0xFF10, 0x19);
+        bus.write_byte(0xFF14, 0x85);
+        assert!(bus.io.audio.square1.enabled);
+        run_frame_sequencer(&mut bus, 3);
+        assert!(bus.io.audio.square1.enabled);
+        bus.write_byte(0xFF10, 0x11);
+        assert!(!bus.io.audio.square1.enabled);
+    }
+
+    #[test]
+    fn duty_cycle_waveform() {
+        let mut bus = powered_bus();
+        // 50% duty, period of 4 clocks so the duty steps once per M-cycle
+        bus.write_byte(0xFF11, 0x80);
+        bus.write_byte(0xFF12, 0xF0);
+        bus.write_byte(0xFF13, 0xFF);
+        bus.write_byte(0xFF14, 0x87);
+
+        let mut output = vec![bus.io.audio.square1.output()];
+        for _ in 0..8 {
+            bus.tick();
+            output.push(bus.io.audio.square1.output());
+        }
+        assert_eq!(output, [15, 0, 0, 0, 0, 15, 15, 15, 15]);
+    }
+
+    fn start_wave(bus: &mut Bus) {
+        for i in 0..0x10 {
+            bus.write_byte(0xFF30 + i, 0x01 + 0x22 * (i % 8) as u8);
+        }
+        bus.write_byte(0xFF1A, 0x80);
+        bus.write_byte(0xFF1C, 0x20);
+        // Period of 4 clocks, one sample per M-cycle
+        bus.write_byte(0xFF1D, 0xFE);
+        bus.write_byte(0xFF1E, 0x87);
+    }
+
+    #[test]
+    fn wave_channel_plays_wave_ram() {
+        let mut bus = powered_bus();
+        start_wave(&mut bus);
+        assert_eq!(bus.read_byte(0xFF26), 0xF4);
+
+        let mut output = vec![];
+        for _ in 0..4 {
+            bus.tick();
+            output.push(bus.io.audio.wave.output());
+        }
+        // Starts from the second sample, high nibble first
+        assert_eq!(output, [0x1, 0x2, 0x3, 0x4]);
+
+        // NR32 shifts the samples right
+        bus.write_byte(0xFF1C, 0x40);
+        bus.tick();
+        assert_eq!(bus.io.audio.wave.output(), 0x5 >> 1);
+        bus.write_byte(0xFF1C, 0x00);
+        assert_eq!(bus.io.audio.wave.output(), 0);
+        assert_eq!(bus.read_byte(0xFF1C), 0x9F);
+
+        // Turning the DAC off stops the channel
+        bus.write_byte(0xFF1A, 0x00);
+        assert_eq!(bus.read_byte(0xFF26), 0xF0);
+    }
+
+    #[test]
+    fn wave_length_is_256_steps() {
+        let mut bus = powered_bus();
+        bus.write_byte(0xFF1A, 0x80);
+        bus.write_byte(0xFF1B, 0xFE);
+        bus.write_byte(0xFF1E, 0xC0);
+        run_frame_sequencer(&mut bus, 2);
+        assert!(bus.io.audio.wave.enabled);
+        run_frame_sequencer(&mut bus, 1);
+        assert!(!bus.io.audio.wave.enabled);
+
+        // A trigger with an expired length plays for the whole 256 steps
+        bus.write_byte(0xFF1E, 0xC0);
+        run_frame_sequencer(&mut bus, 510);
+        assert!(bus.io.audio.wave.enabled);
+        run_frame_sequencer(&mut bus, 2);
+        assert!(!bus.io.audio.wave.enabled);
+    }
+
+    #[test]
+    fn wave_ram_access_while_playing() {
+        let mut bus = powered_bus();
+        bus.mode = HardwareMode::CGB;
+        start_wave(&mut bus);
+        // The CGB accesses the byte being played whatever the address
+        bus.tick();
+        bus.tick();
+        assert_eq!(bus.read_byte(0xFF3F), 0x23);
+        bus.write_byte(0xFF30, 0xAB);
+        assert_eq!(bus.io.audio.wave.ram[1], 0xAB);
+
+        let mut bus = powered_bus();
+        start_wave(&mut bus);
+        // The DMG only in the M-cycle the channel read it
+        bus.tick();
+        assert_eq!(bus.read_byte(0xFF3F), 0x01);
+        bus.write_byte(0xFF1D, 0xFC);
+        bus.tick();
+        bus.tick();
+        assert_eq!(bus.read_byte(0xFF3F), 0xFF);
+        bus.write_byte(0xFF3F, 0x00);
+        assert_eq!(bus.io.audio.wave.ram[0x0F], 0xEF);
+
+        // Wave RAM is reachable with the channel stopped, even with the APU off
+        bus.write_byte(0xFF26, 0x00);
+        bus.write_byte(0xFF3F, 0x42);
+        assert_eq!(bus.read_byte(0xFF3F), 0x42);
+        assert_eq!(bus.read_byte(0xFF30), 0x01);
+    }
+
+    #[test]
+    fn noise_lfsr_widths() {
+        let mut bus = powered_bus();
+        bus.write_byte(0xFF21, 0xF0);
+        // Divisor 8, no shift, the LFSR shifts every 2 M-cycles
+        bus.write_byte(0xFF22, 0x00);
+        bus.write_byte(0xFF23, 0x80);
+        assert_eq!(bus.read_byte(0xFF26), 0xF8);
+        assert_eq!(bus.read_byte(0xFF22), 0x00);
+
+        // All ones at first, then ones shifted out until the first feedback bit
+        let mut output = vec![];
+        for _ in 0..16 {
+            bus.tick();
+            bus.tick();
+            output.push(bus.io.audio.noise.output());
+        }
+        assert!(output[..14].iter().all(|&sample| sample == 0));
+        assert_eq!(output[14], 15);
+
+        // The 7 bits LFSR repeats every 127 shifts
+        bus.write_byte(0xFF22, 0x08);
+        bus.write_byte(0xFF23, 0x80);
+        assert_eq!(bus.read_byte(0xFF22), 0x08);
+        let mut output = vec![];
+        for _ in 0..254 {
+            bus.tick();
+            bus.tick();
+            output.push(bus.io.audio.noise.output());
+        }
+        assert_eq!(output[..127], output[127..]);
+        assert!(output.contains(&15) && output.contains(&0));
+    }
+
+    #[test]
+    fn noise_lfsr_frozen_with_clock_shift_14_and_15() {
+        for nr43 in [0xE0, 0xF0] {
+            let mut bus = powered_bus();
+            bus.write_byte(0xFF21, 0xF0);
+            bus.write_byte(0xFF22, nr43);
+            bus.write_byte(0xFF23, 0x80);
+            // Long enough for the 15 shifts turning the output on at a lower clock shift
+            for _ in 0..16 * (2 << 15) {
+                bus.tick();
+            }
+            assert!(bus.io.audio.noise.enabled);
+            assert_eq!(bus.io.audio.noise.output(), 0);
+        }
+    }
+
+    #[test]
+    fn noise_envelope_and_length() {
+        let mut bus = powered_bus();
+        bus.write_byte(0xFF20, 0x3F);
+        bus.write_byte(0xFF21, 0xA1);
+        bus.write_byte(0xFF23, 0xC0);
+        assert_eq!(bus.io.audio.noise.envelope.volume, 0x0A);
+        run_frame_sequencer(&mut bus, 1);
+        assert!(!bus.io.audio.noise.enabled);
+
+        bus.write_byte(0xFF23, 0x80);
+        run_frame_sequencer(&mut bus, 8);
+        assert_eq!(bus.io.audio.noise.envelope.volume, 0x09);
+        assert!(bus.io.audio.noise.enabled);
+    }
+
+    #[test]
+    fn mixer_routes_channels_to_each_side() {
+        let mut bus = powered_bus();
+        assert_eq!(bus.io.audio.mix(), (0.0, 0.0));
+        // Channel 1 at full volume, high on the first duty step
+        bus.write_byte(0xFF11, 0x80);
+        bus.write_byte(0xFF12, 0xF0);
+        bus.write_byte(0xFF14, 0x80);
+        assert_eq!(bus.io.audio.square1.output(), 15);
+
+        bus.write_byte(0xFF24, 0x70);
+        bus.write_byte(0xFF25, 0x10);
+        assert_eq!(bus.io.audio.mix(), (-0.25, 0.0));
+        bus.write_byte(0xFF24, 0x03);
+        bus.write_byte(0xFF25, 0x11);
+        assert_eq!(bus.io.audio.mix(), (-1.0 / 32.0, -0.125));
+
+        // Enabled DACs output their level even when the channel is stopped
+        bus.write_byte(0xFF1A, 0x80);
+        bus.write_byte(0xFF25, 0x44);
+        assert_eq!(bus.io.audio.mix(), (0.125 / 4.0, 0.5 / 4.0));
+    }
+
+    fn drain_all(bus: &mut Bus) -> Vec<i16> {
+        let mut samples = vec![0; bus.audio_output.available() * 2];
+        let frames = bus.audio_output.drain(&mut samples);
+        assert_eq!(frames * 2, samples.len());
+        samples
+    }
+
+    #[test]
+    fn output_is_resampled_and_dc_blocked() {
+        let mut bus = powered_bus();
+        // About 1 kHz on both sides
+        bus.write_byte(0xFF24, 0x77);
+        bus.write_byte(0xFF25, 0x11);
+        bus.write_byte(0xFF11, 0x80);
+        bus.write_byte(0xFF12, 0xF0);
+        bus.write_byte(0xFF13, 0x80);
+        bus.write_byte(0xFF14, 0x87);
+
+        // A tenth of a second at 48 kHz
+        for _ in 0..104_858 {
+            bus.tick();
+        }
+        let samples = drain_all(&mut bus);
+        assert_eq!(samples.len(), 4800 * 2);
+        assert_eq!(bus.audio_output.available(), 0);
+
+        let left = samples.iter().step_by(2).copied().collect::<Vec<_>>();
+        assert!(left.iter().any(|&sample| sample > 4000));
+        assert!(left.iter().any(|&sample| sample < -4000));
+        // Band-limited, the first step ramps in over a few samples
+        assert_eq!(left[0], 0);
+        assert!(left[..16]
+            .iter()
+            .any(|&sample| sample != 0 && sample.abs() < 2000));
+        assert!(samples.chunks(2).all(|frame| frame[0] == frame[1]));
+
+        // The DC offset of a silent but enabled DAC fades out
+        bus.write_byte(0xFF12, 0x08);
+        bus.write_byte(0xFF14, 0x87);
+        for _ in 0..1_048_576 {
+            bus.tick();
+        }
+        let samples = drain_all(&mut bus);
+        assert!(samples[samples.len() - 200..]
+            .iter()
+            .all(|sample| sample.abs() < 10));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::save_state::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+use self::{noise::NoiseChannel, square::SquareChannel, wave::WaveChannel};
+
+use super::Memory;
+
+mod audio_test;
+pub mod noise;
+pub mod output;
+pub mod square;
+pub mod units;
+pub mod wave;
+
+/// APU registers NR10-NR52 (0xFF10-0xFF26) and wave RAM (0xFF30-0xFF3F)
+#[derive(Debug, Clone, Copy)]
+pub struct AudioRegisters {
+    // NR52 bit 7, all the registers but wave RAM are cleared and read only while powered
+    // off
+    pub enabled: bool,
+    pub nr51: u8,
+    pub nr50: u8,
+    pub square1: SquareChannel,
+    pub square2: SquareChannel,
+    pub wave: WaveChannel,
+    pub noise: NoiseChannel,
+    // Step of the 512 Hz frame sequencer, 0 to 7
+    frame_sequencer: u8,
+}
+
+impl Default for AudioRegisters {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            nr51: 0,
+            nr50: 0,
+            square1: SquareChannel::new(true),
+            square2: SquareChannel::new(false),
+            wave: WaveChannel::default(),
+            noise: NoiseChannel::default(),
+            frame_sequencer: 0,
+        }
+    }
+}
+
+impl AudioRegisters {
+    /// Advance the channels by `clocks` clocks of the 4 MiHz APU clock
+    pub fn tick(&mut self, clocks: u8) {
+        if !self.enabled {
+            return;
+        }
+        self.square1.tick(clocks);
+        self.square2.tick(clocks);
+        self.wave.tick(clocks);
+        self.noise.tick(clocks);
+    }
+
+    /// Clocked at 512 Hz from DIV. Length counters run at 256 Hz, the sweep at 128 Hz
+    /// and the envelopes at 64 Hz
+    pub fn frame_sequencer_step(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.frame_sequencer & 1 == 0 {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_sequencer == 2 || self.frame_sequencer == 6 {
+            self.square1.clock_sweep();
+        }
+        if self.frame_sequencer == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+        self.frame_sequencer = (self.frame_sequencer + 1) % 8;
+    }
+
+    /// Left and right output, from -1 to 1. NR51 routes each channel to the left and
+    /// right terminals, NR50 sets their volume
+    pub fn mix(&self) -> (f32, f32) {
+        if !self.enabled {
+            return (0.0, 0.0);
+        }
+        let channels = [
+            dac(self.square1.envelope.dac_enabled(), self.square1.output()),
+            dac(self.square2.envelope.dac_enabled(), self.square2.output()),
+            dac(self.wave.dac_enabled, self.wave.output()),
+            dac(self.noise.envelope.dac_enabled(), self.noise.output()),
+        ];
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (index, output) in channels.into_iter().enumerate() {
+            if self.nr51 & (0b0001_0000 << index) != 0 {
+                left += output;
+            }
+            if self.nr51 & (0b0000_0001 << index) != 0 {
+                right += output;
+            }
+        }
+        let left_volume = ((self.nr50 >> 4) & 0b111) as f32 + 1.0;
+        let right_volume = (self.nr50 & 0b111) as f32 + 1.0;
+        (left * left_volume / 32.0, right * right_volume / 32.0)
+    }
+
+    fn read_nr52(&self) -> u8 {
+        let mut value = 0b0111_0000;
+        if self.enabled {
+            value |= 0b1000_0000;
+        }
+        if self.square1.enabled {
+            value |= 0b0000_0001;
+        }
+        if self.square2.enabled {
+            value |= 0b0000_0010;
+        }
+        if self.wave.enabled {
+            value |= 0b0000_0100;
+        }
+        if self.noise.enabled {
+            value |= 0b0000_1000;
+        }
+        value
+    }
+
+    fn write_nr52(&mut self, value: u8) {
+        let enabled = value & 0b1000_0000 != 0;
+        if !enabled {
+            let ram = self.wave.ram;
+            *self = Self::default();
+            self.wave.ram = ram;
+        } else if !self.enabled {
+            self.frame_sequencer = 0;
+        }
+        self.enabled = enabled;
+    }
+}
+
+/// Channel DACs turn the digital 0 to 15 output into -1 to 1, a disabled DAC outputs 0
+fn dac(enabled: bool, output: u8) -> f32 {
+    if enabled {
+        1.0 - output as f32 / 7.5
+    } else {
+        0.0
+    }
+}
+
+impl Memory for AudioRegisters {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0xFF10..=0xFF14 => self.square1.read_register(address - 0xFF10),
+            0xFF16..=0xFF19 => self.square2.read_register(address - 0xFF15),
+            0xFF1A..=0xFF1E => self.wave.read_register(address - 0xFF1A),
+            0xFF20..=0xFF23 => self.noise.read_register(address - 0xFF1F),
+            0xFF24 => self.nr50,
+            0xFF25 => self.nr51,
+            0xFF26 => self.read_nr52(),
+            0xFF30..=0xFF3F => self.wave.read_ram(address, false),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        if !self.enabled && !matches!(address, 0xFF26 | 0xFF30..=0xFF3F) {
+            return;
+        }
+        match address {
+            0xFF10..=0xFF14 => self.square1.write_register(address - 0xFF10, value),
+            0xFF15 => (),
+            0xFF16..=0xFF19 => self.square2.write_register(address - 0xFF15, value),
+            0xFF1A..=0xFF1E => self.wave.write_register(address - 0xFF1A, value),
+            0xFF1F => (),
+            0xFF20..=0xFF23 => self.noise.write_register(address - 0xFF1F, value),
+            0xFF24 => self.nr50 = value,
+            0xFF25 => self.nr51 = value,
+            0xFF26 => self.write_nr52(value),
+            0xFF30..=0xFF3F => self.wave.write_ram(address, value, false),
+            _ => panic!("Invalid write to AudioRegisters address: {:04X}", address),
+        }
+    }
+}
+
+impl Snapshot for AudioRegisters {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.nr51);
+        writer.write_u8(self.nr50);
+        self.square1.save_state(writer);
+        self.square2.save_state(writer);
+        self.wave.save_state(writer);
+        self.noise.save_state(writer);
+        writer.write_u8(self.frame_sequencer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enabled = reader.read_bool()?;
+        self.nr51 = reader.read_u8()?;
+        self.nr50 = reader.read_u8()?;
+        self.square1.load_state(reader)?;
+        self.square2.load_state(reader)?;
+        self.wave.load_state(reader)?;
+        self.noise.load_state(reader)?;
+        self.frame_sequencer = reader.read_u8()? % 8;
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+use crate::save_state::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+use super::units::{Envelope, LengthCounter};
+
+// Clocks between LFSR shifts for each NR43 divisor code, before the clock shift
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// Channel 4, white noise from a linear feedback shift register
+#[derive(Debug, Clone, Copy)]
+pub struct NoiseChannel {
+    pub enabled: bool,
+    // NR43 fields
+    pub clock_shift: u8,
+    // 7 bits LFSR, a more metallic periodic noise
+    pub short_width: bool,
+    pub divisor_code: u8,
+    lfsr: u16,
+    timer: u32,
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+}
+
+impl Default for NoiseChannel {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            clock_shift: 0,
+            short_width: false,
+            divisor_code: 0,
+            lfsr: 0x7FFF,
+            timer: 0,
+            length: LengthCounter::default(),
+            envelope: Envelope::default(),
+        }
+    }
+}
+
+impl NoiseChannel {
+    fn period(&self) -> u32 {
+        DIVISORS[self.divisor_code as usize] << self.clock_shift
+    }
+
+    fn read_nr43(&self) -> u8 {
+        let mut value = self.clock_shift << 4 | self.divisor_code;
+        if self.short_width {
+            value |= 0b0000_1000;
+        }
+        value
+    }
+
+    /// Read NR41-NR44 (registers 1 to 4), write only bits read as 1
+    pub fn read_register(&self, register: u16) -> u8 {
+        match register {
+            1 => 0xFF,
+            2 => self.envelope.read(),
+            3 => self.read_nr43(),
+            _ => {
+                if self.length.enabled {
+                    0b1111_1111
+                } else {
+                    0b1011_1111
+                }
+            }
+        }
+    }
+
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            1 => self.length.load(64, (value & 0b0011_1111) as u16),
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => {
+                self.clock_shift = value >> 4;
+                self.short_width = value & 0b0000_1000 != 0;
+                self.divisor_code = value & 0b0000_0111;
+            }
+            _ => {
+                self.length.enabled = value & 0b0100_0000 != 0;
+                if value & 0b1000_0000 != 0 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger(64);
+        self.envelope.trigger();
+        self.lfsr = 0x7FFF;
+        self.timer = self.period();
+    }
+
+    /// Advance by `clocks` clocks of the 4 MiHz APU clock
+    pub fn tick(&mut self, clocks: u8) {
+        let mut clocks = clocks as u32;
+        while clocks >= self.timer {
+            clocks -= self.timer;
+            self.timer = self.period();
+            // Clock shifts 14 and 15 leave the LFSR frozen
+            if self.clock_shift < 14 {
+                self.shift_lfsr();
+            }
+        }
+        self.timer -= clocks;
+    }
+
+    fn shift_lfsr(&mut self) {
+        let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+        self.lfsr = (self.lfsr >> 1) | feedback << 14;
+        if self.short_width {
+            self.lfsr = (self.lfsr & !(1 << 6)) | feedback << 6;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Digital output, 0 to 15, high when bit 0 of the LFSR is cleared
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        (!self.lfsr & 1) as u8 * self.envelope.volume
+    }
+}
+
+impl Snapshot for NoiseChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.read_nr43());
+        writer.write_u16(self.lfsr);
+        writer.write_u32(self.timer);
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enabled = reader.read_bool()?;
+        self.write_register(3, reader.read_u8()?);
+        self.lfsr = reader.read_u16()? & 0x7FFF;
+        self.timer = reader.read_u32()?;
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)
+    }
+}
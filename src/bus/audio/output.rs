@@ -0,0 +1,141 @@
+use std::f64::consts::PI;
+
+// Clock of the APU, the same in single and double speed
+pub const APU_CLOCK: u32 = 4_194_304;
+
+// Taps of the band-limited step and sub-sample positions it's computed for
+const WIDTH: usize = 16;
+const PHASES: usize = 32;
+// Cutoff of the DC blocking high-pass filter
+const HIGH_PASS_HZ: f64 = 20.0;
+
+#[derive(Default, Debug, Clone)]
+struct OutputChannel {
+    // Input level, only its changes are written to the buffer
+    level: f32,
+    // Band-limited impulses for each level change, integrated back into a stepped
+    // signal when read
+    deltas: Vec<f32>,
+    sum: f32,
+    // Previous high-pass filter input and output
+    previous: f32,
+    filtered: f32,
+}
+
+/// Resample the mixer output from the APU clock to the host sample rate
+///
+/// Every change of level is added as a windowed sinc step, so the stream is band-limited
+/// to the output rate instead of aliasing. A high-pass filter then removes the DC offset
+/// of the DACs, like the capacitors on the real output.
+#[derive(Debug, Clone)]
+pub struct AudioOutput {
+    pub sample_rate: u32,
+    // Output samples per APU clock
+    step: f64,
+    // Position of the current clock in output samples, from the start of the buffers
+    time: f64,
+    high_pass: f32,
+    kernels: Vec<[f32; WIDTH]>,
+    channels: [OutputChannel; 2],
+}
+
+impl Default for AudioOutput {
+    fn default() -> Self {
+        Self::new(48_000)
+    }
+}
+
+impl AudioOutput {
+    pub fn new(sample_rate: u32) -> Self {
+        let kernels = (0..PHASES)
+            .map(|phase| {
+                let offset = phase as f64 / PHASES as f64;
+                let mut kernel = [0.0; WIDTH];
+                for (tap, value) in kernel.iter_mut().enumerate() {
+                    // Distance to the center of the step, low-pass at 90% of Nyquist
+                    let x = tap as f64 - (WIDTH / 2) as f64 - offset;
+                    let sinc = if x == 0.0 {
+                        1.0
+                    } else {
+                        (PI * 0.9 * x).sin() / (PI * 0.9 * x)
+                    };
+                    let window = 0.42
+                        + 0.5 * (PI * x / (WIDTH / 2) as f64).cos()
+                        + 0.08 * (2.0 * PI * x / (WIDTH / 2) as f64).cos();
+                    *value = sinc * window.max(0.0);
+                }
+                let sum = kernel.iter().sum::<f64>();
+                kernel.map(|value| (value / sum) as f32)
+            })
+            .collect();
+
+        Self {
+            sample_rate,
+            step: sample_rate as f64 / APU_CLOCK as f64,
+            time: 0.0,
+            high_pass: (1.0 - 2.0 * PI * HIGH_PASS_HZ / sample_rate as f64) as f32,
+            kernels,
+            channels: Default::default(),
+        }
+    }
+
+    /// Add `clocks` APU clocks of the left and right mixer output, from -1 to 1
+    pub fn push(&mut self, left: f32, right: f32, clocks: u8) {
+        let base = self.time as usize;
+        let phase = ((self.time - base as f64) * PHASES as f64) as usize;
+        for (channel, level) in self.channels.iter_mut().zip([left, right]) {
+            if level == channel.level {
+                continue;
+            }
+            let delta = level - channel.level;
+            channel.level = level;
+            if channel.deltas.len() < base + WIDTH {
+                channel.deltas.resize(base + WIDTH, 0.0);
+            }
+            for (tap, weight) in self.kernels[phase].iter().enumerate() {
+                channel.deltas[base + tap] += delta * weight;
+            }
+        }
+        self.time += clocks as f64 * self.step;
+
+        // Nobody is reading, only keep the last second
+        let available = self.available();
+        if available > self.sample_rate as usize {
+            self.read(available - self.sample_rate as usize / 2, |_, _| ());
+        }
+    }
+
+    /// Stereo frames ready to be drained
+    pub fn available(&self) -> usize {
+        self.time as usize
+    }
+
+    /// Move the available samples to `buffer` as interleaved left and right 16 bits
+    /// samples. Returns the number of stereo frames written
+    pub fn drain(&mut self, buffer: &mut [i16]) -> usize {
+        let frames = self.available().min(buffer.len() / 2);
+        self.read(frames, |frame, samples| {
+            buffer[frame * 2..frame * 2 + 2].copy_from_slice(&samples)
+        });
+        frames
+    }
+
+    fn read(&mut self, frames: usize, mut write: impl FnMut(usize, [i16; 2])) {
+        for frame in 0..frames {
+            let mut samples = [0; 2];
+            for (channel, sample) in self.channels.iter_mut().zip(&mut samples) {
+                channel.sum += channel.deltas.get(frame).copied().unwrap_or(0.0);
+                let filtered = channel.sum - channel.previous + self.high_pass * channel.filtered;
+                channel.previous = channel.sum;
+                channel.filtered = filtered;
+                *sample =
+                    (filtered * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+            }
+            write(frame, samples);
+        }
+        for channel in &mut self.channels {
+            channel.deltas.drain(..frames.min(channel.deltas.len()));
+        }
+        self.time -= frames as f64;
+    }
+}
@@ -0,0 +1,244 @@
+use crate::save_state::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+use super::units::{Envelope, LengthCounter};
+
+// Waveforms of the 12.5%, 25%, 50% and 75% duty cycles, played from the MSB
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+/// Frequency sweep of channel 1 set by NR10, clocked at 128 Hz
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Sweep {
+    pub period: u8,
+    pub negate: bool,
+    pub shift: u8,
+    timer: u8,
+    enabled: bool,
+    // Frequency the sweep works on, copied from the channel on trigger
+    shadow: u16,
+    // A subtraction happened since the trigger, clearing negate now stops the channel
+    negate_used: bool,
+}
+
+impl Sweep {
+    fn read(&self) -> u8 {
+        let mut value = 0b1000_0000 | self.period << 4 | self.shift;
+        if self.negate {
+            value |= 0b0000_1000;
+        }
+        value
+    }
+
+    fn write(&mut self, value: u8) {
+        self.period = (value >> 4) & 0b0000_0111;
+        self.negate = value & 0b0000_1000 != 0;
+        self.shift = value & 0b0000_0111;
+    }
+
+    fn reload_timer(&mut self) {
+        // A period of 0 is treated as 8
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    fn next_frequency(&mut self) -> u16 {
+        let delta = self.shadow >> self.shift;
+        if self.negate {
+            self.negate_used = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        }
+    }
+}
+
+/// Square channels 1 and 2, only channel 1 has a frequency sweep
+#[derive(Debug, Clone, Copy)]
+pub struct SquareChannel {
+    // Playing, reported in NR52
+    pub enabled: bool,
+    pub duty: u8,
+    duty_step: u8,
+    // 11 bits period value from NRx3 and NRx4, the tone is 131072 / (2048 - frequency) Hz
+    pub frequency: u16,
+    // Clocks until the next duty step
+    timer: u16,
+    pub length: LengthCounter,
+    pub envelope: Envelope,
+    pub sweep: Option<Sweep>,
+}
+
+impl SquareChannel {
+    pub fn new(sweep: bool) -> Self {
+        Self {
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::default(),
+            envelope: Envelope::default(),
+            sweep: sweep.then(Sweep::default),
+        }
+    }
+
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 4
+    }
+
+    /// Read NRx0-NRx4, write only bits read as 1
+    pub fn read_register(&self, register: u16) -> u8 {
+        match register {
+            0 => self.sweep.map_or(0xFF, |sweep| sweep.read()),
+            1 => self.duty << 6 | 0b0011_1111,
+            2 => self.envelope.read(),
+            3 => 0xFF,
+            _ => {
+                if self.length.enabled {
+                    0b1111_1111
+                } else {
+                    0b1011_1111
+                }
+            }
+        }
+    }
+
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                if let Some(sweep) = &mut self.sweep {
+                    sweep.write(value);
+                    if sweep.negate_used && !sweep.negate {
+                        self.enabled = false;
+                    }
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(64, (value & 0b0011_1111) as u16);
+            }
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x0700) | value as u16,
+            _ => {
+                self.frequency = (self.frequency & 0x00FF) | ((value & 0b0000_0111) as u16) << 8;
+                self.length.enabled = value & 0b0100_0000 != 0;
+                if value & 0b1000_0000 != 0 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger(64);
+        self.timer = self.period();
+        self.envelope.trigger();
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow = self.frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+            sweep.negate_used = false;
+            // The overflow check runs right away when there is a shift
+            if sweep.shift != 0 && sweep.next_frequency() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Advance the duty cycle by `clocks` clocks of the 4 MiHz APU clock
+    pub fn tick(&mut self, clocks: u8) {
+        let mut clocks = clocks as u16;
+        while clocks >= self.timer {
+            clocks -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+        self.timer -= clocks;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        let Some(sweep) = &mut self.sweep else {
+            return;
+        };
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer > 0 {
+            return;
+        }
+        sweep.reload_timer();
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+
+        let frequency = sweep.next_frequency();
+        if frequency > 2047 {
+            self.enabled = false;
+        } else if sweep.shift != 0 {
+            sweep.shadow = frequency;
+            self.frequency = frequency;
+            // The new frequency is checked for overflow again, without being applied
+            if sweep.next_frequency() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Digital output, 0 to 15
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        let high = DUTY_PATTERNS[self.duty as usize] >> (7 - self.duty_step) & 1;
+        high * self.envelope.volume
+    }
+}
+
+impl Snapshot for SquareChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.duty);
+        writer.write_u8(self.duty_step);
+        writer.write_u16(self.frequency);
+        writer.write_u16(self.timer);
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+        if let Some(sweep) = &self.sweep {
+            writer.write_u8(sweep.read());
+            writer.write_u8(sweep.timer);
+            writer.write_bool(sweep.enabled);
+            writer.write_u16(sweep.shadow);
+            writer.write_bool(sweep.negate_used);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enabled = reader.read_bool()?;
+        self.duty = reader.read_u8()? & 0b11;
+        self.duty_step = reader.read_u8()? % 8;
+        self.frequency = reader.read_u16()? & 0x07FF;
+        self.timer = reader.read_u16()?;
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        if let Some(sweep) = &mut self.sweep {
+            sweep.write(reader.read_u8()?);
+            sweep.timer = reader.read_u8()?;
+            sweep.enabled = reader.read_bool()?;
+            sweep.shadow = reader.read_u16()?;
+            sweep.negate_used = reader.read_bool()?;
+        }
+        Ok(())
+    }
+}
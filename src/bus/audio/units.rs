@@ -0,0 +1,114 @@
+use crate::save_state::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+/// Stops the channel once its length runs out, clocked at 256 Hz
+#[derive(Default, Debug, Clone, Copy)]
+pub struct LengthCounter {
+    pub enabled: bool,
+    // Counts down from 64, or 256 for the wave channel
+    pub counter: u16,
+}
+
+impl LengthCounter {
+    /// Load the NRx1 length timer, the channel plays for `max - length` ticks
+    pub fn load(&mut self, max: u16, length: u16) {
+        self.counter = max - length;
+    }
+
+    /// A channel triggered with an expired length plays for the whole length
+    pub fn trigger(&mut self, max: u16) {
+        if self.counter == 0 {
+            self.counter = max;
+        }
+    }
+
+    /// Returns true when the length expires and the channel must stop
+    pub fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+        self.counter -= 1;
+        self.counter == 0
+    }
+}
+
+impl Snapshot for LengthCounter {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u16(self.counter);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enabled = reader.read_bool()?;
+        self.counter = reader.read_u16()?;
+        Ok(())
+    }
+}
+
+/// Volume envelope set by NRx2, clocked at 64 Hz
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Envelope {
+    pub initial_volume: u8,
+    pub increase: bool,
+    // Envelope ticks between volume changes, 0 keeps the volume constant
+    pub period: u8,
+    pub volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn read(&self) -> u8 {
+        let mut value = self.initial_volume << 4 | self.period;
+        if self.increase {
+            value |= 0b0000_1000;
+        }
+        value
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.increase = value & 0b0000_1000 != 0;
+        self.period = value & 0b0000_0111;
+    }
+
+    /// The DAC is off when the upper 5 bits of NRx2 are cleared, which also stops the
+    /// channel
+    pub fn dac_enabled(&self) -> bool {
+        self.read() & 0b1111_1000 != 0
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    pub fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.period;
+        if self.increase && self.volume < 15 {
+            self.volume += 1;
+        } else if !self.increase && self.volume > 0 {
+            self.volume -= 1;
+        }
+    }
+}
+
+impl Snapshot for Envelope {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.read());
+        writer.write_u8(self.volume);
+        writer.write_u8(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.write(reader.read_u8()?);
+        self.volume = reader.read_u8()? & 0x0F;
+        self.timer = reader.read_u8()? & 0b0000_0111;
+        Ok(())
+    }
+}
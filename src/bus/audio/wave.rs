@@ -0,0 +1,166 @@
+use crate::save_state::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+use super::units::LengthCounter;
+
+/// Channel 3, plays the 32 4 bits samples of wave RAM
+#[derive(Default, Debug, Clone, Copy)]
+pub struct WaveChannel {
+    pub enabled: bool,
+    // NR30 bit 7
+    pub dac_enabled: bool,
+    // NR32 output level, 0 mutes, then 100%, 50% and 25%
+    pub output_level: u8,
+    pub frequency: u16,
+    timer: u16,
+    // Sample being played, 0 to 31
+    position: u8,
+    sample: u8,
+    // Wave RAM was read during the last M-cycle, only then does the DMG let the CPU
+    // access it while playing
+    sample_read: bool,
+    pub length: LengthCounter,
+    pub ram: [u8; 0x10],
+}
+
+impl WaveChannel {
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 2
+    }
+
+    /// Read NR30-NR34, write only bits read as 1
+    pub fn read_register(&self, register: u16) -> u8 {
+        match register {
+            0 => {
+                if self.dac_enabled {
+                    0b1111_1111
+                } else {
+                    0b0111_1111
+                }
+            }
+            1 => 0xFF,
+            2 => self.output_level << 5 | 0b1001_1111,
+            3 => 0xFF,
+            _ => {
+                if self.length.enabled {
+                    0b1111_1111
+                } else {
+                    0b1011_1111
+                }
+            }
+        }
+    }
+
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0b1000_0000 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(256, value as u16),
+            2 => self.output_level = (value >> 5) & 0b11,
+            3 => self.frequency = (self.frequency & 0x0700) | value as u16,
+            _ => {
+                self.frequency = (self.frequency & 0x00FF) | ((value & 0b0000_0111) as u16) << 8;
+                self.length.enabled = value & 0b0100_0000 != 0;
+                if value & 0b1000_0000 != 0 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger(256);
+        // The sample buffer isn't refilled, the first sample read is the second one
+        self.position = 0;
+        self.timer = self.period();
+    }
+
+    /// Wave RAM seen by the CPU. While the channel plays the CPU accesses the byte being
+    /// played instead, on the DMG only in the M-cycle the channel read it
+    pub fn read_ram(&self, address: u16, dmg: bool) -> u8 {
+        if !self.enabled {
+            return self.ram[(address - 0xFF30) as usize];
+        }
+        if dmg && !self.sample_read {
+            return 0xFF;
+        }
+        self.ram[self.position as usize / 2]
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8, dmg: bool) {
+        if !self.enabled {
+            self.ram[(address - 0xFF30) as usize] = value;
+        } else if !dmg || self.sample_read {
+            self.ram[self.position as usize / 2] = value;
+        }
+    }
+
+    /// Advance by `clocks` clocks of the 4 MiHz APU clock
+    pub fn tick(&mut self, clocks: u8) {
+        self.sample_read = false;
+        if !self.enabled {
+            return;
+        }
+        let mut clocks = clocks as u16;
+        while clocks >= self.timer {
+            clocks -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+            let byte = self.ram[self.position as usize / 2];
+            // High nibble first
+            self.sample = if self.position & 1 == 0 {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+            self.sample_read = true;
+        }
+        self.timer -= clocks;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Digital output, 0 to 15
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.output_level == 0 {
+            return 0;
+        }
+        self.sample >> (self.output_level - 1)
+    }
+}
+
+impl Snapshot for WaveChannel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_bool(self.dac_enabled);
+        writer.write_u8(self.output_level);
+        writer.write_u16(self.frequency);
+        writer.write_u16(self.timer);
+        writer.write_u8(self.position);
+        writer.write_u8(self.sample);
+        writer.write_bool(self.sample_read);
+        self.length.save_state(writer);
+        writer.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enabled = reader.read_bool()?;
+        self.dac_enabled = reader.read_bool()?;
+        self.output_level = reader.read_u8()? & 0b11;
+        self.frequency = reader.read_u16()? & 0x07FF;
+        self.timer = reader.read_u16()?;
+        self.position = reader.read_u8()? % 32;
+        self.sample = reader.read_u8()? & 0x0F;
+        self.sample_read = reader.read_bool()?;
+        self.length.load_state(reader)?;
+        reader.read_into(&mut self.ram)
+    }
+}
@@ -0,0 +1,361 @@
+#[cfg(test)]
+mod bus_tests {
+    use crate::bus::{Bus, HardwareMode, Memory, MemoryLockOwner, MemoryRegion};
+
+    fn start_dma(bus: &mut Bus, source_upper_byte: u8) {
+        bus.write_byte(0xFF46, source_upper_byte);
+    }
+
+    #[test]
+    fn oam_dma_copies_one_byte_per_m_cycle() {
+        let mut bus = Bus::default();
+        for i in 0..160 {
+            bus.write_byte(0xC100 + i, i as u8 ^ 0x5A);
+        }
+        start_dma(&mut bus, 0xC1);
+
+        for _ in 0..159 {
+            bus.dma_transfer_step();
+        }
+        assert!(bus.oam.dma_active());
+        assert_eq!(bus.oam.read_byte(0xFE00), 0x5A);
+        assert_eq!(bus.oam.read_byte(0xFE9E), 158 ^ 0x5A);
+        assert_eq!(bus.oam.read_byte(0xFE9F), 0);
+
+        bus.dma_transfer_step();
+        assert!(!bus.oam.dma_active());
+        for i in 0..160 {
+            assert_eq!(bus.read_byte(0xFE00 + i), i as u8 ^ 0x5A);
+        }
+    }
+
+    #[test]
+    fn oam_dma_leaves_only_hram_to_the_cpu() {
+        let mut bus = Bus::default();
+        bus.write_byte(0xC000, 0x12);
+        start_dma(&mut bus, 0xC0);
+        bus.dma_transfer_step();
+
+        assert_eq!(bus.read_byte(0xC000), 0xFF);
+        assert_eq!(bus.read_byte(0xFE00), 0xFF);
+        bus.write_byte(0xC000, 0x34);
+        bus.write_byte(0xFF80, 0x56);
+        assert_eq!(bus.read_byte(0xFF80), 0x56);
+        assert_eq!(bus.read_byte(0xFF46), 0xC0);
+
+        for _ in 1..160 {
+            bus.dma_transfer_step();
+        }
+        assert_eq!(bus.read_byte(0xC000), 0x12);
+        assert_eq!(bus.read_byte(0xFE00), 0x12);
+    }
+
+    #[test]
+    fn oam_dma_restarts_on_write() {
+        let mut bus = Bus::default();
+        bus.write_byte(0xC000, 0x11);
+        bus.write_byte(0xC100, 0x22);
+        start_dma(&mut bus, 0xC0);
+        for _ in 0..80 {
+            bus.dma_transfer_step();
+        }
+        start_dma(&mut bus, 0xC1);
+        for _ in 0..159 {
+            bus.dma_transfer_step();
+        }
+        assert!(bus.oam.dma_active());
+        bus.dma_transfer_step();
+        assert!(!bus.oam.dma_active());
+        assert_eq!(bus.read_byte(0xFE00), 0x22);
+    }
+
+    #[test]
+    fn oam_dma_sources_above_wram_read_wram() {
+        let mut bus = Bus::default();
+        bus.write_byte(0xC000, 0x42);
+        bus.write_byte(0xDE00, 0x24);
+
+        start_dma(&mut bus, 0xE0);
+        for _ in 0..160 {
+            bus.dma_transfer_step();
+        }
+        assert_eq!(bus.read_byte(0xFE00), 0x42);
+
+        // FE00 would be OAM itself, hardware reads DE00 instead
+        start_dma(&mut bus, 0xFE);
+        for _ in 0..160 {
+            bus.dma_transfer_step();
+        }
+        assert_eq!(bus.read_byte(0xFE00), 0x24);
+    }
+
+    fn cgb_bus() -> Bus {
+        Bus {
+            mode: HardwareMode::CGB,
+            ..Bus::default()
+        }
+    }
+
+    #[test]
+    fn cgb_vram_bank_select() {
+        let mut bus = cgb_bus();
+        bus.write_byte(0x8000, 0x11);
+        bus.write_byte(0xFF4F, 0x01);
+        assert_eq!(bus.read_byte(0xFF4F), 0xFF);
+        assert_eq!(bus.read_byte(0x8000), 0x00);
+        bus.write_byte(0x8000, 0x22);
+
+        bus.write_byte(0xFF4F, 0x00);
+        assert_eq!(bus.read_byte(0xFF4F), 0xFE);
+        assert_eq!(bus.read_byte(0x8000), 0x11);
+        assert_eq!(bus.vram.1[0], 0x22);
+    }
+
+    #[test]
+    fn cgb_wram_bank_select() {
+        let mut bus = cgb_bus();
+        for bank in 1..8 {
+            bus.write_byte(0xFF70, bank);
+            bus.write_byte(0xD000, bank * 0x10);
+        }
+        // Bank 0 selects bank 1
+        bus.write_byte(0xFF70, 0x00);
+        assert_eq!(bus.read_byte(0xFF70), 0xF8);
+        assert_eq!(bus.read_byte(0xD000), 0x10);
+        bus.write_byte(0xFF70, 0x05);
+        assert_eq!(bus.read_byte(0xD000), 0x50);
+        assert_eq!(bus.read_byte(0xF000), 0x50);
+        bus.write_byte(0xC000, 0x99);
+        bus.write_byte(0xFF70, 0x07);
+        assert_eq!(bus.read_byte(0xC000), 0x99);
+        assert_eq!(bus.read_byte(0xD000), 0x70);
+    }
+
+    #[test]
+    fn dmg_ignores_cgb_registers() {
+        let mut bus = Bus::default();
+        bus.write_byte(0xFF4F, 0x01);
+        bus.write_byte(0xFF70, 0x03);
+        bus.write_byte(0xFF4D, 0x01);
+        bus.write_byte(0x8000, 0x11);
+        bus.write_byte(0xD000, 0x22);
+
+        assert_eq!(bus.read_byte(0xFF4F), 0xFF);
+        assert_eq!(bus.read_byte(0xFF70), 0xFF);
+        assert_eq!(bus.read_byte(0xFF4D), 0xFF);
+        assert_eq!(bus.vram.0[0], 0x11);
+        assert_eq!(bus.external_wram[0][0], 0x22);
+        assert!(!bus.switch_speed());
+    }
+
+    #[test]
+    fn key1_arms_the_speed_switch() {
+        let mut bus = cgb_bus();
+        assert_eq!(bus.read_byte(0xFF4D), 0x7E);
+        assert!(!bus.switch_speed());
+
+        bus.write_byte(0xFF4D, 0x01);
+        assert_eq!(bus.read_byte(0xFF4D), 0x7F);
+        assert!(bus.switch_speed());
+        assert_eq!(bus.read_byte(0xFF4D), 0xFE);
+        assert_eq!(bus.dots_per_cycle(), 2);
+
+        bus.write_byte(0xFF4D, 0x01);
+        assert!(bus.switch_speed());
+        assert_eq!(bus.read_byte(0xFF4D), 0x7E);
+        assert_eq!(bus.dots_per_cycle(), 4);
+    }
+
+    #[test]
+    fn cgb_boot_rom_leaves_the_header_visible() {
+        let mut bus = cgb_bus();
+        let mut boot_rom = vec![0xAA; 0x900];
+        boot_rom[0x200] = 0xBB;
+        bus.load_boot_rom(&boot_rom);
+        bus.rom[0x0100] = 0x00;
+        bus.rom[0x0134] = 0x42;
+        bus.rom[0x0900] = 0xCC;
+
+        assert_eq!(bus.read_byte(0x0000), 0xAA);
+        assert_eq!(bus.read_byte(0x0100), 0x00);
+        assert_eq!(bus.read_byte(0x0134), 0x42);
+        assert_eq!(bus.read_byte(0x0200), 0xBB);
+        assert_eq!(bus.read_byte(0x0900), 0xCC);
+
+        bus.write_byte(0xFF50, 0x11);
+        assert_eq!(bus.read_byte(0x0000), 0x00);
+        assert_eq!(bus.read_byte(0x0200), 0x00);
+    }
+
+    #[test]
+    fn cgb_palette_registers_auto_increment() {
+        let mut bus = cgb_bus();
+        bus.write_byte(0xFF68, 0x80 | 0x3E);
+        assert_eq!(bus.read_byte(0xFF68), 0xFE);
+        bus.write_byte(0xFF69, 0x12);
+        bus.write_byte(0xFF69, 0x34);
+        // Wrapped around to the first byte
+        bus.write_byte(0xFF69, 0x56);
+        assert_eq!(bus.read_byte(0xFF68), 0xC1);
+        assert_eq!(bus.io.cgb_palettes.background.color(7, 3), 0x3412);
+        assert_eq!(bus.io.cgb_palettes.background.data[0], 0x56);
+
+        // Without auto-increment the index stays put
+        bus.write_byte(0xFF6A, 0x08);
+        bus.write_byte(0xFF6B, 0x1F);
+        bus.write_byte(0xFF6B, 0x00);
+        assert_eq!(bus.read_byte(0xFF6A), 0x48);
+        assert_eq!(bus.read_byte(0xFF6B), 0x00);
+        assert_eq!(bus.io.cgb_palettes.object.color(1, 0), 0x7F00);
+    }
+
+    #[test]
+    fn cgb_palette_data_locked_while_drawing() {
+        let mut bus = cgb_bus();
+        bus.current_owner = MemoryLockOwner::PPU;
+        bus.lock(MemoryRegion::CGBPalette);
+        bus.current_owner = MemoryLockOwner::CPU;
+
+        bus.write_byte(0xFF68, 0x00);
+        bus.write_byte(0xFF69, 0x00);
+        assert_eq!(bus.read_byte(0xFF69), 0xFF);
+        assert_eq!(bus.read_byte(0xFF68), 0x40);
+        bus.unlock(MemoryRegion::CGBPalette);
+        assert_eq!(bus.read_byte(0xFF69), 0xFF);
+    }
+
+    fn set_hdma(bus: &mut Bus, source: u16, destination: u16) {
+        bus.write_byte(0xFF51, (source >> 8) as u8);
+        bus.write_byte(0xFF52, source as u8);
+        bus.write_byte(0xFF53, (destination >> 8) as u8);
+        bus.write_byte(0xFF54, destination as u8);
+    }
+
+    #[test]
+    fn general_purpose_hdma_copies_everything_at_once() {
+        let mut bus = cgb_bus();
+        for i in 0..0x30 {
+            bus.write_byte(0xC000 + i, i as u8 + 1);
+        }
+        bus.write_byte(0xFF4F, 1);
+        // The low 4 bits of the addresses are ignored
+        set_hdma(&mut bus, 0xC00F, 0x9F0F);
+        bus.write_byte(0xFF55, 0x02);
+        assert_eq!(bus.read_byte(0xFF55), 0x02);
+
+        assert_eq!(bus.hdma_step(), 3 * 8);
+        assert_eq!(bus.read_byte(0xFF55), 0xFF);
+        assert_eq!(bus.vram.1[0x1F00], 0x01);
+        assert_eq!(bus.vram.1[0x1F2F], 0x30);
+        assert_eq!(bus.vram.0[0x1F00], 0x00);
+        assert_eq!(bus.hdma_step(), 0);
+    }
+
+    #[test]
+    fn hblank_hdma_copies_a_block_per_hblank() {
+        let mut bus = cgb_bus();
+        for i in 0..0x30 {
+            bus.write_byte(0xC000 + i, i as u8 + 1);
+        }
+        bus.io.lcd.status.stat.ppu_mode = 2;
+        set_hdma(&mut bus, 0xC000, 0x8000);
+        bus.write_byte(0xFF55, 0x82);
+        assert_eq!(bus.hdma_step(), 0);
+        assert_eq!(bus.read_byte(0xFF55), 0x02);
+
+        bus.io.hdma.hblank_started();
+        assert_eq!(bus.hdma_step(), 8);
+        assert_eq!(bus.hdma_step(), 0);
+        assert_eq!(bus.read_byte(0xFF55), 0x01);
+        assert_eq!(bus.vram.0[0x0F], 0x10);
+        assert_eq!(bus.vram.0[0x10], 0x00);
+
+        // Cancelled, the remaining length can still be read
+        bus.write_byte(0xFF55, 0x00);
+        assert_eq!(bus.read_byte(0xFF55), 0x81);
+        bus.io.hdma.hblank_started();
+        assert_eq!(bus.hdma_step(), 0);
+        assert_eq!(bus.vram.0[0x10], 0x00);
+
+        // Restarted where it stopped
+        bus.write_byte(0xFF55, 0x81);
+        for _ in 0..2 {
+            bus.io.hdma.hblank_started();
+            bus.hdma_step();
+        }
+        assert_eq!(bus.read_byte(0xFF55), 0xFF);
+        assert_eq!(bus.vram.0[0x10], 0x11);
+        assert_eq!(bus.vram.0[0x2F], 0x30);
+    }
+
+    #[test]
+    fn hblank_hdma_started_in_hblank_copies_a_block_at_once() {
+        let mut bus = cgb_bus();
+        for i in 0..0x30 {
+            bus.write_byte(0xC000 + i, i as u8 + 1);
+        }
+        set_hdma(&mut bus, 0xC000, 0x8000);
+        bus.write_byte(0xFF55, 0x82);
+        assert_eq!(bus.hdma_step(), 8);
+        assert_eq!(bus.hdma_step(), 0);
+        assert_eq!(bus.vram.0[0x0F], 0x10);
+
+        // Same with the LCD off, further blocks wait for the next HBlank
+        bus.io.lcd.status.stat.ppu_mode = 2;
+        bus.io.lcd.control.lcd_enable = false;
+        set_hdma(&mut bus, 0xC010, 0x8100);
+        bus.write_byte(0xFF55, 0x81);
+        assert_eq!(bus.hdma_step(), 8);
+        assert_eq!(bus.hdma_step(), 0);
+        assert_eq!(bus.read_byte(0xFF55), 0x00);
+        assert_eq!(bus.vram.0[0x10F], 0x20);
+        assert_eq!(bus.vram.0[0x110], 0x00);
+    }
+
+    #[test]
+    fn hdma_takes_longer_in_double_speed() {
+        let mut bus = cgb_bus();
+        bus.io.speed_switch.double_speed = true;
+        set_hdma(&mut bus, 0xC000, 0x8000);
+        bus.write_byte(0xFF55, 0x00);
+        assert_eq!(bus.hdma_step(), 16);
+    }
+
+    #[test]
+    fn dmg_has_no_hdma() {
+        let mut bus = Bus::default();
+        bus.write_byte(0xC000, 0x42);
+        set_hdma(&mut bus, 0xC000, 0x8000);
+        bus.write_byte(0xFF55, 0x00);
+        assert_eq!(bus.read_byte(0xFF55), 0xFF);
+        assert_eq!(bus.hdma_step(), 0);
+        assert_eq!(bus.vram.0[0], 0x00);
+    }
+
+    #[test]
+    fn timer_counts_m_cycles() {
+        let mut bus = Bus::default();
+        // Timer on, TIMA increments every 4 M-cycles
+        bus.write_byte(0xFF06, 0xF0);
+        bus.write_byte(0xFF07, 0b0000_0101);
+        bus.write_byte(0xFF05, 0xFE);
+        for _ in 0..8 {
+            bus.tick();
+        }
+        assert_eq!(bus.read_byte(0xFF05), 0xF0);
+        assert!(bus.interupt_flags.timer);
+
+        // DIV increments every 64 M-cycles, writing it resets the whole counter
+        for _ in 0..56 {
+            bus.tick();
+        }
+        assert_eq!(bus.read_byte(0xFF04), 1);
+        for _ in 0..2 {
+            bus.tick();
+        }
+        bus.write_byte(0xFF04, 0x12);
+        assert_eq!(bus.read_byte(0xFF04), 0);
+        // Resetting the counter with the selected bit set is a falling edge
+        assert_eq!(bus.read_byte(0xFF05), 0xF0 + 14 + 1);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::save_state::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+use super::Memory;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum HdmaMode {
+    #[default]
+    Idle,
+    // Whole transfer at once, the CPU is stalled until it's done
+    General,
+    // 0x10 bytes at the start of each HBlank
+    HBlank,
+}
+
+/// CGB VRAM DMA, HDMA1-HDMA5 (0xFF51-0xFF55)
+#[derive(Debug, Clone, Copy)]
+pub struct Hdma {
+    pub source: u16,
+    // Offset in VRAM
+    pub destination: u16,
+    // Blocks of 0x10 bytes left minus one, as read back from HDMA5
+    pub length: u8,
+    pub mode: HdmaMode,
+    // An HBlank started since the last block was copied
+    hblank: bool,
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Self {
+            source: 0,
+            destination: 0,
+            length: 0x7F,
+            mode: HdmaMode::Idle,
+            hblank: false,
+        }
+    }
+}
+
+impl Hdma {
+    /// Called by the PPU when it enters mode 0 on a visible line
+    pub fn hblank_started(&mut self) {
+        if self.mode == HdmaMode::HBlank {
+            self.hblank = true;
+        }
+    }
+
+    /// Blocks of 0x10 bytes to copy now, everything left for a general purpose transfer
+    pub fn pending_blocks(&mut self) -> u8 {
+        match self.mode {
+            HdmaMode::Idle => 0,
+            HdmaMode::General => self.length + 1,
+            HdmaMode::HBlank if self.hblank => {
+                self.hblank = false;
+                1
+            }
+            HdmaMode::HBlank => 0,
+        }
+    }
+
+    /// Move on to the next block once one has been copied
+    pub fn block_done(&mut self) {
+        self.source = self.source.wrapping_add(0x10);
+        self.destination = (self.destination + 0x10) & 0x1FF0;
+        self.length = self.length.wrapping_sub(1) & 0x7F;
+        if self.length == 0x7F {
+            self.mode = HdmaMode::Idle;
+        }
+    }
+}
+
+impl Memory for Hdma {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            // Source and destination are write only
+            0xFF51..=0xFF54 => 0xFF,
+            // Bit 7 is cleared while a transfer is running
+            0xFF55 => match self.mode {
+                HdmaMode::Idle => 0x80 | self.length,
+                HdmaMode::General | HdmaMode::HBlank => self.length,
+            },
+            _ => panic!("Invalid read from Hdma address: {:04X}", address),
+        }
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF51 => self.source = (value as u16) << 8 | (self.source & 0x00F0),
+            0xFF52 => self.source = (self.source & 0xFF00) | (value & 0xF0) as u16,
+            0xFF53 => self.destination = ((value & 0x1F) as u16) << 8 | (self.destination & 0x00F0),
+            0xFF54 => self.destination = (self.destination & 0x1F00) | (value & 0xF0) as u16,
+            0xFF55 => {
+                if self.mode == HdmaMode::HBlank && value & 0x80 == 0 {
+                    // Cancel the HBlank transfer, the remaining length stays readable
+                    self.mode = HdmaMode::Idle;
+                    return;
+                }
+                self.length = value & 0x7F;
+                self.hblank = false;
+                self.mode = if value & 0x80 != 0 {
+                    HdmaMode::HBlank
+                } else {
+                    HdmaMode::General
+                };
+            }
+            _ => panic!("Invalid write to Hdma address: {:04X}", address),
+        }
+    }
+}
+
+impl Snapshot for Hdma {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.source);
+        writer.write_u16(self.destination);
+        writer.write_u8(self.length);
+        writer.write_u8(self.mode as u8);
+        writer.write_bool(self.hblank);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.source = reader.read_u16()?;
+        self.destination = reader.read_u16()? & 0x1FF0;
+        self.length = reader.read_u8()? & 0x7F;
+        self.mode = match reader.read_u8()? {
+            0 => HdmaMode::Idle,
+            1 => HdmaMode::General,
+            2 => HdmaMode::HBlank,
+            _ => return Err(SaveStateError::InvalidValue("HDMA mode")),
+        };
+        self.hblank = reader.read_bool()?;
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use std::default;
+use crate::save_state::{SaveStateError, Snapshot, StateReader, StateWriter};
 
 #[derive(Default, Debug, Clone, Copy)]
 pub enum JoypadRead {
@@ -26,11 +26,57 @@ pub struct JoypadButtons {
     pub down: bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Right,
+    Left,
+    Up,
+    Down,
+}
+
+impl std::str::FromStr for Button {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "a" => Ok(Button::A),
+            "b" => Ok(Button::B),
+            "select" => Ok(Button::Select),
+            "start" => Ok(Button::Start),
+            "right" => Ok(Button::Right),
+            "left" => Ok(Button::Left),
+            "up" => Ok(Button::Up),
+            "down" => Ok(Button::Down),
+            _ => Err(format!("unknown button {}", value)),
+        }
+    }
+}
+
+impl JoypadButtons {
+    pub fn get_mut(&mut self, button: Button) -> &mut bool {
+        match button {
+            Button::A => &mut self.a,
+            Button::B => &mut self.b,
+            Button::Select => &mut self.select,
+            Button::Start => &mut self.start,
+            Button::Right => &mut self.right,
+            Button::Left => &mut self.left,
+            Button::Up => &mut self.up,
+            Button::Down => &mut self.down,
+        }
+    }
+}
+
 impl JoypadRegister {
+    /// Bits 4 and 5 select the directions and the buttons respectively, both active low
     pub fn write_byte(&mut self, value: u8) {
         match value & 0b0011_0000 {
-            0b0010_0000 => self.read = JoypadRead::Buttons,
-            0b0001_0000 => self.read = JoypadRead::Directions,
+            0b0001_0000 => self.read = JoypadRead::Buttons,
+            0b0010_0000 => self.read = JoypadRead::Directions,
             _ => self.read = JoypadRead::None,
         }
     }
@@ -38,40 +84,84 @@ impl JoypadRegister {
 
 impl std::convert::From<JoypadRegister> for u8 {
     fn from(value: JoypadRegister) -> Self {
+        // Pressed buttons read as 0, unused bits read as 1
         match value.read {
-            JoypadRead::None => 0b0011_1111,
+            JoypadRead::None => 0b1111_1111,
             JoypadRead::Buttons => {
-                let mut result = 0b0010_0000;
+                let mut result = 0b1101_1111;
                 if value.buttons.a {
-                    result |= 0b0000_0001;
+                    result &= !0b0000_0001;
                 }
                 if value.buttons.b {
-                    result |= 0b0000_0010;
+                    result &= !0b0000_0010;
                 }
                 if value.buttons.select {
-                    result |= 0b0000_0100;
+                    result &= !0b0000_0100;
                 }
                 if value.buttons.start {
-                    result |= 0b0000_1000;
+                    result &= !0b0000_1000;
                 }
                 result
             }
             JoypadRead::Directions => {
-                let mut result = 0b0001_0000;
+                let mut result = 0b1110_1111;
                 if value.buttons.right {
-                    result |= 0b0000_0001;
+                    result &= !0b0000_0001;
                 }
                 if value.buttons.left {
-                    result |= 0b0000_0010;
+                    result &= !0b0000_0010;
                 }
                 if value.buttons.up {
-                    result |= 0b0000_0100;
+                    result &= !0b0000_0100;
                 }
                 if value.buttons.down {
-                    result |= 0b0000_1000;
+                    result &= !0b0000_1000;
                 }
                 result
             }
         }
     }
 }
+
+impl Snapshot for JoypadRegister {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(match self.read {
+            JoypadRead::Buttons => 0,
+            JoypadRead::Directions => 1,
+            JoypadRead::None => 2,
+        });
+        for pressed in [
+            self.buttons.a,
+            self.buttons.b,
+            self.buttons.select,
+            self.buttons.start,
+            self.buttons.right,
+            self.buttons.left,
+            self.buttons.up,
+            self.buttons.down,
+        ] {
+            writer.write_bool(pressed);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.read = match reader.read_u8()? {
+            0 => JoypadRead::Buttons,
+            1 => JoypadRead::Directions,
+            _ => JoypadRead::None,
+        };
+        for pressed in [
+            &mut self.buttons.a,
+            &mut self.buttons.b,
+            &mut self.buttons.select,
+            &mut self.buttons.start,
+            &mut self.buttons.right,
+            &mut self.buttons.left,
+            &mut self.buttons.up,
+            &mut self.buttons.down,
+        ] {
+            *pressed = reader.read_bool()?;
+        }
+        Ok(())
+    }
+}
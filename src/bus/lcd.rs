@@ -1,4 +1,6 @@
-use super::{InteruptFlags, Memory};
+use crate::save_state::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+use super::Memory;
 
 #[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub enum WindowTileMapArea {
@@ -136,7 +138,8 @@ pub struct StatRegister {
 
 impl std::convert::From<StatRegister> for u8 {
     fn from(value: StatRegister) -> Self {
-        let mut result = 0;
+        // Bit 7 is unused and always reads as 1
+        let mut result = 0b1000_0000;
         if value.lyc_interupt {
             result |= 0b0100_0000;
         }
@@ -188,8 +191,17 @@ impl Memory for LCDStatus {
     }
     fn write_byte(&mut self, address: u16, value: u8) {
         match address {
-            0xFF41 => self.stat = value.into(),
-            0xFF44 => self.ly = value,
+            // Only the interrupt sources are writable, the mode and LYC=LY bits come from the PPU
+            0xFF41 => {
+                let written = StatRegister::from(value);
+                self.stat = StatRegister {
+                    lyc_ly: self.stat.lyc_ly,
+                    ppu_mode: self.stat.ppu_mode,
+                    ..written
+                };
+            }
+            // LY is read-only
+            0xFF44 => (),
             0xFF45 => self.lyc = value,
             _ => panic!("Invalid write to LCDStatus address: {:04X}", address),
         }
@@ -197,12 +209,13 @@ impl Memory for LCDStatus {
 }
 
 impl LCDStatus {
-    pub fn tick(&self, interupt_flags: &mut InteruptFlags) {
-        if self.stat.lyc_interupt {
-            if self.ly == self.lyc {
-                interupt_flags.lcd_stat = true;
-            }
-        }
+    /// State of the STAT interrupt line, all the enabled sources ORed together
+    pub fn interrupt_line(&self) -> bool {
+        let stat = &self.stat;
+        (stat.lyc_interupt && stat.lyc_ly)
+            || (stat.mode_0_hblank_interrupt && stat.ppu_mode == 0)
+            || (stat.mode_1_vblank_interrupt && stat.ppu_mode == 1)
+            || (stat.mode_2_oam_interrupt && stat.ppu_mode == 2)
     }
 }
 
@@ -257,6 +270,7 @@ impl std::convert::From<u8> for Color {
     }
 }
 
+/// Monochrome palette, `color0` is the shade of color index 0 in bits 1-0
 #[derive(Default, Debug, Clone, Copy)]
 pub struct ColorPalette {
     pub color3: Color,
@@ -265,13 +279,25 @@ pub struct ColorPalette {
     pub color0: Color,
 }
 
+impl ColorPalette {
+    /// Shade of the 2 bits color index `index`
+    pub fn color(&self, index: u8) -> Color {
+        match index & 0b11 {
+            0 => self.color0,
+            1 => self.color1,
+            2 => self.color2,
+            _ => self.color3,
+        }
+    }
+}
+
 impl std::convert::From<ColorPalette> for u8 {
     fn from(value: ColorPalette) -> Self {
         let mut result = 0;
-        result |= value.color3 as u8;
-        result |= (value.color2 as u8) << 2;
-        result |= (value.color1 as u8) << 4;
-        result |= (value.color0 as u8) << 6;
+        result |= value.color0 as u8;
+        result |= (value.color1 as u8) << 2;
+        result |= (value.color2 as u8) << 4;
+        result |= (value.color3 as u8) << 6;
         result
     }
 }
@@ -279,10 +305,10 @@ impl std::convert::From<ColorPalette> for u8 {
 impl std::convert::From<u8> for ColorPalette {
     fn from(value: u8) -> Self {
         ColorPalette {
-            color3: (value & 0b0000_0011).into(),
-            color2: ((value & 0b0000_1100) >> 2).into(),
-            color1: ((value & 0b0011_0000) >> 4).into(),
-            color0: ((value & 0b1100_0000) >> 6).into(),
+            color0: (value & 0b0000_0011).into(),
+            color1: ((value & 0b0000_1100) >> 2).into(),
+            color2: ((value & 0b0011_0000) >> 4).into(),
+            color3: ((value & 0b1100_0000) >> 6).into(),
         }
     }
 }
@@ -347,7 +373,7 @@ impl Memory for LCDRegisters {
             0xFF41 => self.status.write_byte(address, value),
             0xFF42 => self.pos_scroll.scy = value,
             0xFF43 => self.pos_scroll.scx = value,
-            0xFF44 => self.status.ly = value,
+            0xFF44 => self.status.write_byte(address, value),
             0xFF45 => self.status.lyc = value,
             0xFF47 => self.palettes.bgp = value.into(),
             0xFF48 => self.palettes.obp0 = value.into(),
@@ -358,3 +384,34 @@ impl Memory for LCDRegisters {
         }
     }
 }
+
+impl Snapshot for LCDRegisters {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.control.into());
+        writer.write_u8(self.status.stat.into());
+        writer.write_u8(self.status.ly);
+        writer.write_u8(self.status.lyc);
+        writer.write_u8(self.pos_scroll.scy);
+        writer.write_u8(self.pos_scroll.scx);
+        writer.write_u8(self.pos_scroll.wy);
+        writer.write_u8(self.pos_scroll.wx);
+        writer.write_u8(self.palettes.bgp.into());
+        writer.write_u8(self.palettes.obp0.into());
+        writer.write_u8(self.palettes.obp1.into());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.control = reader.read_u8()?.into();
+        self.status.stat = reader.read_u8()?.into();
+        self.status.ly = reader.read_u8()?;
+        self.status.lyc = reader.read_u8()?;
+        self.pos_scroll.scy = reader.read_u8()?;
+        self.pos_scroll.scx = reader.read_u8()?;
+        self.pos_scroll.wy = reader.read_u8()?;
+        self.pos_scroll.wx = reader.read_u8()?;
+        self.palettes.bgp = reader.read_u8()?.into();
+        self.palettes.obp0 = reader.read_u8()?.into();
+        self.palettes.obp1 = reader.read_u8()?.into();
+        Ok(())
+    }
+}
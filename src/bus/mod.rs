@@ -1,16 +1,26 @@
+use crate::{
+    cartridge::{header::CGBFlag, Cartridge, MemoryBankController, RamMapping},
+    save_state::{SaveStateError, Snapshot, StateReader, StateWriter},
+};
+
 use self::{
-    audio::{AudioRegisters, WavePattern},
-    joypad::JoypadRegister,
+    audio::{output::AudioOutput, AudioRegisters},
+    hdma::Hdma,
+    joypad::{Button, JoypadRegister},
     lcd::LCDRegisters,
     oam::Oam,
+    palettes::CGBPalettes,
     serial::SerialRegister,
     time_divider::TimeDividerRegister,
 };
 
 pub mod audio;
+mod bus_test;
+pub mod hdma;
 pub mod joypad;
 pub mod lcd;
 pub mod oam;
+pub mod palettes;
 pub mod serial;
 pub mod time_divider;
 
@@ -23,22 +33,23 @@ pub struct InteruptFlags {
     pub v_blank: bool,
 }
 
+// IF and IE share the same layout, bit 0 being the highest priority
 impl std::convert::From<InteruptFlags> for u8 {
     fn from(flags: InteruptFlags) -> u8 {
         let mut value = 0;
-        if flags.joypad {
+        if flags.v_blank {
             value |= 0b0000_0001;
         }
-        if flags.serial {
+        if flags.lcd_stat {
             value |= 0b0000_0010;
         }
         if flags.timer {
             value |= 0b0000_0100;
         }
-        if flags.lcd_stat {
+        if flags.serial {
             value |= 0b0000_1000;
         }
-        if flags.v_blank {
+        if flags.joypad {
             value |= 0b0001_0000;
         }
         value
@@ -48,12 +59,65 @@ impl std::convert::From<InteruptFlags> for u8 {
 impl std::convert::From<u8> for InteruptFlags {
     fn from(value: u8) -> Self {
         InteruptFlags {
-            joypad: value & 0b0000_0001 != 0,
-            serial: value & 0b0000_0010 != 0,
+            v_blank: value & 0b0000_0001 != 0,
+            lcd_stat: value & 0b0000_0010 != 0,
             timer: value & 0b0000_0100 != 0,
-            lcd_stat: value & 0b0000_1000 != 0,
-            v_blank: value & 0b0001_0000 != 0,
+            serial: value & 0b0000_1000 != 0,
+            joypad: value & 0b0001_0000 != 0,
+        }
+    }
+}
+
+/// Hardware the machine runs as, the CGB adds VRAM/WRAM banking and double speed
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum HardwareMode {
+    #[default]
+    DMG,
+    CGB,
+}
+
+impl HardwareMode {
+    /// Mode picked by the cartridge header, color enhanced cartridges run as CGB
+    pub fn from_cartridge(cartridge: &Cartridge) -> Self {
+        match cartridge.header.cgb_flag {
+            CGBFlag::None => HardwareMode::DMG,
+            CGBFlag::Supported | CGBFlag::Only => HardwareMode::CGB,
+        }
+    }
+}
+
+impl std::str::FromStr for HardwareMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "dmg" => Ok(HardwareMode::DMG),
+            "cgb" => Ok(HardwareMode::CGB),
+            _ => Err(format!(
+                "unknown hardware mode {}, expected dmg or cgb",
+                value
+            )),
+        }
+    }
+}
+
+/// KEY1 (0xFF4D), CGB speed switch armed by the game and performed by STOP
+#[derive(Default, Debug, Clone, Copy)]
+pub struct SpeedSwitch {
+    pub double_speed: bool,
+    pub armed: bool,
+}
+
+impl std::convert::From<SpeedSwitch> for u8 {
+    fn from(value: SpeedSwitch) -> u8 {
+        let mut result = 0b0111_1110;
+        if value.double_speed {
+            result |= 0b1000_0000;
         }
+        if value.armed {
+            result |= 0b0000_0001;
+        }
+        result
     }
 }
 
@@ -63,13 +127,15 @@ pub struct IORegisters {
     pub serial: SerialRegister,
     pub timer_divider: TimeDividerRegister,
     pub audio: AudioRegisters,
-    pub wave_pattern: WavePattern,
     pub lcd: LCDRegisters,
     // TODO: CGB registers
+    // KEY0, bit 2 set by the CGB boot ROM for cartridges without CGB support
+    pub key0: u8,
+    pub speed_switch: SpeedSwitch,
     pub vram_bank: u8,
     pub disable_boot_rom: u8,
-    pub _vram_dma: u8,
-    pub _bg_obj_palletes: u8,
+    pub hdma: Hdma,
+    pub cgb_palettes: CGBPalettes,
     pub wram_bank: u8,
 }
 
@@ -79,15 +145,19 @@ impl Memory for IORegisters {
             0xFF00 => self.joypad.into(),
             0xFF01..=0xFF02 => self.serial.read_byte(address),
             0xFF04..=0xFF07 => self.timer_divider.read_byte(address),
-            0xFF10..=0xFF26 => self.audio.read_byte(address),
-            0xFF30..=0xFF3F => self.wave_pattern.read_byte(address),
+            0xFF10..=0xFF26 | 0xFF30..=0xFF3F => self.audio.read_byte(address),
             0xFF40..=0xFF45 => self.lcd.read_byte(address),
             0xFF47..=0xFF4B => self.lcd.read_byte(address),
-            0xFF4F => self.vram_bank,
+            0xFF4C => self.key0 | 0b1111_0011,
+            0xFF4D => self.speed_switch.into(),
+            0xFF4F => self.vram_bank | 0xFE,
             0xFF50 => self.disable_boot_rom,
-            0xFF51..=0xFF55 => 0,
-            0xFF68..=0xFF6B => 0,
-            0xFF70 => self.wram_bank,
+            0xFF51..=0xFF55 => self.hdma.read_byte(address),
+            0xFF56 => 0xFF,
+            0xFF68..=0xFF6B => self.cgb_palettes.read_byte(address),
+            0xFF6C => 0,
+            0xFF70 => self.wram_bank | 0xF8,
+            0xFF72..=0xFF77 => 0xFF,
             _ => panic!("Invalid read from IORegisters address: {:04X}", address),
         }
     }
@@ -97,14 +167,28 @@ impl Memory for IORegisters {
             0xFF00 => self.joypad.write_byte(value),
             0xFF01..=0xFF02 => self.serial.write_byte(address, value),
             0xFF04..=0xFF07 => self.timer_divider.write_byte(address, value),
-            0xFF10..=0xFF26 => self.audio.write_byte(address, value),
-            0xFF30..=0xFF3F => self.wave_pattern.write_byte(address, value),
+            0xFF10..=0xFF26 | 0xFF30..=0xFF3F => self.audio.write_byte(address, value),
             0xFF40..=0xFF4B => self.lcd.write_byte(address, value),
-            0xFF4F => self.vram_bank = value,
+            // KEY0 is locked once the boot ROM is unmapped
+            0xFF4C if self.disable_boot_rom == 0 => self.key0 = value & 0b0000_1100,
+            0xFF4C => (),
+            0xFF4D => self.speed_switch.armed = value & 0b0000_0001 != 0,
+            0xFF4F => self.vram_bank = value & 0b0000_0001,
             0xFF50 => self.disable_boot_rom = value,
-            0xFF51..=0xFF55 => (),
-            0xFF68..=0xFF6B => (),
-            0xFF70 => self.wram_bank = value,
+            0xFF51..=0xFF55 => {
+                self.hdma.write_byte(address, value);
+                // Started during HBlank or with the LCD off, the first block is copied right away
+                if address == 0xFF55
+                    && (self.lcd.status.stat.ppu_mode == 0 || !self.lcd.control.lcd_enable)
+                {
+                    self.hdma.hblank_started();
+                }
+            }
+            0xFF56 => (),
+            0xFF68..=0xFF6B => self.cgb_palettes.write_byte(address, value),
+            0xFF6C => (),
+            0xFF70 => self.wram_bank = value & 0b0000_0111,
+            0xFF72..=0xFF77 => (),
             _ => panic!("Invalid write to IORegisters address: {:04X}", address),
         }
     }
@@ -114,8 +198,11 @@ impl Memory for IORegisters {
 pub struct Bus {
     pub memory_lock: MemoryLock,
     pub current_owner: MemoryLockOwner,
+    pub mode: HardwareMode,
 
-    pub boot_rom: [u8; 0x100],
+    // 0x100 bytes for the DMG, 0x900 for the CGB which also maps 0x0200-0x08FF
+    pub boot_rom: Vec<u8>,
+    pub mbc: MemoryBankController,
     pub rom: [u8; 0x4000],
     pub banked_rom: Vec<[u8; 0x4000]>,
     pub vram: ([u8; 0x2000], [u8; 0x2000]),
@@ -125,6 +212,8 @@ pub struct Bus {
     pub oam: Oam,
     pub interupt_flags: InteruptFlags,
     pub io: IORegisters,
+    // Resampled APU output waiting to be drained by the frontend, not part of save states
+    pub audio_output: AudioOutput,
     pub hram: [u8; 0x0080],
     pub interupt_enable: InteruptFlags,
 }
@@ -134,8 +223,10 @@ impl Default for Bus {
         Self {
             memory_lock: MemoryLock::default(),
             current_owner: MemoryLockOwner::CPU,
+            mode: HardwareMode::DMG,
 
-            boot_rom: [0; 0x100],
+            boot_rom: vec![0; 0x100],
+            mbc: MemoryBankController::None,
             rom: [0; 0x4000],
             banked_rom: vec![[0; 0x4000]],
             vram: ([0; 0x2000], [0; 0x2000]),
@@ -145,6 +236,7 @@ impl Default for Bus {
             oam: Oam::default(),
             interupt_flags: InteruptFlags::default(),
             io: IORegisters::default(),
+            audio_output: AudioOutput::default(),
             hram: [0; 0x0080],
             interupt_enable: InteruptFlags::default(),
         }
@@ -162,6 +254,7 @@ pub enum MemoryLockOwner {
 pub struct MemoryLock {
     vram: Option<MemoryLockOwner>,
     oam: Option<MemoryLockOwner>,
+    cgb_palette: Option<MemoryLockOwner>,
 }
 
 #[derive(Default, Debug, Clone, Copy)]
@@ -169,8 +262,8 @@ pub enum MemoryRegion {
     #[default]
     VRAM,
     OAM,
-    // TODO: CGB WRAM,
-    // CGBPalette,
+    // BCPD and OCPD, the palette RAM being read while drawing
+    CGBPalette,
 }
 
 impl Bus {
@@ -182,6 +275,9 @@ impl Bus {
             MemoryRegion::OAM => {
                 self.memory_lock.oam = Some(self.current_owner);
             }
+            MemoryRegion::CGBPalette => {
+                self.memory_lock.cgb_palette = Some(self.current_owner);
+            }
         }
     }
 
@@ -193,74 +289,357 @@ impl Bus {
             MemoryRegion::OAM => {
                 self.memory_lock.oam = None;
             }
+            MemoryRegion::CGBPalette => {
+                self.memory_lock.cgb_palette = None;
+            }
         }
     }
 
     pub fn locked(&self, address: u16) -> bool {
-        match address {
-            0x8000..=0x9FFF => {
-                self.memory_lock.vram.is_some()
-                    && self.memory_lock.vram != Some(MemoryLockOwner::PPU)
-            }
-            0xFE00..=0xFE9F => {
-                self.memory_lock.oam.is_some() && self.memory_lock.oam != Some(MemoryLockOwner::PPU)
-            }
-            _ => false,
+        // The OAM DMA owns the external bus, only HRAM and the registers remain reachable
+        if self.oam.dma_active() && self.current_owner == MemoryLockOwner::CPU && address < 0xFF00 {
+            return true;
         }
+        // A locked region can only be reached by the owner of the lock
+        let lock = match address {
+            0x8000..=0x9FFF => self.memory_lock.vram,
+            0xFE00..=0xFE9F => self.memory_lock.oam,
+            0xFF69 | 0xFF6B => self.memory_lock.cgb_palette,
+            _ => None,
+        };
+        lock.is_some_and(|owner| owner != self.current_owner)
     }
 
     pub fn load_boot_rom(&mut self, boot_rom: &[u8]) {
-        self.boot_rom[..boot_rom.len()].copy_from_slice(boot_rom);
-    }
-
-    pub fn load_rom(&mut self, rom: &[u8]) {
-        for (i, byte) in rom.iter().enumerate() {
-            match i {
-                0..=0x3FFF => self.rom[i] = *byte,
-                _ => {
-                    let bank = (i / 0x4000) - 1;
-                    let offset = i % 0x4000;
-                    if self.banked_rom.len() <= bank {
-                        self.banked_rom.push([0; 0x4000]);
-                    }
-                    self.banked_rom[bank][offset] = *byte;
-                }
+        self.boot_rom = boot_rom.to_vec();
+    }
+
+    fn boot_rom_mapped(&self, address: u16) -> bool {
+        self.io.disable_boot_rom == 0 && (address as usize) < self.boot_rom.len()
+    }
+
+    /// CGB running a DMG cartridge: the PPU behaves as on DMG, but its colors go through
+    /// the CGB palettes the boot ROM set up
+    pub fn dmg_compatibility(&self) -> bool {
+        self.mode == HardwareMode::CGB && self.io.key0 & 0b0000_0100 != 0
+    }
+
+    /// Dots the PPU advances per M-cycle, the CPU runs twice as fast in double speed
+    pub fn dots_per_cycle(&self) -> u8 {
+        if self.io.speed_switch.double_speed {
+            2
+        } else {
+            4
+        }
+    }
+
+    /// Perform the speed switch armed through KEY1, returns false when none is armed
+    pub fn switch_speed(&mut self) -> bool {
+        if self.mode != HardwareMode::CGB || !self.io.speed_switch.armed {
+            return false;
+        }
+        let speed_switch = &mut self.io.speed_switch;
+        speed_switch.double_speed = !speed_switch.double_speed;
+        speed_switch.armed = false;
+        self.io.timer_divider.write_byte(0xFF04, 0);
+        true
+    }
+
+    fn vram_bank(&self) -> usize {
+        match self.mode {
+            HardwareMode::DMG => 0,
+            HardwareMode::CGB => self.io.vram_bank as usize,
+        }
+    }
+
+    /// Bank mapped at 0xD000-0xDFFF, as an index in `external_wram`
+    fn wram_bank(&self) -> usize {
+        match self.mode {
+            HardwareMode::DMG => 0,
+            HardwareMode::CGB => (self.io.wram_bank.max(1) - 1) as usize,
+        }
+    }
+
+    pub fn load_rom(&mut self, cartridge: &Cartridge) {
+        self.mbc = cartridge.mbc;
+
+        let mut banks = cartridge.rom.chunks_exact(0x4000);
+        if let Some(bank) = banks.next() {
+            self.rom.copy_from_slice(bank);
+        }
+        self.banked_rom = banks
+            .map(|bank| {
+                let mut banked_rom = [0; 0x4000];
+                banked_rom.copy_from_slice(bank);
+                banked_rom
+            })
+            .collect();
+
+        self.external_ram = vec![[0; 0x2000]; cartridge.ram_size().div_ceil(0x2000)];
+    }
+
+    /// Header and global checksums of the loaded ROM, identifying it in save states
+    pub fn rom_checksums(&self) -> (u8, u16) {
+        (
+            self.rom[0x014D],
+            u16::from_be_bytes([self.rom[0x014E], self.rom[0x014F]]),
+        )
+    }
+
+    /// ROM bank `bank`, wrapping around the number of banks of the cartridge
+    fn rom_bank(&self, bank: usize) -> &[u8; 0x4000] {
+        match bank % (self.banked_rom.len() + 1) {
+            0 => &self.rom,
+            bank => &self.banked_rom[bank - 1],
+        }
+    }
+
+    fn read_external_ram(&self, address: u16) -> u8 {
+        let offset = address as usize - 0xA000;
+        match self.mbc.ram_mapping() {
+            _ if self.external_ram.is_empty() => self.mbc.read_byte(address),
+            RamMapping::Bank(bank) => self.external_ram[bank % self.external_ram.len()][offset],
+            // Only the lower 4 bits are wired, the upper bits read as 1
+            RamMapping::Nibbles => self.external_ram[0][offset & 0x01FF] | 0xF0,
+            RamMapping::Disabled | RamMapping::Register => self.mbc.read_byte(address),
+        }
+    }
+
+    fn write_external_ram(&mut self, address: u16, value: u8) {
+        let offset = address as usize - 0xA000;
+        match self.mbc.ram_mapping() {
+            _ if self.external_ram.is_empty() => self.mbc.write_byte(address, value),
+            RamMapping::Bank(bank) => {
+                let bank = bank % self.external_ram.len();
+                self.external_ram[bank][offset] = value;
             }
+            RamMapping::Nibbles => self.external_ram[0][offset & 0x01FF] = value & 0x0F,
+            RamMapping::Disabled | RamMapping::Register => self.mbc.write_byte(address, value),
         }
     }
 }
 
-impl Memory for Bus {
-    fn read_byte(&self, address: u16) -> u8 {
-        if self.locked(address) {
-            return 0xFF;
+impl Snapshot for IORegisters {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.joypad.save_state(writer);
+        self.serial.save_state(writer);
+        self.timer_divider.save_state(writer);
+        self.audio.save_state(writer);
+        self.lcd.save_state(writer);
+        writer.write_u8(self.key0);
+        writer.write_bool(self.speed_switch.double_speed);
+        writer.write_bool(self.speed_switch.armed);
+        writer.write_u8(self.vram_bank);
+        writer.write_u8(self.disable_boot_rom);
+        self.hdma.save_state(writer);
+        self.cgb_palettes.save_state(writer);
+        writer.write_u8(self.wram_bank);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.joypad.load_state(reader)?;
+        self.serial.load_state(reader)?;
+        self.timer_divider.load_state(reader)?;
+        self.audio.load_state(reader)?;
+        self.lcd.load_state(reader)?;
+        self.key0 = reader.read_u8()?;
+        self.speed_switch.double_speed = reader.read_bool()?;
+        self.speed_switch.armed = reader.read_bool()?;
+        self.vram_bank = reader.read_u8()?;
+        self.disable_boot_rom = reader.read_u8()?;
+        self.hdma.load_state(reader)?;
+        self.cgb_palettes.load_state(reader)?;
+        self.wram_bank = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+impl MemoryLockOwner {
+    fn save_state(owner: Option<MemoryLockOwner>, writer: &mut StateWriter) {
+        writer.write_u8(match owner {
+            None => 0,
+            Some(MemoryLockOwner::CPU) => 1,
+            Some(MemoryLockOwner::PPU) => 2,
+        });
+    }
+
+    fn load_state(reader: &mut StateReader) -> Result<Option<MemoryLockOwner>, SaveStateError> {
+        match reader.read_u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(MemoryLockOwner::CPU)),
+            2 => Ok(Some(MemoryLockOwner::PPU)),
+            _ => Err(SaveStateError::InvalidValue("memory lock owner")),
         }
-        match address {
-            0x0000..=0x00FF => {
-                if self.io.disable_boot_rom == 0 {
-                    self.boot_rom[address as usize]
-                } else {
-                    self.rom[address as usize]
+    }
+}
+
+impl Snapshot for Bus {
+    fn save_state(&self, writer: &mut StateWriter) {
+        MemoryLockOwner::save_state(self.memory_lock.vram, writer);
+        MemoryLockOwner::save_state(self.memory_lock.oam, writer);
+        MemoryLockOwner::save_state(self.memory_lock.cgb_palette, writer);
+        MemoryLockOwner::save_state(Some(self.current_owner), writer);
+        writer.write_u8(self.mode as u8);
+        self.mbc.save_state(writer);
+        writer.write_bytes(&self.vram.0);
+        writer.write_bytes(&self.vram.1);
+        writer.write_u32(self.external_ram.len() as u32);
+        for bank in self.external_ram.iter() {
+            writer.write_bytes(bank);
+        }
+        writer.write_bytes(&self.wram);
+        for bank in self.external_wram.iter() {
+            writer.write_bytes(bank);
+        }
+        self.oam.save_state(writer);
+        writer.write_u8(self.interupt_flags.into());
+        self.io.save_state(writer);
+        writer.write_bytes(&self.hram);
+        writer.write_u8(self.interupt_enable.into());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.memory_lock.vram = MemoryLockOwner::load_state(reader)?;
+        self.memory_lock.oam = MemoryLockOwner::load_state(reader)?;
+        self.memory_lock.cgb_palette = MemoryLockOwner::load_state(reader)?;
+        self.current_owner = MemoryLockOwner::load_state(reader)?
+            .ok_or(SaveStateError::InvalidValue("memory lock owner"))?;
+        self.mode = match reader.read_u8()? {
+            0 => HardwareMode::DMG,
+            1 => HardwareMode::CGB,
+            _ => return Err(SaveStateError::InvalidValue("hardware mode")),
+        };
+        self.mbc.load_state(reader)?;
+        reader.read_into(&mut self.vram.0)?;
+        reader.read_into(&mut self.vram.1)?;
+        if reader.read_u32()? as usize != self.external_ram.len() {
+            return Err(SaveStateError::InvalidValue("external RAM size"));
+        }
+        for bank in self.external_ram.iter_mut() {
+            reader.read_into(bank)?;
+        }
+        reader.read_into(&mut self.wram)?;
+        for bank in self.external_wram.iter_mut() {
+            reader.read_into(bank)?;
+        }
+        self.oam.load_state(reader)?;
+        self.interupt_flags = reader.read_u8()?.into();
+        self.io.load_state(reader)?;
+        reader.read_into(&mut self.hram)?;
+        self.interupt_enable = reader.read_u8()?.into();
+        Ok(())
+    }
+}
+
+impl Bus {
+    /// Advance the timer and the APU by one M-cycle
+    pub fn tick(&mut self) {
+        let counter = self.io.timer_divider.system_counter();
+        self.io.timer_divider.tick(&mut self.interupt_flags);
+        self.frame_sequencer_edge(counter);
+        let clocks = self.dots_per_cycle();
+        self.io.audio.tick(clocks);
+        let (left, right) = self.io.audio.mix();
+        self.audio_output.push(left, right, clocks);
+    }
+
+    /// The 512 Hz APU frame sequencer is clocked by the falling edge of DIV bit 4, bit 5
+    /// in double speed
+    fn frame_sequencer_edge(&mut self, previous_counter: u16) {
+        let bit = if self.io.speed_switch.double_speed {
+            1 << 13
+        } else {
+            1 << 12
+        };
+        let counter = self.io.timer_divider.system_counter();
+        if previous_counter & bit != 0 && counter & bit == 0 {
+            self.io.audio.frame_sequencer_step();
+        }
+    }
+
+    /// Press or release `button`, a press requests the joypad interrupt
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let state = self.io.joypad.buttons.get_mut(button);
+        if pressed && !*state {
+            self.interupt_flags.joypad = true;
+        }
+        *state = pressed;
+    }
+
+    /// Copy the next byte of a running OAM DMA transfer, called once per M-cycle
+    pub fn dma_transfer_step(&mut self) {
+        if let Some(source) = self.oam.dma_source() {
+            let value = self.read_unlocked(source);
+            self.oam.dma_write(value);
+        }
+    }
+
+    /// Copy the VRAM DMA blocks due now, returns the M-cycles the CPU is stalled for
+    pub fn hdma_step(&mut self) -> u16 {
+        let blocks = self.io.hdma.pending_blocks();
+        for _ in 0..blocks {
+            let hdma = self.io.hdma;
+            let bank = self.vram_bank();
+            for offset in 0..0x10 {
+                let value = self.read_unlocked(hdma.source.wrapping_add(offset));
+                let address = (hdma.destination + offset) as usize;
+                match bank {
+                    0 => self.vram.0[address] = value,
+                    _ => self.vram.1[address] = value,
                 }
             }
-            0x0100..=0x3FFF => self.rom[address as usize],
-            0x4000..=0x7FFF => self.banked_rom[0][address as usize - 0x4000],
-            0x8000..=0x9FFF => self.vram.0[address as usize - 0x8000],
-            0xA000..=0xBFFF => self.external_ram[0][address as usize - 0xA000],
+            self.io.hdma.block_done();
+        }
+        // A block takes 8 M-cycles in single speed, twice as many in double speed
+        blocks as u16 * 32 / self.dots_per_cycle() as u16
+    }
+
+    /// Read ignoring the memory locks, for the DMA which owns the bus
+    fn read_unlocked(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x00FF | 0x0200..=0x08FF if self.boot_rom_mapped(address) => {
+                self.boot_rom[address as usize]
+            }
+            0x0000..=0x3FFF => self.rom_bank(self.mbc.rom_bank_low())[address as usize],
+            0x4000..=0x7FFF => self.rom_bank(self.mbc.rom_bank_high())[address as usize - 0x4000],
+            0x8000..=0x9FFF => match self.vram_bank() {
+                0 => self.vram.0[address as usize - 0x8000],
+                _ => self.vram.1[address as usize - 0x8000],
+            },
+            0xA000..=0xBFFF => self.read_external_ram(address),
             0xC000..=0xCFFF => self.wram[address as usize - 0xC000],
-            0xD000..=0xDFFF => self.external_wram[0][address as usize - 0xD000],
-            0xE000..=0xFDFF => self.read_byte(address - 0x2000),
+            0xD000..=0xDFFF => self.external_wram[self.wram_bank()][address as usize - 0xD000],
+            0xE000..=0xFDFF => self.read_unlocked(address - 0x2000),
             0xFE00..=0xFE9F => self.oam.read_byte(address),
             0xFEA0..=0xFEFF => 0,
             0xFF00..=0xFF0E => self.io.read_byte(address),
             0xFF0F => self.interupt_flags.into(),
+            // The DMG only lets the CPU into wave RAM when channel 3 just read it
+            0xFF30..=0xFF3F if self.mode == HardwareMode::DMG => {
+                self.io.audio.wave.read_ram(address, true)
+            }
             0xFF10..=0xFF45 => self.io.read_byte(address),
             0xFF46 => self.oam.read_byte(address),
+            // CGB only registers
+            0xFF4C..=0xFF4F | 0xFF51..=0xFF56 | 0xFF68..=0xFF77
+                if self.mode == HardwareMode::DMG =>
+            {
+                0xFF
+            }
             0xFF47..=0xFF7F => self.io.read_byte(address),
             0xFF80..=0xFFFE => self.hram[address as usize - 0xFF80],
             0xFFFF => self.interupt_enable.into(),
         }
     }
+}
+
+impl Memory for Bus {
+    fn read_byte(&self, address: u16) -> u8 {
+        if self.locked(address) {
+            return 0xFF;
+        }
+        self.read_unlocked(address)
+    }
 
     fn write_byte(&mut self, address: u16, value: u8) {
         if self.locked(address) {
@@ -268,19 +647,36 @@ impl Memory for Bus {
         }
 
         match address {
-            0x0000..=0x3FFF => self.rom[address as usize] = value,
-            0x4000..=0x7FFF => self.banked_rom[0][address as usize - 0x4000] = value,
-            0x8000..=0x9FFF => self.vram.0[address as usize - 0x8000] = value,
-            0xA000..=0xBFFF => self.external_ram[0][address as usize - 0xA000] = value,
+            0x0000..=0x7FFF => self.mbc.write_byte(address, value),
+            0x8000..=0x9FFF => match self.vram_bank() {
+                0 => self.vram.0[address as usize - 0x8000] = value,
+                _ => self.vram.1[address as usize - 0x8000] = value,
+            },
+            0xA000..=0xBFFF => self.write_external_ram(address, value),
             0xC000..=0xCFFF => self.wram[address as usize - 0xC000] = value,
-            0xD000..=0xDFFF => self.external_wram[0][address as usize - 0xD000] = value,
+            0xD000..=0xDFFF => {
+                let bank = self.wram_bank();
+                self.external_wram[bank][address as usize - 0xD000] = value;
+            }
             0xE000..=0xFDFF => self.write_byte(address - 0x2000, value),
             0xFE00..=0xFE9F => self.oam.write_byte(address, value),
             0xFEA0..=0xFEFF => (),
+            0xFF04..=0xFF07 => {
+                let counter = self.io.timer_divider.system_counter();
+                self.io
+                    .timer_divider
+                    .write_register(address, value, &mut self.interupt_flags);
+                self.frame_sequencer_edge(counter);
+            }
             0xFF00..=0xFF0E => self.io.write_byte(address, value),
             0xFF0F => self.interupt_flags = value.into(),
+            0xFF30..=0xFF3F if self.mode == HardwareMode::DMG => {
+                self.io.audio.wave.write_ram(address, value, true)
+            }
             0xFF10..=0xFF45 => self.io.write_byte(address, value),
             0xFF46 => self.oam.write_byte(address, value),
+            0xFF4C..=0xFF4F | 0xFF51..=0xFF56 | 0xFF68..=0xFF77
+                if self.mode == HardwareMode::DMG => {}
             0xFF47..=0xFF7F => self.io.write_byte(address, value),
             0xFF80..=0xFFFE => self.hram[address as usize - 0xFF80] = value,
             0xFFFF => self.interupt_enable = value.into(),
@@ -294,13 +690,13 @@ pub trait Memory {
 
     fn read_word(&self, address: u16) -> u16 {
         let low = self.read_byte(address);
-        let high = self.read_byte(address + 1);
+        let high = self.read_byte(address.wrapping_add(1));
         u16::from_le_bytes([low, high])
     }
 
     fn write_word(&mut self, address: u16, value: u16) {
         let [low, high] = value.to_le_bytes();
         self.write_byte(address, low);
-        self.write_byte(address + 1, high);
+        self.write_byte(address.wrapping_add(1), high);
     }
 }
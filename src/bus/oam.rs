@@ -1,4 +1,6 @@
-use super::{Bus, Memory};
+use crate::save_state::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+use super::Memory;
 
 #[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub enum DMGPalette {
@@ -139,19 +141,34 @@ pub struct Oam {
 }
 
 impl Oam {
-    pub fn dma_transfer_step(&mut self, memory: Bus) {
+    /// An OAM DMA transfer is running, the CPU can only reach HRAM meanwhile
+    pub fn dma_active(&self) -> bool {
+        self.transfer
+    }
+
+    /// Source address of the next byte of the running OAM DMA transfer, if any
+    pub fn dma_source(&self) -> Option<u16> {
         if !self.transfer {
-            return;
+            return None;
         }
+        let source = (self.source_upper_byte as u16) << 8 | self.progress;
+        // Sources from E000 up read WRAM, like the echo RAM does
+        Some(if source >= 0xE000 {
+            source - 0x2000
+        } else {
+            source
+        })
+    }
+
+    /// Store the byte read from `dma_source` and move on to the next one
+    pub fn dma_write(&mut self, value: u8) {
+        let oa_index = (self.progress / 4) as usize;
+        self.data[oa_index].write_byte(self.progress % 4, value);
+        self.progress += 1;
         if self.progress == 160 {
             self.progress = 0;
             self.transfer = false;
-            return;
         }
-        println!("DMA transfer step: {:#04X}", self.progress);
-
-        let source_address = (self.source_upper_byte as u16) << 8 | self.progress as u16;
-        self.write_byte(0xFE00 + self.progress, memory.read_byte(source_address));
     }
 }
 
@@ -180,8 +197,10 @@ impl Memory for Oam {
 
     fn write_byte(&mut self, address: u16, value: u8) {
         match address {
+            // Writing the source starts a transfer, restarting any running one
             0xFF46 => {
                 self.transfer = true;
+                self.progress = 0;
                 self.source_upper_byte = value;
             }
             _ => {
@@ -192,3 +211,29 @@ impl Memory for Oam {
         }
     }
 }
+
+impl Snapshot for Oam {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.transfer);
+        writer.write_u16(self.progress);
+        writer.write_u8(self.source_upper_byte);
+        for address in 0xFE00..=0xFE9F {
+            writer.write_u8(self.read_byte(address));
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let transfer = reader.read_bool()?;
+        let progress = reader.read_u16()?;
+        if progress >= 160 {
+            return Err(SaveStateError::InvalidValue("OAM DMA"));
+        }
+        self.transfer = transfer;
+        self.progress = progress;
+        self.source_upper_byte = reader.read_u8()?;
+        for address in 0xFE00..=0xFE9F {
+            self.write_byte(address, reader.read_u8()?);
+        }
+        Ok(())
+    }
+}
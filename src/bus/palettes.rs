@@ -0,0 +1,107 @@
+use crate::save_state::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+use super::Memory;
+
+/// CGB palette RAM: 8 palettes of 4 little endian RGB555 colors, reached through an
+/// index register (BCPS/OCPS) and a data register (BCPD/OCPD)
+#[derive(Debug, Clone, Copy)]
+pub struct PaletteMemory {
+    pub index: u8,
+    // Move to the next byte after each write to the data register
+    pub auto_increment: bool,
+    pub data: [u8; 64],
+}
+
+impl Default for PaletteMemory {
+    fn default() -> Self {
+        Self {
+            index: 0,
+            auto_increment: false,
+            // White until the boot ROM or the game sets them
+            data: [0xFF; 64],
+        }
+    }
+}
+
+impl PaletteMemory {
+    /// RGB555 value of the color `color` of the palette `palette`
+    pub fn color(&self, palette: u8, color: u8) -> u16 {
+        let offset = (palette as usize & 0b111) * 8 + (color as usize & 0b11) * 2;
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]]) & 0x7FFF
+    }
+
+    fn read_specification(&self) -> u8 {
+        let mut value = self.index | 0b0100_0000;
+        if self.auto_increment {
+            value |= 0b1000_0000;
+        }
+        value
+    }
+
+    fn write_specification(&mut self, value: u8) {
+        self.index = value & 0b0011_1111;
+        self.auto_increment = value & 0b1000_0000 != 0;
+    }
+
+    fn write_data(&mut self, value: u8) {
+        self.data[self.index as usize] = value;
+        if self.auto_increment {
+            self.index = (self.index + 1) & 0b0011_1111;
+        }
+    }
+}
+
+impl Snapshot for PaletteMemory {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.read_specification());
+        writer.write_bytes(&self.data);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.write_specification(reader.read_u8()?);
+        reader.read_into(&mut self.data)?;
+        Ok(())
+    }
+}
+
+/// Background and object palettes of the CGB, 0xFF68-0xFF6B
+#[derive(Default, Debug, Clone, Copy)]
+pub struct CGBPalettes {
+    pub background: PaletteMemory,
+    pub object: PaletteMemory,
+}
+
+impl Memory for CGBPalettes {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0xFF68 => self.background.read_specification(),
+            0xFF69 => self.background.data[self.background.index as usize],
+            0xFF6A => self.object.read_specification(),
+            0xFF6B => self.object.data[self.object.index as usize],
+            _ => panic!("Invalid read from CGBPalettes address: {:04X}", address),
+        }
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF68 => self.background.write_specification(value),
+            0xFF69 => self.background.write_data(value),
+            0xFF6A => self.object.write_specification(value),
+            0xFF6B => self.object.write_data(value),
+            _ => panic!("Invalid write to CGBPalettes address: {:04X}", address),
+        }
+    }
+}
+
+impl Snapshot for CGBPalettes {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.background.save_state(writer);
+        self.object.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.background.load_state(reader)?;
+        self.object.load_state(reader)?;
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use crate::save_state::{SaveStateError, Snapshot, StateReader, StateWriter};
+
 use super::Memory;
 
 #[derive(Default, Debug, Clone, Copy, PartialEq)]
@@ -81,3 +83,26 @@ impl Memory for SerialRegister {
         }
     }
 }
+
+impl Snapshot for SerialRegister {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.data);
+        writer.write_bool(self.control.transfer_enable);
+        writer.write_u8(self.control.clock_speed as u8);
+        writer.write_u8(self.control.clock_select as u8);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.data = reader.read_u8()?;
+        self.control.transfer_enable = reader.read_bool()?;
+        self.control.clock_speed = match reader.read_u8()? {
+            0 => SerialClockSpeed::Normal,
+            _ => SerialClockSpeed::Double,
+        };
+        self.control.clock_select = match reader.read_u8()? {
+            0 => SerialClockSelect::ExternalClock,
+            _ => SerialClockSelect::InternalClock,
+        };
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use crate::save_state::{SaveStateError, Snapshot, StateReader, StateWriter};
+
 use super::{InteruptFlags, Memory};
 
 #[derive(Default, Debug, Clone, Copy)]
@@ -45,16 +47,20 @@ impl std::convert::From<u8> for TimerControl {
 
 #[derive(Default, Debug, Clone, Copy)]
 pub struct TimeDividerRegister {
-    internal_counter: u64,
-    pub div: u8,
+    // Incremented on every clock, DIV is its upper byte
+    system_counter: u16,
     pub tima: u8,
     pub tma: u8,
     pub tac: TimerControl,
 }
 
 impl TimeDividerRegister {
-    fn incr_div(&mut self) {
-        self.div = self.div.wrapping_add(1);
+    pub fn system_counter(&self) -> u16 {
+        self.system_counter
+    }
+
+    pub fn div(&self) -> u8 {
+        (self.system_counter >> 8) as u8
     }
 
     fn incr_tima(&mut self, interupt_flags: &mut InteruptFlags) {
@@ -67,51 +73,52 @@ impl TimeDividerRegister {
         }
     }
 
-    fn tick_tima(&mut self, interupt_flags: &mut InteruptFlags, cpu_freq_hz: u64) {
-        let clock_select = match self.tac.clock_select {
-            ClockSelect::Hz4096 => 1024,
-            ClockSelect::Hz262144 => 16,
-            ClockSelect::Hz65536 => 64,
-            ClockSelect::Hz16384 => 256,
+    /// TIMA is incremented on the falling edge of the system counter bit selected by TAC,
+    /// gated by the timer enable bit
+    fn timer_signal(&self) -> bool {
+        let bit = match self.tac.clock_select {
+            ClockSelect::Hz4096 => 9,
+            ClockSelect::Hz262144 => 3,
+            ClockSelect::Hz65536 => 5,
+            ClockSelect::Hz16384 => 7,
         };
+        self.tac.timer_enable && self.system_counter & (1 << bit) != 0
+    }
 
-        let cycles_per_tick = cpu_freq_hz / clock_select;
-        if self.internal_counter >= cycles_per_tick {
+    /// Advance by one M-cycle, 4 clocks
+    pub fn tick(&mut self, interupt_flags: &mut InteruptFlags) {
+        let signal = self.timer_signal();
+        self.system_counter = self.system_counter.wrapping_add(4);
+        if signal && !self.timer_signal() {
             self.incr_tima(interupt_flags);
-            self.internal_counter = 0;
         }
     }
 
-    fn tick_div(&mut self, cpu_freq_hz: u64) {
-        let cycles_per_tick = cpu_freq_hz / 16384;
-        if self.internal_counter >= cycles_per_tick {
-            self.incr_div();
-            self.internal_counter = 0;
+    /// Writes to DIV and TAC can make the timer signal fall, which increments TIMA
+    pub fn write_register(&mut self, address: u16, value: u8, interupt_flags: &mut InteruptFlags) {
+        let signal = self.timer_signal();
+        self.write_byte(address, value);
+        if signal && !self.timer_signal() {
+            self.incr_tima(interupt_flags);
         }
     }
-
-    pub fn tick(&mut self, interupt_flags: &mut InteruptFlags, cpu_freq_hz: u64) {
-        self.internal_counter += 1;
-        self.tick_tima(interupt_flags, cpu_freq_hz);
-        self.tick_div(cpu_freq_hz);
-    }
 }
 
 impl Memory for TimeDividerRegister {
     fn read_byte(&self, address: u16) -> u8 {
         match address {
-            0xFF04 => self.div,
+            0xFF04 => self.div(),
             0xFF05 => self.tima,
             0xFF06 => self.tma,
-            0xFF07 => self.tac.into(),
+            0xFF07 => u8::from(self.tac) | 0b1111_1000,
             _ => 0,
         }
     }
 
     fn write_byte(&mut self, address: u16, value: u8) {
         match address {
-            0xFF04 => self.div = 0,
-            // 0xFF05 => self.tima = value,
+            0xFF04 => self.system_counter = 0,
+            0xFF05 => self.tima = value,
             0xFF06 => self.tma = value,
             0xFF07 => self.tac = value.into(),
             _ => panic!(
@@ -121,3 +128,20 @@ impl Memory for TimeDividerRegister {
         }
     }
 }
+
+impl Snapshot for TimeDividerRegister {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.system_counter);
+        writer.write_u8(self.tima);
+        writer.write_u8(self.tma);
+        writer.write_u8(self.tac.into());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.system_counter = reader.read_u16()?;
+        self.tima = reader.read_u8()?;
+        self.tma = reader.read_u8()?;
+        self.tac = reader.read_u8()?.into();
+        Ok(())
+    }
+}
@@ -0,0 +1,483 @@
+#[cfg(test)]
+mod cartridge_tests {
+    use crate::{
+        bus::{Bus, Memory},
+        cartridge::{
+            header::{CGBFlag, CartridgeHeader, CartridgeType, Destination},
+            mbc3::TimeSource,
+            save::{SaveFile, RTC_FOOTER_SIZE},
+            Cartridge, CartridgeError, MemoryBankController,
+        },
+    };
+
+    fn build_rom(cartridge_type: u8, rom_size_code: u8, ram_size_code: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000 << rom_size_code];
+        rom[0x0134..0x013A].copy_from_slice(b"TETRIS");
+        rom[0x0147] = cartridge_type;
+        rom[0x0148] = rom_size_code;
+        rom[0x0149] = ram_size_code;
+        rom[0x014A] = 0x01;
+        rom[0x014B] = 0x01;
+        fix_checksums(&mut rom);
+        rom
+    }
+
+    /// ROM where the first byte of every bank holds the bank number
+    fn build_banked_cartridge(
+        cartridge_type: u8,
+        rom_size_code: u8,
+        ram_size_code: u8,
+    ) -> Cartridge {
+        let mut rom = build_rom(cartridge_type, rom_size_code, ram_size_code);
+        for (bank, data) in rom.chunks_exact_mut(0x4000).enumerate() {
+            data[0] = bank as u8;
+        }
+        fix_checksums(&mut rom);
+        Cartridge::new(rom).unwrap()
+    }
+
+    fn load(cartridge: &Cartridge) -> Bus {
+        let mut bus = Bus::default();
+        bus.io.disable_boot_rom = 1;
+        bus.load_rom(cartridge);
+        bus
+    }
+
+    fn fix_checksums(rom: &mut [u8]) {
+        rom[0x014D] = CartridgeHeader::compute_header_checksum(rom);
+        let global = CartridgeHeader::compute_global_checksum(rom);
+        rom[0x014E..0x0150].copy_from_slice(&global.to_be_bytes());
+    }
+
+    #[test]
+    fn parse_header() {
+        let mut rom = build_rom(0x13, 0x02, 0x03);
+        rom[0x0143] = 0x80;
+        rom[0x0146] = 0x03;
+        rom[0x014B] = 0x33;
+        rom[0x0144..0x0146].copy_from_slice(b"01");
+        rom[0x014C] = 0x02;
+        fix_checksums(&mut rom);
+
+        let cartridge = Cartridge::new(rom).unwrap();
+        let header = cartridge.header;
+        assert_eq!(header.title, "TETRIS");
+        assert_eq!(header.cgb_flag, CGBFlag::Supported);
+        assert!(header.sgb_flag);
+        assert_eq!(header.cartridge_type, CartridgeType::MBC3RamBattery);
+        assert_eq!(header.rom_size(), Ok(0x20000));
+        assert_eq!(header.rom_banks(), Ok(8));
+        assert_eq!(header.ram_size(), Ok(0x8000));
+        assert_eq!(header.destination, Destination::Overseas);
+        assert_eq!(header.licensee(), "01");
+        assert_eq!(header.version, 0x02);
+    }
+
+    #[test]
+    fn truncated_rom() {
+        assert_eq!(
+            Cartridge::new(vec![0; 0x100]).unwrap_err(),
+            CartridgeError::Truncated(0x100)
+        );
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let mut rom = build_rom(0x01, 0x01, 0x00);
+        rom.truncate(0x8000);
+        assert_eq!(
+            Cartridge::new(rom).unwrap_err(),
+            CartridgeError::SizeMismatch {
+                expected: 0x10000,
+                actual: 0x8000
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_size_codes() {
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[0x0148] = 0x20;
+        fix_checksums(&mut rom);
+        assert_eq!(
+            Cartridge::new(rom.clone()).unwrap_err(),
+            CartridgeError::InvalidRomSize(0x20)
+        );
+
+        rom[0x0148] = 0x00;
+        rom[0x0149] = 0x09;
+        fix_checksums(&mut rom);
+        assert_eq!(
+            Cartridge::new(rom).unwrap_err(),
+            CartridgeError::InvalidRamSize(0x09)
+        );
+    }
+
+    #[test]
+    fn corrupt_checksums() {
+        let mut rom = build_rom(0x00, 0x00, 0x00);
+        rom[0x0134] = b'X';
+        assert!(matches!(
+            Cartridge::new(rom.clone()).unwrap_err(),
+            CartridgeError::HeaderChecksum { .. }
+        ));
+
+        fix_checksums(&mut rom);
+        rom[0x4000] ^= 0xFF;
+        assert!(matches!(
+            Cartridge::new(rom).unwrap_err(),
+            CartridgeError::GlobalChecksum { .. }
+        ));
+    }
+
+    #[test]
+    fn mbc1_rom_banking() {
+        // 2 MiB ROM, 128 banks
+        let mut bus = load(&build_banked_cartridge(0x01, 0x06, 0x00));
+        assert_eq!(bus.read_byte(0x0000), 0);
+        assert_eq!(bus.read_byte(0x4000), 1);
+
+        bus.write_byte(0x2000, 0x05);
+        assert_eq!(bus.read_byte(0x4000), 5);
+
+        // Bank 0 maps to bank 1, and so do the upper bits beyond the 5 bits register
+        bus.write_byte(0x2000, 0x00);
+        assert_eq!(bus.read_byte(0x4000), 1);
+        bus.write_byte(0x2000, 0x20);
+        assert_eq!(bus.read_byte(0x4000), 1);
+
+        // Upper bank bits
+        bus.write_byte(0x2000, 0x03);
+        bus.write_byte(0x4000, 0x01);
+        assert_eq!(bus.read_byte(0x4000), 0x23);
+        assert_eq!(bus.read_byte(0x0000), 0);
+
+        // Advanced banking mode switches the 0000–3FFF area on large ROMs
+        bus.write_byte(0x6000, 0x01);
+        assert_eq!(bus.read_byte(0x0000), 0x20);
+        bus.write_byte(0x4000, 0x02);
+        assert_eq!(bus.read_byte(0x0000), 0x40);
+        assert_eq!(bus.read_byte(0x4000), 0x43);
+
+        // Writes never reach the ROM
+        bus.write_byte(0x6000, 0x00);
+        assert_eq!(bus.read_byte(0x0000), 0);
+        assert_eq!(bus.rom[0x0000], 0);
+    }
+
+    #[test]
+    fn mbc1_rom_bank_wraps() {
+        // 128 KiB ROM, 8 banks
+        let mut bus = load(&build_banked_cartridge(0x01, 0x02, 0x00));
+        bus.write_byte(0x2000, 0x09);
+        assert_eq!(bus.read_byte(0x4000), 1);
+    }
+
+    #[test]
+    fn mbc1_ram_banking() {
+        // 512 KiB ROM, 32 KiB RAM
+        let mut bus = load(&build_banked_cartridge(0x03, 0x04, 0x03));
+        assert_eq!(bus.external_ram.len(), 4);
+
+        // Disabled RAM
+        bus.write_byte(0xA000, 0x42);
+        assert_eq!(bus.read_byte(0xA000), 0xFF);
+
+        bus.write_byte(0x0000, 0x0A);
+        bus.write_byte(0xA000, 0x42);
+        assert_eq!(bus.read_byte(0xA000), 0x42);
+
+        // Simple banking mode always maps RAM bank 0
+        bus.write_byte(0x4000, 0x02);
+        assert_eq!(bus.read_byte(0xA000), 0x42);
+
+        bus.write_byte(0x6000, 0x01);
+        assert_eq!(bus.read_byte(0xA000), 0x00);
+        bus.write_byte(0xA000, 0x24);
+        assert_eq!(bus.external_ram[2][0], 0x24);
+        assert_eq!(bus.external_ram[0][0], 0x42);
+
+        bus.write_byte(0x0000, 0x00);
+        assert_eq!(bus.read_byte(0xA000), 0xFF);
+    }
+
+    fn set_time(bus: &mut Bus, timestamp: u64) {
+        if let MemoryBankController::MBC3(mbc) = &mut bus.mbc {
+            mbc.rtc.time_source = TimeSource::Manual(timestamp);
+        }
+    }
+
+    fn latch_rtc(bus: &mut Bus) {
+        bus.write_byte(0x6000, 0x00);
+        bus.write_byte(0x6000, 0x01);
+    }
+
+    fn read_rtc(bus: &mut Bus, register: u8) -> u8 {
+        bus.write_byte(0x4000, register);
+        bus.read_byte(0xA000)
+    }
+
+    fn load_mbc3(cartridge: &Cartridge) -> Bus {
+        let mut bus = load(cartridge);
+        bus.mbc = MemoryBankController::with_time_source(&cartridge.header, TimeSource::Manual(0))
+            .unwrap();
+        bus
+    }
+
+    #[test]
+    fn mbc3_banking() {
+        // 2 MiB ROM, 32 KiB RAM
+        let mut bus = load_mbc3(&build_banked_cartridge(0x13, 0x06, 0x03));
+        bus.write_byte(0x2000, 0x7F);
+        assert_eq!(bus.read_byte(0x4000), 0x7F);
+        bus.write_byte(0x2000, 0x00);
+        assert_eq!(bus.read_byte(0x4000), 0x01);
+
+        bus.write_byte(0x0000, 0x0A);
+        for bank in 0..4 {
+            bus.write_byte(0x4000, bank);
+            bus.write_byte(0xA000, bank + 0x10);
+        }
+        for bank in 0..4 {
+            assert_eq!(bus.external_ram[bank][0], bank as u8 + 0x10);
+        }
+    }
+
+    #[test]
+    fn mbc3_rtc_latch() {
+        let mut bus = load_mbc3(&build_banked_cartridge(0x10, 0x02, 0x03));
+        bus.write_byte(0x0000, 0x0A);
+
+        set_time(&mut bus, 3 * 86400 + 2 * 3600 + 5 * 60 + 7);
+        // Registers keep the latched value until the next latch sequence
+        assert_eq!(read_rtc(&mut bus, 0x08), 0);
+        latch_rtc(&mut bus);
+        assert_eq!(read_rtc(&mut bus, 0x08), 7);
+        assert_eq!(read_rtc(&mut bus, 0x09), 5);
+        assert_eq!(read_rtc(&mut bus, 0x0A), 2);
+        assert_eq!(read_rtc(&mut bus, 0x0B), 3);
+
+        set_time(&mut bus, 3 * 86400 + 2 * 3600 + 5 * 60 + 20);
+        assert_eq!(read_rtc(&mut bus, 0x08), 7);
+        // Writing 01 again without 00 first doesn't latch
+        bus.write_byte(0x6000, 0x01);
+        assert_eq!(read_rtc(&mut bus, 0x08), 7);
+        latch_rtc(&mut bus);
+        assert_eq!(read_rtc(&mut bus, 0x08), 20);
+    }
+
+    #[test]
+    fn mbc3_rtc_halt_and_carry() {
+        let mut bus = load_mbc3(&build_banked_cartridge(0x10, 0x02, 0x03));
+        bus.write_byte(0x0000, 0x0A);
+
+        // Halt the clock and set it to day 511 23:59:59
+        bus.write_byte(0x4000, 0x0C);
+        bus.write_byte(0xA000, 0b0100_0001);
+        for (register, value) in [(0x08, 59), (0x09, 59), (0x0A, 23), (0x0B, 0xFF)] {
+            bus.write_byte(0x4000, register);
+            bus.write_byte(0xA000, value);
+        }
+        set_time(&mut bus, 100);
+        latch_rtc(&mut bus);
+        assert_eq!(read_rtc(&mut bus, 0x08), 59);
+
+        // Resume, the day counter overflows and sets the carry bit
+        bus.write_byte(0x4000, 0x0C);
+        bus.write_byte(0xA000, 0b0000_0001);
+        set_time(&mut bus, 101);
+        latch_rtc(&mut bus);
+        assert_eq!(read_rtc(&mut bus, 0x08), 0);
+        assert_eq!(read_rtc(&mut bus, 0x09), 0);
+        assert_eq!(read_rtc(&mut bus, 0x0A), 0);
+        assert_eq!(read_rtc(&mut bus, 0x0B), 0);
+        assert_eq!(read_rtc(&mut bus, 0x0C) & 0b1100_0001, 0b1000_0000);
+
+        // Disabled RTC reads as open bus
+        bus.write_byte(0x0000, 0x00);
+        assert_eq!(read_rtc(&mut bus, 0x08), 0xFF);
+    }
+
+    #[test]
+    fn mbc5_banking() {
+        // 8 MiB ROM, 512 banks, 128 KiB RAM
+        let mut bus = load(&build_banked_cartridge(0x1B, 0x08, 0x04));
+        assert_eq!(bus.external_ram.len(), 16);
+
+        bus.write_byte(0x2000, 0x00);
+        assert_eq!(bus.read_byte(0x4000), 0);
+        bus.write_byte(0x2000, 0x42);
+        bus.write_byte(0x3000, 0x01);
+        // 0x142, only the low byte is stored in the bank
+        assert_eq!(bus.read_byte(0x4000), 0x42);
+        assert_eq!(bus.mbc.rom_bank_high(), 0x142);
+
+        bus.write_byte(0x0000, 0x0A);
+        bus.write_byte(0x4000, 0x0F);
+        bus.write_byte(0xA000, 0x24);
+        assert_eq!(bus.external_ram[15][0], 0x24);
+    }
+
+    #[test]
+    fn mbc5_rumble() {
+        let mut bus = load(&build_banked_cartridge(0x1E, 0x02, 0x03));
+        bus.write_byte(0x0000, 0x0A);
+        bus.write_byte(0x4000, 0b0000_1010);
+        bus.write_byte(0xA000, 0x24);
+        assert_eq!(bus.external_ram[2][0], 0x24);
+        match bus.mbc {
+            MemoryBankController::MBC5(mbc) => assert!(mbc.rumble),
+            mbc => panic!("Unexpected controller {:?}", mbc),
+        }
+    }
+
+    #[test]
+    fn mbc2_banking_and_ram() {
+        let mut bus = load(&build_banked_cartridge(0x06, 0x03, 0x00));
+        assert_eq!(bus.external_ram.len(), 1);
+
+        // Address bit 8 set selects the ROM bank register
+        bus.write_byte(0x2100, 0x0B);
+        assert_eq!(bus.read_byte(0x4000), 0x0B);
+        bus.write_byte(0x0100, 0x00);
+        assert_eq!(bus.read_byte(0x4000), 0x01);
+
+        bus.write_byte(0xA000, 0x05);
+        assert_eq!(bus.read_byte(0xA000), 0xFF);
+
+        bus.write_byte(0x0000, 0x0A);
+        bus.write_byte(0xA001, 0x35);
+        assert_eq!(bus.read_byte(0xA001), 0xF5);
+        // 512 half bytes mirrored over the whole area
+        assert_eq!(bus.read_byte(0xA201), 0xF5);
+        assert_eq!(bus.read_byte(0xBE01), 0xF5);
+    }
+
+    #[test]
+    fn rom_ram() {
+        let mut bus = load(&build_banked_cartridge(0x09, 0x00, 0x02));
+        bus.write_byte(0x2000, 0x05);
+        assert_eq!(bus.read_byte(0x4000), 1);
+        bus.write_byte(0xA000, 0x24);
+        assert_eq!(bus.read_byte(0xA000), 0x24);
+    }
+
+    #[test]
+    fn unsupported_mapper() {
+        let err = Cartridge::new(build_rom(0x22, 0x00, 0x00)).unwrap_err();
+        assert_eq!(err, CartridgeError::UnsupportedMapper(0x22));
+        assert_eq!(err.to_string(), "unsupported mapper 0x22");
+    }
+
+    #[test]
+    fn save_rtc_footer() {
+        let cartridge = build_banked_cartridge(0x10, 0x02, 0x03);
+        let save_file = SaveFile::new("test.sav", &cartridge).unwrap();
+        let mut bus = load_mbc3(&cartridge);
+        bus.write_byte(0x0000, 0x0A);
+        bus.write_byte(0x4000, 0x03);
+        bus.write_byte(0xA010, 0x42);
+        set_time(&mut bus, 3600 + 2);
+        latch_rtc(&mut bus);
+
+        let data = save_file.encode(&bus);
+        assert_eq!(data.len(), 0x8000 + RTC_FOOTER_SIZE);
+        assert_eq!(data[3 * 0x2000 + 0x10], 0x42);
+        // Current seconds and hours, then latched seconds
+        assert_eq!(data[0x8000..0x8004], [2, 0, 0, 0]);
+        assert_eq!(data[0x8008..0x800C], [1, 0, 0, 0]);
+        assert_eq!(data[0x8014..0x8018], [2, 0, 0, 0]);
+        assert_eq!(data[0x8028..0x8030], 3602u64.to_le_bytes());
+
+        // Restored 60 seconds later
+        let mut restored = load_mbc3(&cartridge);
+        set_time(&mut restored, 3662);
+        save_file.decode(&mut restored, &data);
+        assert_eq!(restored.external_ram[3][0x10], 0x42);
+        restored.write_byte(0x0000, 0x0A);
+        latch_rtc(&mut restored);
+        assert_eq!(read_rtc(&mut restored, 0x08), 2);
+        assert_eq!(read_rtc(&mut restored, 0x09), 1);
+        assert_eq!(read_rtc(&mut restored, 0x0A), 1);
+    }
+
+    #[test]
+    fn save_file_flush_and_load() {
+        let cartridge = build_banked_cartridge(0x03, 0x02, 0x02);
+        let path = std::env::temp_dir().join(format!("gb-save-test-{}.sav", std::process::id()));
+        let mut save_file = SaveFile::new(&path, &cartridge).unwrap();
+
+        let mut bus = load(&cartridge);
+        assert!(!save_file.load(&mut bus).unwrap());
+        bus.write_byte(0x0000, 0x0A);
+        bus.write_byte(0xA123, 0x99);
+        save_file.flush(&bus).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap().len(), 0x2000);
+
+        let mut restored = load(&cartridge);
+        let mut save_file = SaveFile::new(&path, &cartridge).unwrap();
+        assert!(save_file.load(&mut restored).unwrap());
+        assert_eq!(restored.external_ram[0][0x123], 0x99);
+        std::fs::remove_file(&path).unwrap();
+
+        // No battery, no save
+        assert!(SaveFile::new(&path, &build_banked_cartridge(0x02, 0x02, 0x02)).is_none());
+    }
+
+    #[test]
+    fn save_file_flushes_rtc_changes() {
+        let cartridge = build_banked_cartridge(0x10, 0x02, 0x03);
+        let path =
+            std::env::temp_dir().join(format!("gb-rtc-save-test-{}.sav", std::process::id()));
+        let mut save_file = SaveFile::new(&path, &cartridge).unwrap();
+        let mut bus = load_mbc3(&cartridge);
+        bus.write_byte(0x0000, 0x0A);
+        // Halted, the clock only changes through the registers
+        bus.write_byte(0x4000, 0x0C);
+        bus.write_byte(0xA000, 0b0100_0000);
+        save_file.flush(&bus).unwrap();
+
+        // Only the minutes change, the RAM stays the same
+        bus.write_byte(0x4000, 0x09);
+        bus.write_byte(0xA000, 42);
+        save_file.flush(&bus).unwrap();
+
+        let mut restored = load_mbc3(&cartridge);
+        let mut save_file = SaveFile::new(&path, &cartridge).unwrap();
+        assert!(save_file.load(&mut restored).unwrap());
+        restored.write_byte(0x0000, 0x0A);
+        latch_rtc(&mut restored);
+        assert_eq!(read_rtc(&mut restored, 0x09), 42);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn save_file_skips_the_running_clock() {
+        let cartridge = build_banked_cartridge(0x10, 0x02, 0x03);
+        let path =
+            std::env::temp_dir().join(format!("gb-rtc-clock-save-test-{}.sav", std::process::id()));
+        let mut save_file = SaveFile::new(&path, &cartridge).unwrap();
+        let mut bus = load_mbc3(&cartridge);
+        bus.write_byte(0x0000, 0x0A);
+        save_file.flush(&bus).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Only the live registers move, across a minute
+        set_time(&mut bus, 75);
+        save_file.flush(&bus).unwrap();
+        assert!(!path.exists());
+
+        // Latching changes what the game reads back
+        latch_rtc(&mut bus);
+        save_file.flush(&bus).unwrap();
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+
+        set_time(&mut bus, 200);
+        bus.write_byte(0x4000, 0x00);
+        bus.write_byte(0xA000, 0x42);
+        save_file.flush(&bus).unwrap();
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+use super::CartridgeError;
+
+pub const HEADER_START: usize = 0x0100;
+pub const HEADER_END: usize = 0x0150;
+
+const TITLE_START: usize = 0x0134;
+const TITLE_END: usize = 0x0144;
+const CGB_FLAG: usize = 0x0143;
+const NEW_LICENSEE_CODE: usize = 0x0144;
+const SGB_FLAG: usize = 0x0146;
+const CARTRIDGE_TYPE: usize = 0x0147;
+const ROM_SIZE: usize = 0x0148;
+const RAM_SIZE: usize = 0x0149;
+const DESTINATION_CODE: usize = 0x014A;
+const OLD_LICENSEE_CODE: usize = 0x014B;
+const ROM_VERSION: usize = 0x014C;
+const HEADER_CHECKSUM: usize = 0x014D;
+const GLOBAL_CHECKSUM: usize = 0x014E;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum CGBFlag {
+    // Monochrome only cartridge
+    #[default]
+    None,
+    // 0x80: Color enhanced, still runs on monochrome hardware
+    Supported,
+    // 0xC0: Color only
+    Only,
+}
+
+impl std::convert::From<u8> for CGBFlag {
+    fn from(value: u8) -> Self {
+        match value {
+            0x80 => CGBFlag::Supported,
+            0xC0 => CGBFlag::Only,
+            _ => CGBFlag::None,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum Destination {
+    #[default]
+    Japan,
+    Overseas,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum CartridgeType {
+    #[default]
+    RomOnly,
+    MBC1,
+    MBC1Ram,
+    MBC1RamBattery,
+    MBC2,
+    MBC2Battery,
+    RomRam,
+    RomRamBattery,
+    MMM01,
+    MMM01Ram,
+    MMM01RamBattery,
+    MBC3TimerBattery,
+    MBC3TimerRamBattery,
+    MBC3,
+    MBC3Ram,
+    MBC3RamBattery,
+    MBC5,
+    MBC5Ram,
+    MBC5RamBattery,
+    MBC5Rumble,
+    MBC5RumbleRam,
+    MBC5RumbleRamBattery,
+    MBC6,
+    MBC7SensorRumbleRamBattery,
+    PocketCamera,
+    BandaiTama5,
+    HuC3,
+    HuC1RamBattery,
+    Unknown(u8),
+}
+
+impl std::convert::From<u8> for CartridgeType {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => CartridgeType::RomOnly,
+            0x01 => CartridgeType::MBC1,
+            0x02 => CartridgeType::MBC1Ram,
+            0x03 => CartridgeType::MBC1RamBattery,
+            0x05 => CartridgeType::MBC2,
+            0x06 => CartridgeType::MBC2Battery,
+            0x08 => CartridgeType::RomRam,
+            0x09 => CartridgeType::RomRamBattery,
+            0x0B => CartridgeType::MMM01,
+            0x0C => CartridgeType::MMM01Ram,
+            0x0D => CartridgeType::MMM01RamBattery,
+            0x0F => CartridgeType::MBC3TimerBattery,
+            0x10 => CartridgeType::MBC3TimerRamBattery,
+            0x11 => CartridgeType::MBC3,
+            0x12 => CartridgeType::MBC3Ram,
+            0x13 => CartridgeType::MBC3RamBattery,
+            0x19 => CartridgeType::MBC5,
+            0x1A => CartridgeType::MBC5Ram,
+            0x1B => CartridgeType::MBC5RamBattery,
+            0x1C => CartridgeType::MBC5Rumble,
+            0x1D => CartridgeType::MBC5RumbleRam,
+            0x1E => CartridgeType::MBC5RumbleRamBattery,
+            0x20 => CartridgeType::MBC6,
+            0x22 => CartridgeType::MBC7SensorRumbleRamBattery,
+            0xFC => CartridgeType::PocketCamera,
+            0xFD => CartridgeType::BandaiTama5,
+            0xFE => CartridgeType::HuC3,
+            0xFF => CartridgeType::HuC1RamBattery,
+            value => CartridgeType::Unknown(value),
+        }
+    }
+}
+
+impl std::convert::From<CartridgeType> for u8 {
+    fn from(value: CartridgeType) -> Self {
+        match value {
+            CartridgeType::RomOnly => 0x00,
+            CartridgeType::MBC1 => 0x01,
+            CartridgeType::MBC1Ram => 0x02,
+            CartridgeType::MBC1RamBattery => 0x03,
+            CartridgeType::MBC2 => 0x05,
+            CartridgeType::MBC2Battery => 0x06,
+            CartridgeType::RomRam => 0x08,
+            CartridgeType::RomRamBattery => 0x09,
+            CartridgeType::MMM01 => 0x0B,
+            CartridgeType::MMM01Ram => 0x0C,
+            CartridgeType::MMM01RamBattery => 0x0D,
+            CartridgeType::MBC3TimerBattery => 0x0F,
+            CartridgeType::MBC3TimerRamBattery => 0x10,
+            CartridgeType::MBC3 => 0x11,
+            CartridgeType::MBC3Ram => 0x12,
+            CartridgeType::MBC3RamBattery => 0x13,
+            CartridgeType::MBC5 => 0x19,
+            CartridgeType::MBC5Ram => 0x1A,
+            CartridgeType::MBC5RamBattery => 0x1B,
+            CartridgeType::MBC5Rumble => 0x1C,
+            CartridgeType::MBC5RumbleRam => 0x1D,
+            CartridgeType::MBC5RumbleRamBattery => 0x1E,
+            CartridgeType::MBC6 => 0x20,
+            CartridgeType::MBC7SensorRumbleRamBattery => 0x22,
+            CartridgeType::PocketCamera => 0xFC,
+            CartridgeType::BandaiTama5 => 0xFD,
+            CartridgeType::HuC3 => 0xFE,
+            CartridgeType::HuC1RamBattery => 0xFF,
+            CartridgeType::Unknown(value) => value,
+        }
+    }
+}
+
+impl CartridgeType {
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self,
+            CartridgeType::MBC1RamBattery
+                | CartridgeType::MBC2Battery
+                | CartridgeType::RomRamBattery
+                | CartridgeType::MMM01RamBattery
+                | CartridgeType::MBC3TimerBattery
+                | CartridgeType::MBC3TimerRamBattery
+                | CartridgeType::MBC3RamBattery
+                | CartridgeType::MBC5RamBattery
+                | CartridgeType::MBC5RumbleRamBattery
+                | CartridgeType::MBC7SensorRumbleRamBattery
+                | CartridgeType::HuC1RamBattery
+        )
+    }
+
+    pub fn has_timer(&self) -> bool {
+        matches!(
+            self,
+            CartridgeType::MBC3TimerBattery | CartridgeType::MBC3TimerRamBattery
+        )
+    }
+}
+
+/// Decoded cartridge header (0x0100-0x014F)
+/// See https://gbdev.io/pandocs/The_Cartridge_Header.html
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub cgb_flag: CGBFlag,
+    pub sgb_flag: bool,
+    pub cartridge_type: CartridgeType,
+    pub rom_size_code: u8,
+    pub ram_size_code: u8,
+    pub destination: Destination,
+    pub old_licensee_code: u8,
+    pub new_licensee_code: [u8; 2],
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    /// Parse the header of a ROM image, validating the size codes and the header checksum
+    pub fn parse(rom: &[u8]) -> Result<Self, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::Truncated(rom.len()));
+        }
+
+        let cgb_flag = CGBFlag::from(rom[CGB_FLAG]);
+        // On CGB cartridges the last title byte is the CGB flag
+        let title_end = if rom[CGB_FLAG] & 0x80 != 0 {
+            CGB_FLAG
+        } else {
+            TITLE_END
+        };
+        let title = rom[TITLE_START..title_end]
+            .iter()
+            .take_while(|byte| **byte != 0)
+            .map(|byte| {
+                if byte.is_ascii_graphic() || *byte == b' ' {
+                    *byte as char
+                } else {
+                    '?'
+                }
+            })
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+
+        let header = CartridgeHeader {
+            title,
+            cgb_flag,
+            sgb_flag: rom[SGB_FLAG] == 0x03,
+            cartridge_type: rom[CARTRIDGE_TYPE].into(),
+            rom_size_code: rom[ROM_SIZE],
+            ram_size_code: rom[RAM_SIZE],
+            destination: if rom[DESTINATION_CODE] == 0x00 {
+                Destination::Japan
+            } else {
+                Destination::Overseas
+            },
+            old_licensee_code: rom[OLD_LICENSEE_CODE],
+            new_licensee_code: [rom[NEW_LICENSEE_CODE], rom[NEW_LICENSEE_CODE + 1]],
+            version: rom[ROM_VERSION],
+            header_checksum: rom[HEADER_CHECKSUM],
+            global_checksum: u16::from_be_bytes([rom[GLOBAL_CHECKSUM], rom[GLOBAL_CHECKSUM + 1]]),
+        };
+
+        header.rom_size()?;
+        header.ram_size()?;
+
+        let computed = Self::compute_header_checksum(rom);
+        if computed != header.header_checksum {
+            return Err(CartridgeError::HeaderChecksum {
+                expected: header.header_checksum,
+                computed,
+            });
+        }
+
+        Ok(header)
+    }
+
+    /// Checksum over 0x0134-0x014C, verified by the boot ROM
+    pub fn compute_header_checksum(rom: &[u8]) -> u8 {
+        rom[TITLE_START..HEADER_CHECKSUM]
+            .iter()
+            .fold(0u8, |checksum, byte| {
+                checksum.wrapping_sub(*byte).wrapping_sub(1)
+            })
+    }
+
+    /// Sum of every byte of the ROM except the two global checksum bytes
+    pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter()
+            .enumerate()
+            .filter(|(i, _)| *i != GLOBAL_CHECKSUM && *i != GLOBAL_CHECKSUM + 1)
+            .fold(0u16, |checksum, (_, byte)| {
+                checksum.wrapping_add(*byte as u16)
+            })
+    }
+
+    /// ROM size in bytes
+    pub fn rom_size(&self) -> Result<usize, CartridgeError> {
+        match self.rom_size_code {
+            0x00..=0x08 => Ok(0x8000 << self.rom_size_code),
+            code => Err(CartridgeError::InvalidRomSize(code)),
+        }
+    }
+
+    /// Number of 16 KiB ROM banks
+    pub fn rom_banks(&self) -> Result<usize, CartridgeError> {
+        Ok(self.rom_size()? / 0x4000)
+    }
+
+    /// External RAM size in bytes
+    pub fn ram_size(&self) -> Result<usize, CartridgeError> {
+        match self.ram_size_code {
+            0x00 => Ok(0),
+            // Unused, but some homebrew report 2 KiB
+            0x01 => Ok(0x800),
+            0x02 => Ok(0x2000),
+            0x03 => Ok(0x8000),
+            0x04 => Ok(0x20000),
+            0x05 => Ok(0x10000),
+            code => Err(CartridgeError::InvalidRamSize(code)),
+        }
+    }
+
+    /// Licensee code, the new two characters code is used when the old code is 0x33
+    pub fn licensee(&self) -> String {
+        if self.old_licensee_code == 0x33 {
+            String::from_utf8_lossy(&self.new_licensee_code).to_string()
+        } else {
+            format!("{:02X}", self.old_licensee_code)
+        }
+    }
+}
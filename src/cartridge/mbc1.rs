@@ -0,0 +1,78 @@
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum BankingMode {
+    // 0000–3FFF and A000–BFFF locked to bank 0
+    #[default]
+    Simple = 0,
+    // 0000–3FFF and A000–BFFF switched by the upper bank bits
+    Advanced = 1,
+}
+
+/// MBC1 memory bank controller
+/// See https://gbdev.io/pandocs/MBC1.html
+#[derive(Default, Debug, Clone, Copy)]
+pub struct MBC1 {
+    pub ram_enable: bool,
+    // 5 bits register (2000–3FFF)
+    pub rom_bank: u8,
+    // 2 bits register (4000–5FFF), upper ROM bank bits or RAM bank
+    pub upper_bank: u8,
+    pub banking_mode: BankingMode,
+}
+
+impl MBC1 {
+    pub fn new() -> Self {
+        Self {
+            rom_bank: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enable = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // Bank 0 can't be selected in the switchable area, 0x20/0x40/0x60 map to
+                // 0x21/0x41/0x61 since only the lower 5 bits are checked
+                self.rom_bank = value & 0b0001_1111;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.upper_bank = value & 0b0000_0011,
+            0x6000..=0x7FFF => {
+                self.banking_mode = if value & 0b0000_0001 != 0 {
+                    BankingMode::Advanced
+                } else {
+                    BankingMode::Simple
+                }
+            }
+            // RAM disabled
+            0xA000..=0xBFFF => (),
+            _ => panic!("Invalid write to MBC1 address: {:04X}", address),
+        }
+    }
+
+    /// ROM bank mapped at 0000–3FFF
+    pub fn rom_bank_low(&self) -> usize {
+        match self.banking_mode {
+            BankingMode::Simple => 0,
+            BankingMode::Advanced => (self.upper_bank as usize) << 5,
+        }
+    }
+
+    /// ROM bank mapped at 4000–7FFF
+    pub fn rom_bank_high(&self) -> usize {
+        (self.upper_bank as usize) << 5 | self.rom_bank as usize
+    }
+
+    /// RAM bank mapped at A000–BFFF, None when RAM is disabled
+    pub fn ram_bank(&self) -> Option<usize> {
+        if !self.ram_enable {
+            return None;
+        }
+        match self.banking_mode {
+            BankingMode::Simple => Some(0),
+            BankingMode::Advanced => Some(self.upper_bank as usize),
+        }
+    }
+}
@@ -0,0 +1,42 @@
+/// MBC2 memory bank controller, with 512×4 bits of built-in RAM
+/// See https://gbdev.io/pandocs/MBC2.html
+#[derive(Default, Debug, Clone, Copy)]
+pub struct MBC2 {
+    pub ram_enable: bool,
+    // 4 bits register
+    pub rom_bank: u8,
+}
+
+impl MBC2 {
+    pub fn new() -> Self {
+        Self {
+            rom_bank: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            // Bit 8 of the address selects between the RAM enable and the ROM bank registers
+            0x0000..=0x3FFF => {
+                if address & 0x0100 == 0 {
+                    self.ram_enable = value & 0x0F == 0x0A;
+                } else {
+                    self.rom_bank = value & 0b0000_1111;
+                    if self.rom_bank == 0 {
+                        self.rom_bank = 1;
+                    }
+                }
+            }
+            0x4000..=0x7FFF => (),
+            // RAM disabled
+            0xA000..=0xBFFF => (),
+            _ => panic!("Invalid write to MBC2 address: {:04X}", address),
+        }
+    }
+
+    /// ROM bank mapped at 4000–7FFF
+    pub fn rom_bank_high(&self) -> usize {
+        self.rom_bank as usize
+    }
+}
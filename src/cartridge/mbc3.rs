@@ -0,0 +1,208 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum TimeSource {
+    // Host wall clock
+    #[default]
+    Host,
+    // Fixed unix timestamp in seconds, moved forward by hand for deterministic runs
+    Manual(u64),
+}
+
+impl TimeSource {
+    /// Current unix timestamp in seconds
+    pub fn now(&self) -> u64 {
+        match self {
+            TimeSource::Host => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+            TimeSource::Manual(timestamp) => *timestamp,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct RtcRegisters {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub day_low: u8,
+    // Bit 0: day counter bit 8, bit 6: halt, bit 7: day counter carry
+    pub day_high: u8,
+}
+
+impl RtcRegisters {
+    pub fn halted(&self) -> bool {
+        self.day_high & 0b0100_0000 != 0
+    }
+
+    pub fn days(&self) -> u16 {
+        (self.day_high as u16 & 0b1) << 8 | self.day_low as u16
+    }
+
+    fn set_days(&mut self, days: u64) {
+        if days > 0x1FF {
+            self.day_high |= 0b1000_0000;
+        }
+        let days = days % 0x200;
+        self.day_low = days as u8;
+        self.day_high = (self.day_high & 0b1111_1110) | (days >> 8) as u8;
+    }
+
+    /// Advance the clock by `seconds`, wrapping the day counter and setting the carry bit
+    pub fn advance(&mut self, seconds: u64) {
+        if seconds == 0 {
+            return;
+        }
+        let seconds = (self.seconds & 0b0011_1111) as u64 + seconds;
+        let minutes = (self.minutes & 0b0011_1111) as u64 + seconds / 60;
+        let hours = (self.hours & 0b0001_1111) as u64 + minutes / 60;
+        let days = self.days() as u64 + hours / 24;
+
+        self.seconds = (seconds % 60) as u8;
+        self.minutes = (minutes % 60) as u8;
+        self.hours = (hours % 24) as u8;
+        self.set_days(days);
+    }
+
+    pub fn read_byte(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.day_low,
+            0x0C => self.day_high | 0b0011_1110,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_byte(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => self.seconds = value & 0b0011_1111,
+            0x09 => self.minutes = value & 0b0011_1111,
+            0x0A => self.hours = value & 0b0001_1111,
+            0x0B => self.day_low = value,
+            0x0C => self.day_high = value & 0b1100_0001,
+            _ => (),
+        }
+    }
+}
+
+/// MBC3 real time clock, the counters are brought up to date lazily from the time source
+#[derive(Default, Debug, Clone, Copy)]
+pub struct RealTimeClock {
+    pub time_source: TimeSource,
+    pub registers: RtcRegisters,
+    pub latched: RtcRegisters,
+    // Unix timestamp the registers were last brought up to date
+    pub last_update: u64,
+}
+
+impl RealTimeClock {
+    pub fn new(time_source: TimeSource) -> Self {
+        Self {
+            time_source,
+            last_update: time_source.now(),
+            ..Default::default()
+        }
+    }
+
+    pub fn update(&mut self) {
+        let now = self.time_source.now();
+        if !self.registers.halted() {
+            self.registers.advance(now.saturating_sub(self.last_update));
+        }
+        // A clock going backwards doesn't count the same seconds twice
+        self.last_update = self.last_update.max(now);
+    }
+
+    pub fn latch(&mut self) {
+        self.update();
+        self.latched = self.registers;
+    }
+
+    pub fn read_byte(&self, register: u8) -> u8 {
+        self.latched.read_byte(register)
+    }
+
+    pub fn write_byte(&mut self, register: u8, value: u8) {
+        self.update();
+        self.registers.write_byte(register, value);
+    }
+}
+
+/// MBC3 memory bank controller with real time clock
+/// See https://gbdev.io/pandocs/MBC3.html
+#[derive(Default, Debug, Clone, Copy)]
+pub struct MBC3 {
+    pub ram_timer_enable: bool,
+    // 7 bits register (2000–3FFF)
+    pub rom_bank: u8,
+    // 4000–5FFF: 00–03 selects a RAM bank, 08–0C a RTC register
+    pub ram_rtc_select: u8,
+    // Last value written to 6000–7FFF, latching happens on a 00 -> 01 sequence
+    pub latch: u8,
+    pub rtc: RealTimeClock,
+}
+
+impl MBC3 {
+    pub fn new(time_source: TimeSource) -> Self {
+        Self {
+            rom_bank: 1,
+            latch: 0xFF,
+            rtc: RealTimeClock::new(time_source),
+            ..Default::default()
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_timer_enable = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.rom_bank = value & 0b0111_1111;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.ram_rtc_select = value,
+            0x6000..=0x7FFF => {
+                if self.latch == 0x00 && value == 0x01 {
+                    self.rtc.latch();
+                }
+                self.latch = value;
+            }
+            0xA000..=0xBFFF => {
+                if self.ram_timer_enable {
+                    self.rtc.write_byte(self.ram_rtc_select, value);
+                }
+            }
+            _ => panic!("Invalid write to MBC3 address: {:04X}", address),
+        }
+    }
+
+    /// RTC register reads through A000–BFFF
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0xA000..=0xBFFF if self.ram_timer_enable => self.rtc.read_byte(self.ram_rtc_select),
+            _ => 0xFF,
+        }
+    }
+
+    /// ROM bank mapped at 4000–7FFF
+    pub fn rom_bank_high(&self) -> usize {
+        self.rom_bank as usize
+    }
+
+    /// RAM bank mapped at A000–BFFF, None when RAM is disabled or a RTC register is selected
+    pub fn ram_bank(&self) -> Option<usize> {
+        match self.ram_rtc_select {
+            0x00..=0x03 if self.ram_timer_enable => Some(self.ram_rtc_select as usize),
+            _ => None,
+        }
+    }
+
+    pub fn rtc_selected(&self) -> bool {
+        self.ram_timer_enable && (0x08..=0x0C).contains(&self.ram_rtc_select)
+    }
+}
@@ -0,0 +1,59 @@
+/// MBC5 memory bank controller
+/// See https://gbdev.io/pandocs/MBC5.html
+#[derive(Default, Debug, Clone, Copy)]
+pub struct MBC5 {
+    pub ram_enable: bool,
+    // 9 bits register, low byte at 2000–2FFF and bit 8 at 3000–3FFF
+    pub rom_bank: u16,
+    // 4 bits register (4000–5FFF)
+    pub ram_bank: u8,
+    // Rumble carts use bit 3 of the RAM bank register to drive the motor
+    pub has_rumble: bool,
+    pub rumble: bool,
+}
+
+impl MBC5 {
+    pub fn new(has_rumble: bool) -> Self {
+        Self {
+            rom_bank: 1,
+            has_rumble,
+            ..Default::default()
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enable = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0x0FF) | ((value as u16 & 0b1) << 8)
+            }
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.rumble = value & 0b0000_1000 != 0;
+                    self.ram_bank = value & 0b0000_0111;
+                } else {
+                    self.ram_bank = value & 0b0000_1111;
+                }
+            }
+            0x6000..=0x7FFF => (),
+            // RAM disabled
+            0xA000..=0xBFFF => (),
+            _ => panic!("Invalid write to MBC5 address: {:04X}", address),
+        }
+    }
+
+    /// ROM bank mapped at 4000–7FFF, unlike the other controllers bank 0 can be selected
+    pub fn rom_bank_high(&self) -> usize {
+        self.rom_bank as usize
+    }
+
+    /// RAM bank mapped at A000–BFFF, None when RAM is disabled
+    pub fn ram_bank(&self) -> Option<usize> {
+        if self.ram_enable {
+            Some(self.ram_bank as usize)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use std::fmt;
+
+use crate::{
+    bus::Memory,
+    save_state::{SaveStateError, Snapshot, StateReader, StateWriter},
+};
+
+use self::{
+    header::{CartridgeHeader, CartridgeType},
+    mbc1::{BankingMode, MBC1},
+    mbc2::MBC2,
+    mbc3::{RealTimeClock, RtcRegisters, TimeSource, MBC3},
+    mbc5::MBC5,
+};
+
+mod cartridge_test;
+pub mod header;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod save;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CartridgeError {
+    // The image is too small to contain a header
+    Truncated(usize),
+    // The image size does not match the header ROM size
+    SizeMismatch { expected: usize, actual: usize },
+    InvalidRomSize(u8),
+    InvalidRamSize(u8),
+    HeaderChecksum { expected: u8, computed: u8 },
+    GlobalChecksum { expected: u16, computed: u16 },
+    // Cartridge type byte of a controller we don't emulate
+    UnsupportedMapper(u8),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::Truncated(size) => {
+                write!(
+                    f,
+                    "truncated ROM image ({} bytes), no cartridge header",
+                    size
+                )
+            }
+            CartridgeError::SizeMismatch { expected, actual } => write!(
+                f,
+                "ROM image is {} bytes but the header declares {} bytes",
+                actual, expected
+            ),
+            CartridgeError::InvalidRomSize(code) => {
+                write!(f, "invalid ROM size code {:#04X}", code)
+            }
+            CartridgeError::InvalidRamSize(code) => {
+                write!(f, "invalid RAM size code {:#04X}", code)
+            }
+            CartridgeError::HeaderChecksum { expected, computed } => write!(
+                f,
+                "header checksum mismatch (expected {:#04X}, computed {:#04X})",
+                expected, computed
+            ),
+            CartridgeError::GlobalChecksum { expected, computed } => write!(
+                f,
+                "global checksum mismatch (expected {:#06X}, computed {:#06X})",
+                expected, computed
+            ),
+            CartridgeError::UnsupportedMapper(cartridge_type) => {
+                write!(f, "unsupported mapper {:#04X}", cartridge_type)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+#[derive(Debug, Clone)]
+pub struct Cartridge {
+    pub header: CartridgeHeader,
+    pub mbc: MemoryBankController,
+    pub rom: Vec<u8>,
+}
+
+impl Cartridge {
+    /// Parse and validate a ROM image
+    pub fn new(rom: Vec<u8>) -> Result<Self, CartridgeError> {
+        let header = CartridgeHeader::parse(&rom)?;
+
+        let expected = header.rom_size()?;
+        if rom.len() != expected {
+            return Err(CartridgeError::SizeMismatch {
+                expected,
+                actual: rom.len(),
+            });
+        }
+
+        let computed = CartridgeHeader::compute_global_checksum(&rom);
+        if computed != header.global_checksum {
+            return Err(CartridgeError::GlobalChecksum {
+                expected: header.global_checksum,
+                computed,
+            });
+        }
+
+        let mbc = MemoryBankController::new(&header)?;
+
+        Ok(Self { header, mbc, rom })
+    }
+
+    /// External RAM size in bytes, including RAM built into the controller
+    pub fn ram_size(&self) -> usize {
+        match self.mbc {
+            MemoryBankController::MBC2(_) => 0x200,
+            _ => self.header.ram_size().unwrap_or(0),
+        }
+    }
+}
+
+impl fmt::Display for Cartridge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = &self.header;
+        write!(
+            f,
+            "\"{}\" type: {:?} ({:#04X}) ROM: {} KiB RAM: {} KiB CGB: {:?} SGB: {} licensee: {} destination: {:?} version: {}",
+            header.title,
+            header.cartridge_type,
+            u8::from(header.cartridge_type),
+            header.rom_size().unwrap_or(0) / 1024,
+            header.ram_size().unwrap_or(0) / 1024,
+            header.cgb_flag,
+            header.sgb_flag,
+            header.licensee(),
+            header.destination,
+            header.version,
+        )
+    }
+}
+
+/// What the controller maps at A000–BFFF
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RamMapping {
+    Disabled,
+    Bank(usize),
+    // MBC2 built-in 512×4 bits RAM, mirrored over the whole area
+    Nibbles,
+    // Controller register, read and written through the controller itself
+    Register,
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub enum MemoryBankController {
+    // ROM only, optionally with up to 8 KiB of RAM
+    #[default]
+    None,
+    MBC1(MBC1),
+    MBC2(MBC2),
+    MBC3(MBC3),
+    MBC5(MBC5),
+}
+
+impl MemoryBankController {
+    pub fn new(header: &CartridgeHeader) -> Result<Self, CartridgeError> {
+        Self::with_time_source(header, TimeSource::Host)
+    }
+
+    /// Controller for `header`, clocks run from `time_source`
+    pub fn with_time_source(
+        header: &CartridgeHeader,
+        time_source: TimeSource,
+    ) -> Result<Self, CartridgeError> {
+        let mbc = match header.cartridge_type {
+            CartridgeType::RomOnly | CartridgeType::RomRam | CartridgeType::RomRamBattery => {
+                MemoryBankController::None
+            }
+            CartridgeType::MBC1 | CartridgeType::MBC1Ram | CartridgeType::MBC1RamBattery => {
+                MemoryBankController::MBC1(MBC1::new())
+            }
+            CartridgeType::MBC2 | CartridgeType::MBC2Battery => {
+                MemoryBankController::MBC2(MBC2::new())
+            }
+            CartridgeType::MBC3TimerBattery
+            | CartridgeType::MBC3TimerRamBattery
+            | CartridgeType::MBC3
+            | CartridgeType::MBC3Ram
+            | CartridgeType::MBC3RamBattery => MemoryBankController::MBC3(MBC3::new(time_source)),
+            CartridgeType::MBC5 | CartridgeType::MBC5Ram | CartridgeType::MBC5RamBattery => {
+                MemoryBankController::MBC5(MBC5::new(false))
+            }
+            CartridgeType::MBC5Rumble
+            | CartridgeType::MBC5RumbleRam
+            | CartridgeType::MBC5RumbleRamBattery => MemoryBankController::MBC5(MBC5::new(true)),
+            cartridge_type => return Err(CartridgeError::UnsupportedMapper(cartridge_type.into())),
+        };
+        Ok(mbc)
+    }
+
+    /// Real time clock of MBC3 cartridges
+    pub fn rtc_mut(&mut self) -> Option<&mut RealTimeClock> {
+        match self {
+            MemoryBankController::MBC3(mbc) => Some(&mut mbc.rtc),
+            _ => None,
+        }
+    }
+
+    /// ROM bank mapped at 0000–3FFF
+    pub fn rom_bank_low(&self) -> usize {
+        match self {
+            MemoryBankController::MBC1(mbc) => mbc.rom_bank_low(),
+            _ => 0,
+        }
+    }
+
+    /// ROM bank mapped at 4000–7FFF
+    pub fn rom_bank_high(&self) -> usize {
+        match self {
+            MemoryBankController::None => 1,
+            MemoryBankController::MBC1(mbc) => mbc.rom_bank_high(),
+            MemoryBankController::MBC2(mbc) => mbc.rom_bank_high(),
+            MemoryBankController::MBC3(mbc) => mbc.rom_bank_high(),
+            MemoryBankController::MBC5(mbc) => mbc.rom_bank_high(),
+        }
+    }
+
+    pub fn ram_mapping(&self) -> RamMapping {
+        let bank = match self {
+            MemoryBankController::None => Some(0),
+            MemoryBankController::MBC1(mbc) => mbc.ram_bank(),
+            MemoryBankController::MBC2(mbc) if mbc.ram_enable => return RamMapping::Nibbles,
+            MemoryBankController::MBC2(_) => None,
+            MemoryBankController::MBC3(mbc) if mbc.rtc_selected() => return RamMapping::Register,
+            MemoryBankController::MBC3(mbc) => mbc.ram_bank(),
+            MemoryBankController::MBC5(mbc) => mbc.ram_bank(),
+        };
+        match bank {
+            Some(bank) => RamMapping::Bank(bank),
+            None => RamMapping::Disabled,
+        }
+    }
+}
+
+impl Memory for MemoryBankController {
+    /// Controller registers mapped in A000–BFFF
+    fn read_byte(&self, address: u16) -> u8 {
+        match self {
+            MemoryBankController::MBC3(mbc) => mbc.read_byte(address),
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes to 0000–7FFF, and to A000–BFFF when mapped to a register
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match self {
+            MemoryBankController::None => (),
+            MemoryBankController::MBC1(mbc) => mbc.write_byte(address, value),
+            MemoryBankController::MBC2(mbc) => mbc.write_byte(address, value),
+            MemoryBankController::MBC3(mbc) => mbc.write_byte(address, value),
+            MemoryBankController::MBC5(mbc) => mbc.write_byte(address, value),
+        }
+    }
+}
+
+impl Snapshot for MemoryBankController {
+    fn save_state(&self, writer: &mut StateWriter) {
+        match self {
+            MemoryBankController::None => writer.write_u8(0),
+            MemoryBankController::MBC1(mbc) => {
+                writer.write_u8(1);
+                writer.write_bool(mbc.ram_enable);
+                writer.write_u8(mbc.rom_bank);
+                writer.write_u8(mbc.upper_bank);
+                writer.write_u8(mbc.banking_mode as u8);
+            }
+            MemoryBankController::MBC2(mbc) => {
+                writer.write_u8(2);
+                writer.write_bool(mbc.ram_enable);
+                writer.write_u8(mbc.rom_bank);
+            }
+            MemoryBankController::MBC3(mbc) => {
+                writer.write_u8(3);
+                writer.write_bool(mbc.ram_timer_enable);
+                writer.write_u8(mbc.rom_bank);
+                writer.write_u8(mbc.ram_rtc_select);
+                writer.write_u8(mbc.latch);
+                for registers in [mbc.rtc.registers, mbc.rtc.latched] {
+                    writer.write_u8(registers.seconds);
+                    writer.write_u8(registers.minutes);
+                    writer.write_u8(registers.hours);
+                    writer.write_u8(registers.day_low);
+                    writer.write_u8(registers.day_high);
+                }
+                writer.write_u64(mbc.rtc.last_update);
+            }
+            MemoryBankController::MBC5(mbc) => {
+                writer.write_u8(5);
+                writer.write_bool(mbc.ram_enable);
+                writer.write_u16(mbc.rom_bank);
+                writer.write_u8(mbc.ram_bank);
+                writer.write_bool(mbc.rumble);
+            }
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let kind = reader.read_u8()?;
+        match self {
+            MemoryBankController::None if kind == 0 => (),
+            MemoryBankController::MBC1(mbc) if kind == 1 => {
+                mbc.ram_enable = reader.read_bool()?;
+                mbc.rom_bank = reader.read_u8()?;
+                mbc.upper_bank = reader.read_u8()?;
+                mbc.banking_mode = match reader.read_u8()? {
+                    0 => BankingMode::Simple,
+                    _ => BankingMode::Advanced,
+                };
+            }
+            MemoryBankController::MBC2(mbc) if kind == 2 => {
+                mbc.ram_enable = reader.read_bool()?;
+                mbc.rom_bank = reader.read_u8()?;
+            }
+            MemoryBankController::MBC3(mbc) if kind == 3 => {
+                mbc.ram_timer_enable = reader.read_bool()?;
+                mbc.rom_bank = reader.read_u8()?;
+                mbc.ram_rtc_select = reader.read_u8()?;
+                mbc.latch = reader.read_u8()?;
+                for registers in [&mut mbc.rtc.registers, &mut mbc.rtc.latched] {
+                    *registers = RtcRegisters {
+                        seconds: reader.read_u8()?,
+                        minutes: reader.read_u8()?,
+                        hours: reader.read_u8()?,
+                        day_low: reader.read_u8()?,
+                        day_high: reader.read_u8()?,
+                    };
+                }
+                mbc.rtc.last_update = reader.read_u64()?;
+            }
+            MemoryBankController::MBC5(mbc) if kind == 5 => {
+                mbc.ram_enable = reader.read_bool()?;
+                mbc.rom_bank = reader.read_u16()?;
+                mbc.ram_bank = reader.read_u8()?;
+                mbc.rumble = reader.read_bool()?;
+            }
+            _ => return Err(SaveStateError::InvalidValue("memory bank controller")),
+        }
+        Ok(())
+    }
+}
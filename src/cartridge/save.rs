@@ -0,0 +1,166 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::bus::Bus;
+
+use super::{
+    mbc3::{RealTimeClock, RtcRegisters, TimeSource},
+    Cartridge, MemoryBankController,
+};
+
+// Current and latched RTC registers as 32 bits words, followed by a 64 bits unix timestamp
+pub const RTC_FOOTER_SIZE: usize = 48;
+// Older variant of the footer with a 32 bits timestamp
+pub const RTC_FOOTER_SIZE_LEGACY: usize = 44;
+
+/// Battery backed save RAM, stored as a raw dump of the external RAM
+/// followed by the RTC footer for MBC3 timer cartridges, like most other emulators do
+#[derive(Debug, Clone)]
+pub struct SaveFile {
+    pub path: PathBuf,
+    ram_size: usize,
+    has_rtc: bool,
+    // RAM and RTC registers last written to or read from disk, to skip flushes when
+    // nothing changed
+    last_saved: Option<Vec<u8>>,
+}
+
+impl SaveFile {
+    /// Save file for `cartridge` at `path`, None if the cartridge has no battery
+    pub fn new(path: impl Into<PathBuf>, cartridge: &Cartridge) -> Option<Self> {
+        if !cartridge.header.cartridge_type.has_battery() {
+            return None;
+        }
+        Some(Self {
+            path: path.into(),
+            ram_size: cartridge.ram_size(),
+            has_rtc: cartridge.header.cartridge_type.has_timer(),
+            last_saved: None,
+        })
+    }
+
+    /// `<rom>.sav` next to the ROM file
+    pub fn default_path(rom_path: impl AsRef<Path>) -> PathBuf {
+        rom_path.as_ref().with_extension("sav")
+    }
+
+    pub fn encode(&self, bus: &Bus) -> Vec<u8> {
+        let mut data: Vec<u8> = bus
+            .external_ram
+            .iter()
+            .flatten()
+            .copied()
+            .take(self.ram_size)
+            .collect();
+
+        if self.has_rtc {
+            if let MemoryBankController::MBC3(mbc) = &bus.mbc {
+                let mut rtc = mbc.rtc;
+                rtc.update();
+                Self::encode_clock(&rtc, &mut data);
+            }
+        }
+        data
+    }
+
+    pub fn decode(&self, bus: &mut Bus, data: &[u8]) {
+        let ram_size = self.ram_size.min(data.len());
+        for (i, byte) in data[..ram_size].iter().enumerate() {
+            bus.external_ram[i / 0x2000][i % 0x2000] = *byte;
+        }
+
+        let footer = &data[ram_size..];
+        if !self.has_rtc || footer.len() < RTC_FOOTER_SIZE_LEGACY {
+            return;
+        }
+        if let MemoryBankController::MBC3(mbc) = &mut bus.mbc {
+            mbc.rtc = Self::decode_clock(footer, mbc.rtc.time_source);
+            // Account for the time spent while the emulator was closed
+            mbc.rtc.update();
+        }
+    }
+
+    fn encode_clock(rtc: &RealTimeClock, data: &mut Vec<u8>) {
+        for registers in [rtc.registers, rtc.latched] {
+            for value in [
+                registers.seconds,
+                registers.minutes,
+                registers.hours,
+                registers.day_low,
+                registers.day_high,
+            ] {
+                data.extend_from_slice(&(value as u32).to_le_bytes());
+            }
+        }
+        data.extend_from_slice(&rtc.last_update.to_le_bytes());
+    }
+
+    fn decode_clock(footer: &[u8], time_source: TimeSource) -> RealTimeClock {
+        let word = |index: usize| footer[index * 4];
+        let registers = |offset: usize| RtcRegisters {
+            seconds: word(offset),
+            minutes: word(offset + 1),
+            hours: word(offset + 2),
+            day_low: word(offset + 3),
+            day_high: word(offset + 4),
+        };
+        let last_update = if footer.len() >= RTC_FOOTER_SIZE {
+            u64::from_le_bytes(footer[40..48].try_into().unwrap())
+        } else {
+            u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64
+        };
+        RealTimeClock {
+            time_source,
+            registers: registers(0),
+            latched: registers(5),
+            last_update,
+        }
+    }
+
+    /// Load the save file into `bus`, returns false if there is no save file yet
+    pub fn load(&mut self, bus: &mut Bus) -> io::Result<bool> {
+        match fs::read(&self.path) {
+            Ok(data) => {
+                self.decode(bus, &data);
+                self.last_saved = Some(self.encode(bus));
+                Ok(true)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Whether `data` matches the last flush, the running clock having moved forward
+    /// being no change: the live RTC registers tick every second
+    fn unchanged(&self, data: &[u8]) -> bool {
+        let Some(last_saved) = &self.last_saved else {
+            return false;
+        };
+        if data.len() != self.ram_size + RTC_FOOTER_SIZE || last_saved.len() != data.len() {
+            return last_saved == data;
+        }
+        let now = u64::from_le_bytes(data[data.len() - 8..].try_into().unwrap());
+        // The clock of the last flush, run up to the time of this one
+        let mut rtc = Self::decode_clock(&last_saved[self.ram_size..], TimeSource::Manual(now));
+        rtc.update();
+        let mut expected = last_saved[..self.ram_size].to_vec();
+        Self::encode_clock(&rtc, &mut expected);
+        expected == data
+    }
+
+    /// Write the save RAM and the RTC registers to disk if they changed since the last flush
+    pub fn flush(&mut self, bus: &Bus) -> io::Result<()> {
+        let data = self.encode(bus);
+        if self.unchanged(&data) {
+            return Ok(());
+        }
+        // Write then rename so a crash never leaves a truncated save behind
+        let tmp_path = self.path.with_extension("sav.tmp");
+        fs::write(&tmp_path, &data)?;
+        fs::rename(&tmp_path, &self.path)?;
+        self.last_saved = Some(data);
+        Ok(())
+    }
+}
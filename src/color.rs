@@ -0,0 +1,172 @@
+use std::{fmt, fs, path::Path};
+
+use crate::bus::lcd::Color;
+
+/// RGBA colors the four DMG shades are shown as, from white to black
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputPalette(pub [[u8; 4]; 4]);
+
+impl OutputPalette {
+    pub const GREY: Self = Self([
+        [0xFF, 0xFF, 0xFF, 0xFF],
+        [0xAA, 0xAA, 0xAA, 0xFF],
+        [0x55, 0x55, 0x55, 0xFF],
+        [0x00, 0x00, 0x00, 0xFF],
+    ]);
+    /// Green tinted screen of the original DMG
+    pub const GREEN: Self = Self([
+        [0x9B, 0xBC, 0x0F, 0xFF],
+        [0x8B, 0xAC, 0x0F, 0xFF],
+        [0x30, 0x62, 0x30, 0xFF],
+        [0x0F, 0x38, 0x0F, 0xFF],
+    ]);
+    /// Grey screen of the Game Boy Pocket
+    pub const POCKET: Self = Self([
+        [0xC4, 0xCF, 0xA1, 0xFF],
+        [0x8B, 0x95, 0x6D, 0xFF],
+        [0x4D, 0x53, 0x3C, 0xFF],
+        [0x1F, 0x1F, 0x1F, 0xFF],
+    ]);
+
+    pub fn rgba(&self, shade: Color) -> [u8; 4] {
+        self.0[shade as usize]
+    }
+}
+
+impl Default for OutputPalette {
+    fn default() -> Self {
+        Self::GREY
+    }
+}
+
+impl std::str::FromStr for OutputPalette {
+    type Err = String;
+
+    /// A preset name, or four comma separated hex colors from white to black
+    /// (`e0f8d0,88c070,346856,081820`, a leading `#` is allowed)
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "grey" | "gray" => return Ok(Self::GREY),
+            "green" => return Ok(Self::GREEN),
+            "pocket" => return Ok(Self::POCKET),
+            _ => (),
+        }
+
+        let colors = value.split(',').map(str::trim).collect::<Vec<_>>();
+        if colors.len() != 4 {
+            return Err(format!(
+                "invalid palette {}, expected green, pocket, grey or 4 hex colors",
+                value
+            ));
+        }
+        let mut palette = [[0xFF; 4]; 4];
+        for (rgba, color) in palette.iter_mut().zip(colors) {
+            let hex = color.strip_prefix('#').unwrap_or(color);
+            let rgb = match u32::from_str_radix(hex, 16) {
+                Ok(rgb) if hex.len() == 6 => rgb,
+                _ => return Err(format!("invalid hex color {}", color)),
+            };
+            rgba[..3].copy_from_slice(&rgb.to_be_bytes()[1..]);
+        }
+        Ok(Self(palette))
+    }
+}
+
+/// How CGB RGB555 colors are turned into RGBA
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum ColorCorrection {
+    // Channels scaled as is, saturated compared to the real screen
+    #[default]
+    None,
+    // Mimic the CGB LCD, colors bleed into each other and whites are dimmer
+    Cgb,
+}
+
+impl ColorCorrection {
+    pub fn rgba(&self, color: u16) -> [u8; 4] {
+        let r = (color & 0x1F) as u32;
+        let g = ((color >> 5) & 0x1F) as u32;
+        let b = ((color >> 10) & 0x1F) as u32;
+        match self {
+            // Scale the 5 bits channels to 8 bits, 0x1F gives 0xFF
+            ColorCorrection::None => {
+                let scale = |value: u32| (value << 3 | value >> 2) as u8;
+                [scale(r), scale(g), scale(b), 0xFF]
+            }
+            ColorCorrection::Cgb => {
+                let curve = |value: u32| (value.min(960) >> 2) as u8;
+                [
+                    curve(r * 26 + g * 4 + b * 2),
+                    curve(g * 24 + b * 8),
+                    curve(r * 6 + g * 4 + b * 22),
+                    0xFF,
+                ]
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    // Line number and reason
+    Invalid(usize, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "{}", err),
+            ConfigError::Invalid(line, reason) => write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Display settings read from a config file of `key = value` lines, `#` starts a
+/// comment line:
+///
+/// ```text
+/// palette = pocket
+/// color_correction = true
+/// ```
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct DisplayConfig {
+    pub palette: Option<OutputPalette>,
+    pub color_correction: Option<bool>,
+}
+
+impl DisplayConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let config = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Self::parse(&config)
+    }
+
+    pub fn parse(config: &str) -> Result<Self, ConfigError> {
+        let mut result = Self::default();
+        for (number, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: String| ConfigError::Invalid(number + 1, reason);
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(invalid(format!("expected key = value, got {}", line)));
+            };
+            let value = value.trim();
+            match key.trim() {
+                "palette" => result.palette = Some(value.parse().map_err(invalid)?),
+                "color_correction" => {
+                    result.color_correction = Some(match value {
+                        "true" | "on" => true,
+                        "false" | "off" => false,
+                        _ => return Err(invalid(format!("expected true or false, got {}", value))),
+                    })
+                }
+                key => return Err(invalid(format!("unknown key {}", key))),
+            }
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod color_tests {
+    use crate::{
+        bus::{lcd::Color, Bus, HardwareMode, Memory},
+        color::{ColorCorrection, ConfigError, DisplayConfig, OutputPalette},
+        ppu::PPU,
+    };
+
+    #[test]
+    fn palette_presets_and_hex_quads() {
+        assert_eq!("green".parse(), Ok(OutputPalette::GREEN));
+        assert_eq!("Pocket".parse(), Ok(OutputPalette::POCKET));
+        assert_eq!("gray".parse(), Ok(OutputPalette::GREY));
+
+        let palette: OutputPalette = "#e0f8d0, 88c070,#346856,081820".parse().unwrap();
+        assert_eq!(palette.rgba(Color::White), [0xE0, 0xF8, 0xD0, 0xFF]);
+        assert_eq!(palette.rgba(Color::LightGray), [0x88, 0xC0, 0x70, 0xFF]);
+        assert_eq!(palette.rgba(Color::Black), [0x08, 0x18, 0x20, 0xFF]);
+
+        assert!("e0f8d0,88c070,346856".parse::<OutputPalette>().is_err());
+        assert!("e0f8d0,88c070,346856,08182"
+            .parse::<OutputPalette>()
+            .is_err());
+        assert!("e0f8d0,88c070,346856,zz1820"
+            .parse::<OutputPalette>()
+            .is_err());
+    }
+
+    #[test]
+    fn color_correction_curve() {
+        assert_eq!(ColorCorrection::None.rgba(0x7FFF), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(ColorCorrection::None.rgba(0x001F), [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(ColorCorrection::None.rgba(0x0000), [0x00, 0x00, 0x00, 0xFF]);
+
+        // White is dimmed, pure colors bleed into the other channels
+        assert_eq!(ColorCorrection::Cgb.rgba(0x7FFF), [0xF0, 0xF0, 0xF0, 0xFF]);
+        assert_eq!(ColorCorrection::Cgb.rgba(0x001F), [0xC9, 0x00, 0x2E, 0xFF]);
+        assert_eq!(ColorCorrection::Cgb.rgba(0x0000), [0x00, 0x00, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn display_config_file() {
+        let config = DisplayConfig::parse(
+            "# Output settings\n\npalette = #9bbc0f,#8bac0f,#306230,#0f380f\ncolor_correction = on\n",
+        )
+        .unwrap();
+        assert_eq!(config.palette, Some(OutputPalette::GREEN));
+        assert_eq!(config.color_correction, Some(true));
+        assert_eq!(DisplayConfig::parse("").unwrap(), DisplayConfig::default());
+
+        assert!(matches!(
+            DisplayConfig::parse("palette = green\nscale = 3"),
+            Err(ConfigError::Invalid(2, _))
+        ));
+        assert!(matches!(
+            DisplayConfig::parse("color_correction = maybe"),
+            Err(ConfigError::Invalid(1, _))
+        ));
+        assert!(matches!(
+            DisplayConfig::parse("palette"),
+            Err(ConfigError::Invalid(1, _))
+        ));
+    }
+
+    fn render_frame(ppu: &mut PPU, bus: &mut Bus) {
+        while !ppu.frame_ready {
+            ppu.run_for(bus, 1);
+        }
+    }
+
+    #[test]
+    fn framebuffer_uses_the_output_settings() {
+        let mut bus = Bus::default();
+        // LCD and BG on, color 0 shown as the darkest shade
+        bus.write_byte(0xFF40, 0b1001_0001);
+        bus.write_byte(0xFF47, 0b0000_0011);
+        let mut ppu = PPU::new();
+        ppu.output_palette = OutputPalette::POCKET;
+        render_frame(&mut ppu, &mut bus);
+        assert_eq!(ppu.frame[0..4], OutputPalette::POCKET.rgba(Color::Black));
+
+        // Turning the LCD off blanks the screen to the lightest shade
+        bus.write_byte(0xFF40, 0);
+        ppu.run_for(&mut bus, 1);
+        assert_eq!(ppu.frame[0..4], OutputPalette::POCKET.rgba(Color::White));
+
+        bus.mode = HardwareMode::CGB;
+        bus.io.lcd.status.ly = 0;
+        bus.write_byte(0xFF40, 0b1001_0001);
+        ppu.color_correction = ColorCorrection::Cgb;
+        ppu.frame_ready = false;
+        render_frame(&mut ppu, &mut bus);
+        ppu.frame_ready = false;
+        render_frame(&mut ppu, &mut bus);
+        assert_eq!(ppu.frame[0..4], [0xF0, 0xF0, 0xF0, 0xFF]);
+    }
+}
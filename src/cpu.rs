@@ -1,10 +1,10 @@
-use std::u8;
-
 use crate::{
     bus::{Bus, Memory, MemoryLockOwner},
+    cartridge::save::SaveFile,
     // memory::{MemoryBus, MemoryBusClient},
     opcodes::Instruction,
     ppu::PPU,
+    save_state::{SaveStateError, Snapshot, StateReader, StateWriter},
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -119,6 +119,23 @@ impl Register {
     }
 }
 
+impl Snapshot for Register {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.get_af());
+        writer.write_u16(self.get_bc());
+        writer.write_u16(self.get_de());
+        writer.write_u16(self.get_hl());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.set_af(reader.read_u16()?);
+        self.set_bc(reader.read_u16()?);
+        self.set_de(reader.read_u16()?);
+        self.set_hl(reader.read_u16()?);
+        Ok(())
+    }
+}
+
 // #[derive(Debug, Clone, Copy)]
 // pub enum JoypadButton {
 //     A,
@@ -131,6 +148,16 @@ impl Register {
 //     Down,
 // }
 
+/// Clock cycles to draw a full frame, 154 lines of 456 dots
+pub const CYCLES_PER_FRAME: u64 = 70224;
+
+/// Memory access made during an M-cycle, with the byte read or written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusAccess {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
 pub struct CPU {
     pub registers: Register,
     pub program_counter: u16,
@@ -141,6 +168,26 @@ pub struct CPU {
     pub debug: bool,
     pub walk: bool,
     pub is_halted: bool,
+    // STOP low-power mode, the clocks are stopped until a joypad line goes low
+    pub is_stopped: bool,
+    // Set by EI, IME is enabled once the next instruction has run
+    pub ei_pending: bool,
+    // HALT skipped with IME off and an interupt pending, PC isn't incremented after the
+    // next opcode fetch
+    pub halt_bug: bool,
+    // Set by the unused opcodes, nothing but a reset brings the CPU back
+    pub is_locked: bool,
+    pub save_file: Option<SaveFile>,
+    // M-cycles the rest of the machine was advanced by since the start of the step
+    step_cycles: u16,
+    // When set, one entry is appended per M-cycle, `None` for the cycles spent internally
+    pub bus_trace: Option<Vec<Option<BusAccess>>>,
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CPU {
@@ -155,17 +202,77 @@ impl CPU {
             debug: false,
             walk: false,
             is_halted: false,
+            is_stopped: false,
+            ei_pending: false,
+            halt_bug: false,
+            is_locked: false,
+            save_file: None,
+            step_cycles: 0,
+            bus_trace: None,
         }
     }
 
     #[inline]
     fn advance_pc(&mut self, nb_bytes: u8) {
-        self.program_counter += nb_bytes as u16;
+        self.program_counter = self.program_counter.wrapping_add(nb_bytes as u16);
+    }
+
+    /// Advance the rest of the machine by one M-cycle, every memory access takes one.
+    /// Also used alone for the cycles an instruction spends without touching memory
+    pub fn tick(&mut self) {
+        self.advance_machine(1);
+        self.step_cycles += 1;
+        if let Some(trace) = &mut self.bus_trace {
+            trace.push(None);
+        }
     }
 
+    /// Read a byte, the timers, DMA and PPU having caught up to the access
+    pub fn read_byte(&mut self, address: u16) -> u8 {
+        self.tick();
+        let value = self.memory_bus.read_byte(address);
+        self.trace_access(BusAccess::Read(address, value));
+        value
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        self.tick();
+        self.memory_bus.write_byte(address, value);
+        self.trace_access(BusAccess::Write(address, value));
+    }
+
+    /// Record the access made by the M-cycle that just ran
+    fn trace_access(&mut self, access: BusAccess) {
+        if let Some(cycle) = self.bus_trace.as_mut().and_then(|trace| trace.last_mut()) {
+            *cycle = Some(access);
+        }
+    }
+
+    /// Little endian, the low byte is accessed first
+    pub fn read_word(&mut self, address: u16) -> u16 {
+        let low = self.read_byte(address);
+        let high = self.read_byte(address.wrapping_add(1));
+        u16::from_le_bytes([low, high])
+    }
+
+    pub fn write_word(&mut self, address: u16, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.write_byte(address, low);
+        self.write_byte(address.wrapping_add(1), high);
+    }
+
+    /// Decode and execute the instruction at PC, the machine advancing with each access
     #[inline]
-    fn read_instruction(&mut self) -> Option<u8> {
-        let instruction = Instruction::from_byte(self, self.program_counter);
+    fn read_instruction(&mut self) {
+        let start = self.step_cycles;
+        let pc = self.program_counter;
+        // The HALT bug reads the byte following the opcode from the opcode's own address
+        let operands = if self.halt_bug {
+            pc
+        } else {
+            pc.wrapping_add(1)
+        };
+        let (instruction, info) = Instruction::from_byte(self, pc, operands);
         if self.debug {
             println!(
                 "PC: {:0004x?} OP: {:#02x?} I: {:?}",
@@ -174,114 +281,217 @@ impl CPU {
                 instruction
             );
         }
+        self.advance_pc(info.length - self.halt_bug as u8);
+        self.halt_bug = false;
+
+        let cycles = if instruction.execute(self) {
+            info.branch_cycles
+        } else {
+            info.cycles
+        };
+        // The opcode tables count clock cycles, 4 per M-cycle. What the accesses didn't
+        // use was spent internally, at the end of the instruction
+        while self.step_cycles - start < cycles as u16 / 4 {
+            self.tick();
+        }
+    }
 
-        match instruction {
-            Instruction::STOP => {
-                self.advance_pc(1);
-                None
+    #[inline]
+    pub fn handle_interupt(&mut self) {
+        if self.interupt_master_enable && !self.is_locked && self.interupt_pending() {
+            self.interupt_master_enable = false;
+            // EI then HALT hits the HALT bug, the handler returns to the HALT itself
+            if self.halt_bug {
+                self.program_counter = self.program_counter.wrapping_sub(1);
+                self.halt_bug = false;
             }
-            _ => {
-                self.advance_pc(Instruction::nb_bytes(
-                    self.memory_bus.read_byte(self.program_counter),
-                ));
+            // Two M-cycles waiting, the push of PC, then one more to jump
+            self.tick();
+            self.dispatch_interupt();
+            self.tick();
+        }
+    }
+
+    /// An interupt is both requested in IF and enabled in IE, whatever IME is
+    pub fn interupt_pending(&self) -> bool {
+        let enable: u8 = self.memory_bus.interupt_enable.into();
+        let flags: u8 = self.memory_bus.interupt_flags.into();
+        enable & flags & 0x1F != 0
+    }
 
-                let cycles = instruction.execute(self);
-                // let pc = self.program_counter;
+    /// Jump to the vector of the highest priority pending interupt, clearing its flag
+    fn dispatch_interupt(&mut self) {
+        if self.memory_bus.interupt_enable.v_blank && self.memory_bus.interupt_flags.v_blank {
+            self.call(0x40);
+            self.memory_bus.interupt_flags.v_blank = false;
+        } else if self.memory_bus.interupt_enable.lcd_stat
+            && self.memory_bus.interupt_flags.lcd_stat
+        {
+            self.call(0x48);
+            self.memory_bus.interupt_flags.lcd_stat = false;
+        } else if self.memory_bus.interupt_enable.timer && self.memory_bus.interupt_flags.timer {
+            self.call(0x50);
+            self.memory_bus.interupt_flags.timer = false;
+        } else if self.memory_bus.interupt_enable.serial && self.memory_bus.interupt_flags.serial {
+            self.call(0x58);
+            self.memory_bus.interupt_flags.serial = false;
+        } else if self.memory_bus.interupt_enable.joypad && self.memory_bus.interupt_flags.joypad {
+            self.call(0x60);
+            self.memory_bus.interupt_flags.joypad = false;
+        }
+    }
 
-                Some(cycles)
+    /// Snapshot of the whole machine, tied to the loaded ROM
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(self.memory_bus.rom_checksums());
+        self.registers.save_state(&mut writer);
+        writer.write_u16(self.program_counter);
+        writer.write_u16(self.stack_pointer);
+        writer.write_bool(self.interupt_master_enable);
+        writer.write_bool(self.is_halted);
+        writer.write_bool(self.is_stopped);
+        writer.write_bool(self.ei_pending);
+        writer.write_bool(self.halt_bug);
+        writer.write_bool(self.is_locked);
+        self.memory_bus.save_state(&mut writer);
+        self.ppu.save_state(&mut writer);
+        writer.data
+    }
+
+    /// Restore a snapshot taken by `save_state`, the machine is left untouched on error
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(data, self.memory_bus.rom_checksums())?;
+        let mut registers = self.registers;
+        registers.load_state(&mut reader)?;
+        let program_counter = reader.read_u16()?;
+        let stack_pointer = reader.read_u16()?;
+        let interupt_master_enable = reader.read_bool()?;
+        let is_halted = reader.read_bool()?;
+        let is_stopped = reader.read_bool()?;
+        let ei_pending = reader.read_bool()?;
+        let halt_bug = reader.read_bool()?;
+        let is_locked = reader.read_bool()?;
+        let mut memory_bus = self.memory_bus.clone();
+        memory_bus.load_state(&mut reader)?;
+        let mut ppu = self.ppu.clone();
+        ppu.load_state(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(SaveStateError::TrailingData);
+        }
+
+        self.registers = registers;
+        self.program_counter = program_counter;
+        self.stack_pointer = stack_pointer;
+        self.interupt_master_enable = interupt_master_enable;
+        self.is_halted = is_halted;
+        self.is_stopped = is_stopped;
+        self.ei_pending = ei_pending;
+        self.halt_bug = halt_bug;
+        self.is_locked = is_locked;
+        self.memory_bus = memory_bus;
+        self.ppu = ppu;
+        Ok(())
+    }
+
+    /// Write battery backed RAM to the save file, if any
+    pub fn flush_save(&mut self) {
+        if let Some(save_file) = &mut self.save_file {
+            if let Err(err) = save_file.flush(&self.memory_bus) {
+                eprintln!("Failed to write {}: {}", save_file.path.display(), err);
             }
         }
     }
 
-    #[inline]
-    pub fn handle_interupt(&mut self) {
-        if self.interupt_master_enable {
-            if self.memory_bus.interupt_enable.v_blank && self.memory_bus.interupt_flags.v_blank {
-                self.call(0x40);
-                self.memory_bus.interupt_flags.v_blank = false;
-            } else if self.memory_bus.interupt_enable.lcd_stat
-                && self.memory_bus.interupt_flags.lcd_stat
-            {
-                self.call(0x48);
-                self.memory_bus.interupt_flags.lcd_stat = false;
-            } else if self.memory_bus.interupt_enable.timer && self.memory_bus.interupt_flags.timer
-            {
-                self.call(0x50);
-                self.memory_bus.interupt_flags.timer = false;
-            } else if self.memory_bus.interupt_enable.serial
-                && self.memory_bus.interupt_flags.serial
-            {
-                self.call(0x58);
-                self.memory_bus.interupt_flags.serial = false;
-            } else if self.memory_bus.interupt_enable.joypad
-                && self.memory_bus.interupt_flags.joypad
-            {
-                self.call(0x60);
-                self.memory_bus.interupt_flags.joypad = false;
+    /// Read instruction from memory and execute it, the rest of the machine advancing along
+    /// with each memory access. Returns the M-cycles taken, a stopped CPU reports one
+    /// without advancing anything so the frames keep their pace
+    pub fn step(&mut self) -> u16 {
+        self.step_cycles = 0;
+        if self.is_stopped {
+            if u8::from(self.memory_bus.io.joypad) & 0x0F == 0x0F {
+                return 1;
             }
+            self.is_stopped = false;
         }
+        // Any pending interupt ends HALT, it's only serviced when IME is set
+        if self.is_halted && self.interupt_pending() {
+            self.is_halted = false;
+        }
+        // handle interupts
+        self.handle_interupt();
+        let enable_interupts = self.ei_pending;
+        // A halted or locked CPU doesn't fetch anything, the rest of the machine keeps running
+        if self.is_halted || self.is_locked {
+            self.tick();
+        } else {
+            self.read_instruction();
+        }
+        // A DI right after EI cancels it
+        if enable_interupts && self.ei_pending {
+            self.interupt_master_enable = true;
+            self.ei_pending = false;
+        }
+        // The CPU is stalled while the VRAM DMA copies its blocks
+        for _ in 0..self.memory_bus.hdma_step() {
+            self.tick();
+        }
+
+        if self.walk {
+            println!("CPU Registers: {:?}", self.registers);
+            println!("CPU Program Counter: {:#06x?}", self.program_counter);
+            println!("CPU Stack Pointer: {:#06x?}", self.stack_pointer);
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input).unwrap();
+        }
+        self.step_cycles
     }
 
-    pub fn run(&mut self, hz: u64) {
-        let cycles_per_second = hz;
+    /// Run the timers, the APU, the OAM DMA and the PPU for `cycles` M-cycles
+    fn advance_machine(&mut self, cycles: u16) {
+        for _ in 0..cycles {
+            self.memory_bus.tick();
+            self.memory_bus.dma_transfer_step();
+        }
+        self.ppu.run_for(&mut self.memory_bus, cycles);
+        self.memory_bus.current_owner = MemoryLockOwner::CPU;
+    }
 
-        loop {
-            // handle interupts
-            self.handle_interupt();
-            self.memory_bus
-                .io
-                .timer_divider
-                .tick(&mut self.memory_bus.interupt_flags, cycles_per_second);
-            self.memory_bus
-                .io
-                .lcd
-                .status
-                .tick(&mut self.memory_bus.interupt_flags);
-            match self.read_instruction() {
-                Some(cycles) => {
-                    // let seconds = cycles as f32 / cycles_per_second;
-                    // std::thread::sleep(std::time::Duration::from_secs_f32(seconds));
-                    self.memory_bus.oam.dma_transfer_step(
-                        /* TODO: Improve this shit */ self.memory_bus.clone(),
-                    );
-                    self.ppu.run_for(&mut self.memory_bus, cycles);
-                    self.memory_bus.current_owner = MemoryLockOwner::CPU;
-                }
-                None => break,
-            }
-            if self.walk {
-                println!("CPU Registers: {:?}", self.registers);
-                println!("CPU Program Counter: {:#06x?}", self.program_counter);
-                println!("CPU Stack Pointer: {:#06x?}", self.stack_pointer);
-                let mut input = String::new();
-                std::io::stdin().read_line(&mut input).unwrap();
-            }
+    /// Run until the PPU completes a frame, or for a frame worth of cycles when the LCD
+    /// doesn't produce any, returns the clock cycles run
+    pub fn run_frame(&mut self) -> u64 {
+        let mut cycles = 0;
+        self.ppu.frame_ready = false;
+        while !self.ppu.frame_ready && cycles < CYCLES_PER_FRAME {
+            cycles += self.step() as u64 * self.memory_bus.dots_per_cycle() as u64;
         }
+        cycles
     }
 
-    /// Read instruction from memory and execute it
-    /// The full set of steps is as follows:
-    /// - Use the program counter to read the instruction byte from memory.
-    /// - Translate the byte to one of the instances of the Instruction enum
-    /// - If we can successfully translate the instruction call our execute method else panic which now returns the next program counter
-    /// - Set this next program counter on our CPU
-    // fn step(&mut self) {
-    //     let mut opcode = self.memory_bus.read_byte(self.program_counter);
-    //     let prefixed = opcode == 0xCB;
-    //     if prefixed {
-    //         opcode = self.memory_bus.read_next_byte();
-    //     }
-    //     let next_pc = if let Some(instruction) = Instruction::from_byte(opcode, prefixed) {
-    //         self.execute(instruction)
-    //     } else {
-    //         panic!("Instruction {:?} not implemented", opcode)
-    //     };
-    // }
-
-    fn jump(&mut self, should_jump: bool) -> u16 {
-        todo!("Jump not implemented")
+    /// Run for `cycles` clock cycles, flushing the save RAM about once per `hz` cycles.
+    /// STOP doesn't end the run, the CPU sleeps in it until a button is pressed
+    pub fn run(&mut self, hz: u64, cycles: u64) {
+        let cycles_per_second = hz;
+        let mut elapsed = 0;
+        // Clock cycles since the save RAM was last flushed
+        let mut save_cycles = 0;
+
+        while elapsed < cycles {
+            let step_cycles = self.step() as u64 * self.memory_bus.dots_per_cycle() as u64;
+            elapsed += step_cycles;
+            // let seconds = step_cycles as f32 / cycles_per_second;
+            // std::thread::sleep(std::time::Duration::from_secs_f32(seconds));
+
+            // Flush the save RAM about once per emulated second
+            save_cycles += step_cycles;
+            if save_cycles >= cycles_per_second {
+                save_cycles = 0;
+                self.flush_save();
+            }
+        }
+        self.flush_save();
     }
 
+    /// Push PC and jump, the push taking 3 M-cycles
     pub fn call(&mut self, address: u16) {
         self.push_word(self.program_counter);
         self.program_counter = address;
@@ -295,14 +505,19 @@ impl CPU {
     }
 
     // Stack
+    /// An internal M-cycle to decrement SP, then the high byte is written before the low one
     pub fn push_word(&mut self, value: u16) {
-        self.stack_pointer -= 2;
-        self.memory_bus.write_word(self.stack_pointer, value);
+        let [low, high] = value.to_le_bytes();
+        self.tick();
+        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+        self.write_byte(self.stack_pointer, high);
+        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+        self.write_byte(self.stack_pointer, low);
     }
 
     pub fn pop_word(&mut self) -> u16 {
-        let value = self.memory_bus.read_word(self.stack_pointer);
-        self.stack_pointer += 2;
+        let value = self.read_word(self.stack_pointer);
+        self.stack_pointer = self.stack_pointer.wrapping_add(2);
         value
     }
 }
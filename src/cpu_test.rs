@@ -1,648 +1,976 @@
 #[cfg(test)]
 mod cpu_tests {
-    use crate::{bus::Memory, cpu::CPU};
+    use crate::{
+        bus::{joypad::Button, HardwareMode, Memory},
+        cpu::{BusAccess, CPU, CYCLES_PER_FRAME},
+        opcodes::OpcodeInfo,
+    };
+
+    // CPU running straight from the cartridge ROM, without the boot ROM mapped over it
+    fn new_cpu() -> CPU {
+        let mut cpu = CPU::new();
+        cpu.memory_bus.io.disable_boot_rom = 1;
+        cpu
+    }
+
+    #[test]
+    fn interupt_bits_follow_priority_order() {
+        let mut cpu = new_cpu();
+        cpu.memory_bus.write_byte(0xFF0F, 0b0000_0001);
+        assert!(cpu.memory_bus.interupt_flags.v_blank);
+        assert!(!cpu.memory_bus.interupt_flags.joypad);
+
+        cpu.memory_bus.write_byte(0xFFFF, 0b0001_0100);
+        assert!(cpu.memory_bus.interupt_enable.timer);
+        assert!(cpu.memory_bus.interupt_enable.joypad);
+        assert!(!cpu.memory_bus.interupt_enable.v_blank);
+        cpu.memory_bus.interupt_flags.lcd_stat = true;
+        cpu.memory_bus.interupt_flags.v_blank = false;
+        cpu.memory_bus.interupt_flags.serial = true;
+        assert_eq!(cpu.memory_bus.read_byte(0xFF0F) & 0x1F, 0b0000_1010);
+    }
 
     #[test]
     fn add_c() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x01;
         cpu.registers.c = 0x02;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x81);
+        cpu.memory_bus.rom[0x0000] = 0x81;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0x03);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn addhl_bc() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.set_hl(0x01);
         cpu.registers.set_bc(0x02);
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x09);
+        cpu.memory_bus.rom[0x0000] = 0x09;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.get_hl(), 0x03);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn add_d8() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x01;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xC6);
-        cpu.memory_bus.write_byte(0x0001, 0x02);
+        cpu.memory_bus.rom[0x0000] = 0xC6;
+        cpu.memory_bus.rom[0x0001] = 0x02;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0x03);
-        assert_eq!(cpu.program_counter, 0x0003);
+        assert_eq!(cpu.program_counter, 0x0004);
     }
 
     #[test]
     fn adc_b() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x01;
         cpu.registers.b = 0x02;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x88);
+        cpu.memory_bus.rom[0x0000] = 0x88;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0x04);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn adc_d8() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x01;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCE);
-        cpu.memory_bus.write_byte(0x0001, 0x02);
+        cpu.memory_bus.rom[0x0000] = 0xCE;
+        cpu.memory_bus.rom[0x0001] = 0x02;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0x04);
-        assert_eq!(cpu.program_counter, 0x0003);
+        assert_eq!(cpu.program_counter, 0x0004);
     }
 
     #[test]
     fn and_d() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0b0000_0011;
         cpu.registers.d = 0b0000_0010;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xA2);
+        cpu.memory_bus.rom[0x0000] = 0xA2;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0b0000_0010);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn and_d8() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x01;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xE6);
-        cpu.memory_bus.write_byte(0x0001, 0x02);
+        cpu.memory_bus.rom[0x0000] = 0xE6;
+        cpu.memory_bus.rom[0x0001] = 0x02;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0x00);
-        assert_eq!(cpu.program_counter, 0x0003);
+        assert_eq!(cpu.program_counter, 0x0004);
     }
 
     #[test]
     fn ccf() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.f.carry = false;
         cpu.registers.f.half_carry = true;
         cpu.registers.f.subtract = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x3F);
+        cpu.memory_bus.rom[0x0000] = 0x3F;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
-        assert_eq!(cpu.registers.f.carry, true);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.program_counter, 0x0002);
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.subtract);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn cp() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x01;
         cpu.registers.b = 0x02;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xB8);
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0000] = 0xB8;
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0x01);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn cp_hl() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x01;
         cpu.registers.set_hl(0x02);
-        cpu.memory_bus.write_byte(0x0000, 0xBE);
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0000] = 0xBE;
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0x01);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
     #[test]
     fn cp_zero() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x01;
         cpu.registers.b = 0x01;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xB8);
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0000] = 0xB8;
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0x01);
-        assert_eq!(cpu.registers.f.zero, true);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert!(cpu.registers.f.zero);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn cpl() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x00;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x2F);
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0000] = 0x2F;
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0xFF);
-        assert_eq!(cpu.registers.f.subtract, true);
-        assert_eq!(cpu.registers.f.half_carry, true);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert!(cpu.registers.f.subtract);
+        assert!(cpu.registers.f.half_carry);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn inc() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x00;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x3C);
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0000] = 0x3C;
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0b0000_0001);
-        assert_eq!(cpu.registers.f.zero, false);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn dec() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x01;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x3D);
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0000] = 0x3D;
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0x00);
-        assert_eq!(cpu.registers.f.zero, true);
-        assert_eq!(cpu.registers.f.subtract, true);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn dec_zero() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x01;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x3D);
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0000] = 0x3D;
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0x00);
-        assert_eq!(cpu.registers.f.zero, true);
-        assert_eq!(cpu.registers.f.subtract, true);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn rl() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.b = 0b1000_0000;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0x10);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0x10;
 
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
-        assert_eq!(cpu.registers.b, 0b0000_0000);
-        assert_eq!(cpu.registers.f.zero, false);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.carry, true);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert_eq!(cpu.registers.b, 0b0000_0001);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
     }
     #[test]
     fn rr() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.b = 0b0000_0001;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0x18);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0x18;
 
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
-        assert_eq!(cpu.registers.b, 0b0000_0000);
-        assert_eq!(cpu.registers.f.zero, false);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.carry, true);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert_eq!(cpu.registers.b, 0b1000_0000);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
     }
 
     #[test]
     fn rla() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0b1000_0000;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x17);
-        cpu.memory_bus.write_byte(0x0001, 0x10);
+        cpu.memory_bus.rom[0x0000] = 0x17;
+        cpu.memory_bus.rom[0x0001] = 0x10;
 
-        cpu.run(4.194304);
-        assert_eq!(cpu.registers.a, 0b0000_0000);
-        assert_eq!(cpu.registers.f.zero, false);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.carry, true);
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert_eq!(cpu.registers.a, 0b0000_0001);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
     }
 
     #[test]
     fn rra() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0b0000_0001;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x1F);
-        cpu.memory_bus.write_byte(0x0001, 0x10);
+        cpu.memory_bus.rom[0x0000] = 0x1F;
+        cpu.memory_bus.rom[0x0001] = 0x10;
 
-        cpu.run(4.194304);
-        assert_eq!(cpu.registers.a, 0b0000_0000);
-        assert_eq!(cpu.registers.f.zero, false);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.carry, true);
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert_eq!(cpu.registers.a, 0b1000_0000);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
     }
 
     #[test]
     fn rlc() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.b = 0b1000_0000;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0x00);
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0x00;
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.b, 0b0000_0001);
-        assert_eq!(cpu.registers.f.zero, false);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.carry, false);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
     }
     #[test]
     fn rrc() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.b = 0b0000_0001;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0x08);
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0x08;
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.b, 0b1000_0000);
-        assert_eq!(cpu.registers.f.zero, false);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.carry, false);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
     }
 
     #[test]
     fn rrca() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0b0000_0001;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x0F);
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0000] = 0x0F;
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0b1000_0000);
-        assert_eq!(cpu.registers.f.zero, false);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.carry, false);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
     }
 
     #[test]
     fn scf() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.f.carry = false;
         cpu.registers.f.half_carry = true;
         cpu.registers.f.subtract = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x37);
+        cpu.memory_bus.rom[0x0000] = 0x37;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
-        assert_eq!(cpu.registers.f.carry, true);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.program_counter, 0x0002);
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.subtract);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn sla() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.b = 0b1000_0000;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0x20);
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0x20;
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.b, 0b0000_0000);
-        assert_eq!(cpu.registers.f.zero, true);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.carry, true);
+        assert!(cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
     }
 
     #[test]
     fn sra() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.b = 0b0000_0001;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0x28);
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0x28;
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.b, 0b0000_0000);
-        assert_eq!(cpu.registers.f.zero, true);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.carry, true);
+        assert!(cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
     }
 
     #[test]
     fn bit_6_h() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.h = 0b0100_0000;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0x74);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0x74;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
-        assert_eq!(cpu.registers.f.zero, false);
-        assert_eq!(cpu.program_counter, 0x0003);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert!(!cpu.registers.f.zero);
+        assert_eq!(cpu.program_counter, 0x0004);
     }
 
     #[test]
     fn bit_4_b() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.h = 0b0100_0000;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0x40);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0x40;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
-        assert_eq!(cpu.registers.f.zero, true);
-        assert_eq!(cpu.program_counter, 0x0003);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert!(cpu.registers.f.zero);
+        assert_eq!(cpu.program_counter, 0x0004);
     }
 
     #[test]
     fn call_nn() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCD);
-        cpu.memory_bus.write_byte(0x0001, 0x00);
-        cpu.memory_bus.write_byte(0x0002, 0x10);
+        cpu.memory_bus.rom[0x0000] = 0xCD;
+        cpu.memory_bus.rom[0x0001] = 0x00;
+        cpu.memory_bus.rom[0x0002] = 0x10;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0010, 0x10);
-        cpu.run(4.194304);
-        assert_eq!(cpu.program_counter, 0x0011);
-        assert_eq!(cpu.stack_pointer, 0xFFFD);
-        assert_eq!(cpu.memory_bus.read_word(0xFFFD), 0x0003);
+        cpu.memory_bus.rom[0x1000] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert_eq!(cpu.program_counter, 0x1002);
+        assert_eq!(cpu.stack_pointer, 0xFFFC);
+        assert_eq!(cpu.memory_bus.read_word(0xFFFC), 0x0003);
     }
 
     #[test]
     fn interupt_joy() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.program_counter = 0x0000;
         cpu.interupt_master_enable = true;
-        cpu.memory_bus.write_byte(0x0000, 0xFB);
+        cpu.memory_bus.rom[0x0000] = 0xFB;
         cpu.memory_bus.interupt_flags.joypad = true;
         cpu.memory_bus.interupt_enable.joypad = true;
         // Stop instruction at interupt vector
-        cpu.memory_bus.write_byte(0x0060, 0x10);
-        cpu.memory_bus.write_byte(0x0061, 0x10);
-        cpu.run(4.194304);
-        assert_eq!(cpu.program_counter, 0x0061);
-        assert_eq!(cpu.interupt_master_enable, true);
+        cpu.memory_bus.rom[0x0060] = 0x10;
+        cpu.memory_bus.rom[0x0061] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert_eq!(cpu.program_counter, 0x0062);
+        // Serviced right away, IME was already set. Dispatching clears it
+        assert!(!cpu.interupt_master_enable);
+        assert!(!cpu.memory_bus.interupt_flags.joypad);
+        assert_eq!(cpu.memory_bus.read_word(0xFFFC), 0x0000);
     }
 
     #[test]
     fn pop_bc() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.set_de(0x0003);
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xD5);
-        cpu.memory_bus.write_byte(0x0001, 0xC1);
+        cpu.memory_bus.rom[0x0000] = 0xD5;
+        cpu.memory_bus.rom[0x0001] = 0xC1;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.get_bc(), 0x0003);
     }
 
     #[test]
     fn push_bc() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.set_bc(0x0003);
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xC5);
+        cpu.memory_bus.rom[0x0000] = 0xC5;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
-        assert_eq!(cpu.memory_bus.read_word(0xFFFD), 0x0003);
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert_eq!(cpu.memory_bus.read_word(0xFFFC), 0x0003);
     }
 
     #[test]
     fn set_2_c() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.c = 0x03;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0xD1);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0xD1;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.c, 0x07);
-        assert_eq!(cpu.program_counter, 0x0003);
+        assert_eq!(cpu.program_counter, 0x0004);
     }
 
     #[test]
     fn set_3_hl() {
-        let mut cpu = CPU::new();
-        cpu.registers.set_hl(0x03);
+        let mut cpu = new_cpu();
+        cpu.registers.set_hl(0xC003);
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0xDE);
-        cpu.memory_bus.write_byte(0x03, 0b0000_0011);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0xDE;
+        cpu.memory_bus.write_byte(0xC003, 0b0000_0011);
 
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(
             cpu.memory_bus.read_byte(cpu.registers.get_hl()),
             0b0000_1011
         );
-        assert_eq!(cpu.program_counter, 0x0003);
+        assert_eq!(cpu.program_counter, 0x0004);
     }
 
     #[test]
     fn res_1_c() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.c = 0x03;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0x89);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0x89;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.c, 0x01);
-        assert_eq!(cpu.program_counter, 0x0003);
+        assert_eq!(cpu.program_counter, 0x0004);
     }
 
     #[test]
     fn res_2_hl() {
-        let mut cpu = CPU::new();
-        cpu.registers.set_hl(0x07);
+        let mut cpu = new_cpu();
+        cpu.registers.set_hl(0xC007);
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0x96);
-        cpu.memory_bus.write_byte(0x07, 0b0000_0111);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0x96;
+        cpu.memory_bus.write_byte(0xC007, 0b0000_0111);
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(
             cpu.memory_bus.read_byte(cpu.registers.get_hl()),
             0b0000_0011
         );
-        assert_eq!(cpu.program_counter, 0x0003);
+        assert_eq!(cpu.program_counter, 0x0004);
     }
 
     #[test]
     fn xor_c() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0b0000_0011;
         cpu.registers.c = 0b0000_0010;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xA9);
+        cpu.memory_bus.rom[0x0000] = 0xA9;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0b0000_0001);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn or_c() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0b0000_0001;
         cpu.registers.c = 0b0000_0010;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xA9);
+        cpu.memory_bus.rom[0x0000] = 0xA9;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.a, 0b0000_0011);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn swap() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.b = 0b0000_0001;
 
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0x30);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0x30;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.b, 0b0001_0000);
-        assert_eq!(cpu.program_counter, 0x0003);
+        assert_eq!(cpu.program_counter, 0x0004);
     }
 
     #[test]
     fn sbc() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x02;
         cpu.registers.b = 0x01;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x98);
+        cpu.memory_bus.rom[0x0000] = 0x98;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
 
         assert_eq!(cpu.registers.a, 0x00);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn sub() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.a = 0x02;
         cpu.registers.b = 0x01;
 
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x90);
+        cpu.memory_bus.rom[0x0000] = 0x90;
         // Stop instruction
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
 
         assert_eq!(cpu.registers.a, 0x01);
-        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.program_counter, 0x0003);
     }
 
     #[test]
     fn srl() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         cpu.registers.b = 0b0000_0001;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0xCB);
-        cpu.memory_bus.write_byte(0x0001, 0x38);
+        cpu.memory_bus.rom[0x0000] = 0xCB;
+        cpu.memory_bus.rom[0x0001] = 0x38;
 
-        cpu.memory_bus.write_byte(0x0002, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0002] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         assert_eq!(cpu.registers.b, 0b0000_0000);
-        assert_eq!(cpu.registers.f.zero, true);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.carry, true);
+        assert!(cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
     }
 
     #[test]
     fn daa() {
-        let mut cpu = CPU::new();
+        let mut cpu = new_cpu();
         //0x60 + 0x60 = 0xc0
         cpu.registers.a = 0xc0;
         cpu.registers.f.carry = true;
         cpu.program_counter = 0x0000;
-        cpu.memory_bus.write_byte(0x0000, 0x27);
+        cpu.memory_bus.rom[0x0000] = 0x27;
 
-        cpu.memory_bus.write_byte(0x0001, 0x10);
-        cpu.run(4.194304);
+        cpu.memory_bus.rom[0x0001] = 0x10;
+        cpu.run(4194304, CYCLES_PER_FRAME);
         //0x90 + 0x90 = 0x120 (0x20 + carry)
         assert_eq!(cpu.registers.a, 0x20);
-        assert_eq!(cpu.registers.f.zero, false);
-        assert_eq!(cpu.registers.f.subtract, false);
-        assert_eq!(cpu.registers.f.half_carry, false);
-        assert_eq!(cpu.registers.f.carry, true);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn stop_switches_speed_when_armed() {
+        let mut cpu = new_cpu();
+        cpu.memory_bus.mode = HardwareMode::CGB;
+        cpu.program_counter = 0x0000;
+        // LD A, 0x01; LDH (0x4D), A; STOP; LD B, 0x42; STOP
+        cpu.memory_bus.rom[0x0000..0x0009]
+            .copy_from_slice(&[0x3E, 0x01, 0xE0, 0x4D, 0x10, 0x00, 0x06, 0x42, 0x10]);
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert_eq!(cpu.registers.b, 0x42);
+        assert_eq!(cpu.program_counter, 0x000A);
+        assert!(cpu.memory_bus.io.speed_switch.double_speed);
+        assert!(!cpu.memory_bus.io.speed_switch.armed);
+        assert_eq!(cpu.memory_bus.read_byte(0xFF4D), 0xFE);
+    }
+
+    #[test]
+    fn hblank_dma_runs_during_mode_0() {
+        let mut cpu = new_cpu();
+        cpu.memory_bus.mode = HardwareMode::CGB;
+        for i in 0..0x20 {
+            cpu.memory_bus.write_byte(0xC000 + i, i as u8 + 1);
+        }
+        cpu.memory_bus.write_byte(0xFF40, 0b1001_0001);
+        cpu.memory_bus.write_byte(0xFF51, 0xC0);
+        cpu.memory_bus.write_byte(0xFF53, 0x80);
+        while cpu.memory_bus.io.lcd.status.stat.ppu_mode != 2 {
+            cpu.step();
+        }
+        cpu.memory_bus.write_byte(0xFF55, 0x81);
+
+        // Running NOPs, one block per line
+        while cpu.memory_bus.io.lcd.status.ly == 0 {
+            cpu.step();
+        }
+        assert_eq!(cpu.memory_bus.vram.0[0x0F], 0x10);
+        assert_eq!(cpu.memory_bus.vram.0[0x10], 0x00);
+        assert_eq!(cpu.memory_bus.read_byte(0xFF55), 0x00);
+        while cpu.memory_bus.io.lcd.status.ly == 1 {
+            cpu.step();
+        }
+        assert_eq!(cpu.memory_bus.vram.0[0x1F], 0x20);
+        assert_eq!(cpu.memory_bus.read_byte(0xFF55), 0xFF);
+    }
+
+    /// M-cycles `step` takes for the instruction bytes at 0xC000 in WRAM, followed by
+    /// zeros, with both states of the flags so conditional branches are taken once
+    fn opcode_cycles(cpu: &mut CPU, opcode: &[u8]) -> Vec<u16> {
+        [0x00, 0xF0]
+            .into_iter()
+            .map(|flags| {
+                cpu.memory_bus.wram.fill(0);
+                cpu.memory_bus.wram[..opcode.len()].copy_from_slice(opcode);
+                cpu.program_counter = 0xC000;
+                cpu.stack_pointer = 0xD000;
+                // Zeroed registers keep the (C) and (a8) accesses on the joypad register
+                cpu.registers.a = 0;
+                cpu.registers.set_bc(0);
+                cpu.registers.set_de(0);
+                cpu.registers.set_hl(0xC800);
+                cpu.registers.f = flags.into();
+                cpu.is_halted = false;
+                cpu.is_stopped = false;
+                cpu.is_locked = false;
+                cpu.step()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_opcode_takes_its_table_cycles() {
+        let mut cpu = new_cpu();
+        for opcode in 0..=0xFF {
+            if opcode == 0xCB {
+                continue;
+            }
+            let info = OpcodeInfo::unprefixed(opcode);
+            let mut cycles = opcode_cycles(&mut cpu, &[opcode]);
+            cycles.sort();
+            let mut expected = [info.cycles as u16 / 4, info.branch_cycles as u16 / 4];
+            expected.sort();
+            assert_eq!(cycles, expected, "{:#04x}", opcode);
+        }
+        for opcode in 0..=0xFF {
+            let info = OpcodeInfo::prefixed(opcode);
+            let cycles = opcode_cycles(&mut cpu, &[0xCB, opcode]);
+            assert_eq!(cycles, [info.cycles as u16 / 4; 2], "CB {:#04x}", opcode);
+        }
+    }
+
+    #[test]
+    fn every_opcode_fetches_its_table_length() {
+        // JR, JP, CALL, RET and RST, which don't leave PC after the instruction
+        const JUMPS: [u8; 30] = [
+            0x18, 0x20, 0x28, 0x30, 0x38, 0xC0, 0xC2, 0xC3, 0xC4, 0xC7, 0xC8, 0xC9, 0xCA, 0xCC,
+            0xCD, 0xCF, 0xD0, 0xD2, 0xD4, 0xD7, 0xD8, 0xD9, 0xDA, 0xDC, 0xDF, 0xE7, 0xE9, 0xEF,
+            0xF7, 0xFF,
+        ];
+        let mut cpu = new_cpu();
+        let unprefixed = (0..=0xFF)
+            .filter(|&opcode| opcode != 0xCB)
+            .map(|opcode| (vec![opcode], OpcodeInfo::unprefixed(opcode)));
+        let prefixed = (0..=0xFF).map(|opcode| (vec![0xCB, opcode], OpcodeInfo::prefixed(opcode)));
+        for (bytes, info) in unprefixed.chain(prefixed) {
+            cpu.memory_bus.wram.fill(0);
+            cpu.memory_bus.wram[..bytes.len()].copy_from_slice(&bytes);
+            cpu.program_counter = 0xC000;
+            cpu.stack_pointer = 0xD000;
+            cpu.registers.set_hl(0xC800);
+            cpu.is_halted = false;
+            cpu.is_stopped = false;
+            cpu.is_locked = false;
+            cpu.bus_trace = Some(Vec::new());
+            cpu.step();
+
+            // The decoder reads the opcode and its operands one after the other, STOP
+            // skips its second byte without fetching it
+            let fetched = cpu
+                .bus_trace
+                .take()
+                .unwrap()
+                .iter()
+                .zip(0xC000..)
+                .take_while(|&(access, address)| {
+                    matches!(access, Some(BusAccess::Read(read, _)) if *read == address)
+                })
+                .count()
+                + (bytes == [0x10]) as usize;
+            assert_eq!(fetched, info.length as usize, "{:02X?}", bytes);
+            if bytes.len() == 2 || !JUMPS.contains(&bytes[0]) {
+                assert_eq!(
+                    cpu.program_counter,
+                    0xC000 + info.length as u16,
+                    "{:02X?}",
+                    bytes
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reads_see_the_timer_at_the_access() {
+        let mut cpu = new_cpu();
+        // LDH A, (DIV) reads in its third M-cycle
+        cpu.memory_bus.rom[0x0000..0x0002].copy_from_slice(&[0xF0, 0x04]);
+        cpu.memory_bus.write_byte(0xFF04, 0);
+        // 3 M-cycles before DIV increments
+        for _ in 0..61 {
+            cpu.tick();
+        }
+        assert_eq!(cpu.memory_bus.read_byte(0xFF04), 0x00);
+        assert_eq!(cpu.step(), 3);
+        assert_eq!(cpu.registers.a, 0x01);
+    }
+
+    #[test]
+    fn writes_reach_the_timer_at_the_access() {
+        let mut cpu = new_cpu();
+        // LD (DIV), A writes in its last M-cycle, nothing runs after the reset
+        cpu.memory_bus.rom[0x0000..0x0003].copy_from_slice(&[0xEA, 0x04, 0xFF]);
+        for _ in 0..100 {
+            cpu.tick();
+        }
+        assert_eq!(cpu.step(), 4);
+        assert_eq!(cpu.memory_bus.io.timer_divider.system_counter(), 0);
+    }
+
+    #[test]
+    fn halt_wakes_on_pending_interupt_without_ime() {
+        let mut cpu = new_cpu();
+        // HALT; LD B, 0x42; STOP
+        cpu.memory_bus.rom[0x0000..0x0004].copy_from_slice(&[0x76, 0x06, 0x42, 0x10]);
+        cpu.memory_bus.interupt_enable.timer = true;
+        cpu.step();
+        assert!(cpu.is_halted);
+        // Time goes on while halted
+        let div = cpu.memory_bus.io.timer_divider.system_counter();
+        for _ in 0..10 {
+            assert_eq!(cpu.step(), 1);
+        }
+        assert_eq!(cpu.program_counter, 0x0001);
+        assert_ne!(cpu.memory_bus.io.timer_divider.system_counter(), div);
+
+        cpu.memory_bus.interupt_flags.timer = true;
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert!(!cpu.is_halted);
+        assert_eq!(cpu.registers.b, 0x42);
+        assert_eq!(cpu.program_counter, 0x0005);
+        // Not serviced with IME off
+        assert!(cpu.memory_bus.interupt_flags.timer);
+    }
+
+    #[test]
+    fn halt_services_interupt_with_ime() {
+        let mut cpu = new_cpu();
+        cpu.interupt_master_enable = true;
+        cpu.memory_bus.rom[0x0000] = 0x76;
+        cpu.memory_bus.rom[0x0050] = 0x10;
+        cpu.memory_bus.interupt_enable.timer = true;
+        cpu.step();
+        assert!(cpu.is_halted);
+
+        cpu.memory_bus.interupt_flags.timer = true;
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert_eq!(cpu.program_counter, 0x0052);
+        assert_eq!(cpu.memory_bus.read_word(0xFFFC), 0x0001);
+        assert!(!cpu.memory_bus.interupt_flags.timer);
+    }
+
+    #[test]
+    fn halt_bug_reads_the_next_byte_twice() {
+        let mut cpu = new_cpu();
+        // HALT; LD A, 0x14 read as LD A, 0x3E then INC D; STOP
+        cpu.memory_bus.rom[0x0000..0x0004].copy_from_slice(&[0x76, 0x3E, 0x14, 0x10]);
+        cpu.memory_bus.interupt_enable.timer = true;
+        cpu.memory_bus.interupt_flags.timer = true;
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert!(!cpu.is_halted);
+        assert_eq!(cpu.registers.a, 0x3E);
+        assert_eq!(cpu.registers.d, 0x01);
+        assert_eq!(cpu.program_counter, 0x0005);
+    }
+
+    #[test]
+    fn ei_enables_interupts_after_the_next_instruction() {
+        let mut cpu = new_cpu();
+        // EI; LD B, 0x42; STOP, with the joypad interupt already pending
+        cpu.memory_bus.rom[0x0000..0x0004].copy_from_slice(&[0xFB, 0x06, 0x42, 0x10]);
+        cpu.memory_bus.rom[0x0060] = 0x10;
+        cpu.memory_bus.interupt_enable.joypad = true;
+        cpu.memory_bus.interupt_flags.joypad = true;
+        cpu.step();
+        assert!(!cpu.interupt_master_enable);
+        cpu.step();
+        assert!(cpu.interupt_master_enable);
+        assert_eq!(cpu.registers.b, 0x42);
+
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert_eq!(cpu.program_counter, 0x0062);
+        assert_eq!(cpu.memory_bus.read_word(0xFFFC), 0x0003);
+    }
+
+    #[test]
+    fn di_cancels_a_pending_ei() {
+        let mut cpu = new_cpu();
+        // EI; DI; STOP
+        cpu.memory_bus.rom[0x0000..0x0003].copy_from_slice(&[0xFB, 0xF3, 0x10]);
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert!(!cpu.interupt_master_enable);
+        assert!(!cpu.ei_pending);
+    }
+
+    #[test]
+    fn stop_waits_for_a_joypad_line() {
+        let mut cpu = new_cpu();
+        // STOP; LD B, 0x42; STOP
+        cpu.memory_bus.rom[0x0000..0x0005].copy_from_slice(&[0x10, 0x00, 0x06, 0x42, 0x10]);
+        cpu.memory_bus.write_byte(0xFF00, 0x10);
+        for _ in 0..100 {
+            cpu.tick();
+        }
+        cpu.step();
+        assert!(cpu.is_stopped);
+        assert_eq!(cpu.program_counter, 0x0002);
+        // DIV is reset and the clocks stay stopped
+        let div = cpu.memory_bus.io.timer_divider.system_counter();
+        assert!(div < 0x10);
+        for _ in 0..10 {
+            assert_eq!(cpu.step(), 1);
+        }
+        assert_eq!(cpu.memory_bus.io.timer_divider.system_counter(), div);
+        assert_eq!(cpu.program_counter, 0x0002);
+
+        cpu.memory_bus.io.joypad.buttons.a = true;
+        cpu.step();
+        assert!(!cpu.is_stopped);
+        assert_eq!(cpu.registers.b, 0x42);
+    }
+
+    #[test]
+    fn stop_under_run_resumes_after_a_button_press() {
+        let mut cpu = new_cpu();
+        // Select the buttons; STOP; LD B, 0x42; JR -2
+        cpu.memory_bus.rom[0x0000..0x000A]
+            .copy_from_slice(&[0x3E, 0x10, 0xE0, 0x00, 0x10, 0x00, 0x06, 0x42, 0x18, 0xFE]);
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert!(cpu.is_stopped);
+        assert_eq!(cpu.program_counter, 0x0006);
+        assert_eq!(cpu.registers.b, 0x00);
+
+        cpu.memory_bus.set_button(Button::A, true);
+        cpu.run(4194304, CYCLES_PER_FRAME);
+        assert!(!cpu.is_stopped);
+        assert_eq!(cpu.registers.b, 0x42);
+        assert_eq!(cpu.program_counter, 0x0008);
     }
 }
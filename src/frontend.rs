@@ -0,0 +1,208 @@
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use pixels::{Pixels, SurfaceTexture};
+use winit::{
+    dpi::LogicalSize,
+    event::{ElementState, Event, KeyboardInput, ModifiersState, VirtualKeyCode, WindowEvent},
+    event_loop::EventLoop,
+    window::WindowBuilder,
+};
+
+use crate::{
+    bus::joypad::{Button, JoypadButtons},
+    cpu::{CPU, CYCLES_PER_FRAME},
+    ppu::{HEIGHT, WIDTH},
+    save_state,
+    wav::AudioRecorder,
+};
+
+const SCALE: u32 = 3;
+// Flush the save RAM about once per second
+const SAVE_FLUSH_FRAMES: u32 = 60;
+
+/// Window displaying the PPU framebuffer and feeding the keyboard to the joypad
+///
+/// Keys: arrows for the d-pad, X for A, Z for B, Enter for Start and Backspace or right
+/// shift for Select. F1–F4 load a quick save slot, Shift+F1–F4 write it.
+pub struct Frontend {
+    pub cpu: CPU,
+    pub cpu_speed: u64,
+    pub rom_path: PathBuf,
+    // Quick save slot written when the window is closed
+    pub exit_state_slot: Option<u8>,
+    // WAV file the APU output is written to
+    pub audio_recorder: Option<AudioRecorder<BufWriter<File>>>,
+}
+
+impl Frontend {
+    pub fn new(cpu: CPU, cpu_speed: u64, rom_path: impl Into<PathBuf>) -> Self {
+        Self {
+            cpu,
+            cpu_speed,
+            rom_path: rom_path.into(),
+            exit_state_slot: None,
+            audio_recorder: None,
+        }
+    }
+
+    pub fn run(mut self) -> ! {
+        let event_loop = EventLoop::new();
+        let window = {
+            let size = LogicalSize::new((WIDTH * SCALE) as f64, (HEIGHT * SCALE) as f64);
+            WindowBuilder::new()
+                .with_title("gb")
+                .with_inner_size(size)
+                .with_min_inner_size(LogicalSize::new(WIDTH as f64, HEIGHT as f64))
+                .build(&event_loop)
+                .unwrap()
+        };
+
+        let mut pixels = {
+            let window_size = window.inner_size();
+            let surface_texture =
+                SurfaceTexture::new(window_size.width, window_size.height, &window);
+            Pixels::new(WIDTH, HEIGHT, surface_texture).unwrap()
+        };
+
+        let frame_duration =
+            Duration::from_secs_f64(CYCLES_PER_FRAME as f64 / self.cpu_speed as f64);
+        let mut next_frame = Instant::now();
+        let mut modifiers = ModifiersState::empty();
+        let mut frames = 0;
+
+        event_loop.run(move |event, _, control_flow| match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => {
+                    self.exit();
+                    control_flow.set_exit();
+                }
+                WindowEvent::Resized(size) => {
+                    if let Err(err) = pixels.resize_surface(size.width, size.height) {
+                        eprintln!("Failed to resize the surface: {}", err);
+                    }
+                }
+                WindowEvent::ModifiersChanged(state) => modifiers = state,
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(key),
+                            state,
+                            ..
+                        },
+                    ..
+                } => self.handle_key(key, state, modifiers.shift()),
+                _ => (),
+            },
+            Event::MainEventsCleared => {
+                let now = Instant::now();
+                if now < next_frame {
+                    control_flow.set_wait_until(next_frame);
+                    return;
+                }
+                // Don't try to catch up after a stall
+                next_frame = (next_frame + frame_duration).max(now);
+
+                self.cpu.run_frame();
+                self.record_audio();
+                frames += 1;
+                if frames % SAVE_FLUSH_FRAMES == 0 {
+                    self.cpu.flush_save();
+                }
+                pixels.frame_mut().copy_from_slice(&self.cpu.ppu.frame[..]);
+                window.request_redraw();
+                control_flow.set_wait_until(next_frame);
+            }
+            Event::RedrawRequested(_) => {
+                if let Err(err) = pixels.render() {
+                    eprintln!("Failed to render the frame: {}", err);
+                    self.exit();
+                    control_flow.set_exit();
+                }
+            }
+            _ => (),
+        })
+    }
+
+    fn handle_key(&mut self, key: VirtualKeyCode, state: ElementState, shift: bool) {
+        let pressed = state == ElementState::Pressed;
+        let slot = match key {
+            VirtualKeyCode::F1 => Some(1),
+            VirtualKeyCode::F2 => Some(2),
+            VirtualKeyCode::F3 => Some(3),
+            VirtualKeyCode::F4 => Some(4),
+            _ => None,
+        };
+        if let Some(slot) = slot {
+            if pressed {
+                if shift {
+                    self.save_slot(slot);
+                } else {
+                    self.load_slot(slot);
+                }
+            }
+            return;
+        }
+
+        let button = match key {
+            VirtualKeyCode::Right => Button::Right,
+            VirtualKeyCode::Left => Button::Left,
+            VirtualKeyCode::Up => Button::Up,
+            VirtualKeyCode::Down => Button::Down,
+            VirtualKeyCode::X => Button::A,
+            VirtualKeyCode::Z => Button::B,
+            VirtualKeyCode::Return => Button::Start,
+            VirtualKeyCode::Back | VirtualKeyCode::RShift => Button::Select,
+            _ => return,
+        };
+        self.cpu.memory_bus.set_button(button, pressed);
+    }
+
+    fn save_slot(&self, slot: u8) {
+        let path = save_state::slot_path(&self.rom_path, slot);
+        match fs::write(&path, self.cpu.save_state()) {
+            Ok(()) => println!("Saved state {}", path.display()),
+            Err(err) => eprintln!("Failed to save state {}: {}", path.display(), err),
+        }
+    }
+
+    fn load_slot(&mut self, slot: u8) {
+        let path = save_state::slot_path(&self.rom_path, slot);
+        match fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|state| self.cpu.load_state(&state).map_err(|err| err.to_string()))
+        {
+            Ok(()) => {
+                // Keys held on the host shouldn't come from the state
+                self.cpu.memory_bus.io.joypad.buttons = JoypadButtons::default();
+                println!("Loaded state {}", path.display());
+            }
+            Err(err) => eprintln!("Failed to load state {}: {}", path.display(), err),
+        }
+    }
+
+    fn record_audio(&mut self) {
+        if let Some(recorder) = &mut self.audio_recorder {
+            if let Err(err) = recorder.record(&mut self.cpu.memory_bus.audio_output) {
+                eprintln!("Failed to record audio: {}", err);
+                self.audio_recorder = None;
+            }
+        }
+    }
+
+    fn exit(&mut self) {
+        self.cpu.flush_save();
+        if let Some(recorder) = self.audio_recorder.take() {
+            if let Err(err) = recorder.finish() {
+                eprintln!("Failed to record audio: {}", err);
+            }
+        }
+        if let Some(slot) = self.exit_state_slot.take() {
+            self.save_slot(slot);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    fmt, fs,
+    io::{self, Seek, Write},
+    path::Path,
+};
+
+use crate::{bus::joypad::Button, cartridge::mbc3::TimeSource, cpu::CPU, wav::AudioRecorder};
+
+// Clock cycles per emulated second
+const CLOCK_SPEED: u64 = 4_194_304;
+
+#[derive(Debug)]
+pub enum InputScriptError {
+    Io(io::Error),
+    // Line number and reason
+    Invalid(usize, String),
+}
+
+impl fmt::Display for InputScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputScriptError::Io(err) => write!(f, "{}", err),
+            InputScriptError::Invalid(line, reason) => write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl std::error::Error for InputScriptError {}
+
+/// Joypad input of a headless run, one `frame button state` line per change where the
+/// state is `down` or `up`. Changes apply before running that frame, counted from 0, and
+/// `#` starts a comment line:
+///
+/// ```text
+/// # Skip the title screen
+/// 120 start down
+/// 125 start up
+/// ```
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct InputScript {
+    // Frame, button and pressed, sorted by frame
+    events: Vec<(u64, Button, bool)>,
+}
+
+impl InputScript {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, InputScriptError> {
+        let script = fs::read_to_string(path).map_err(InputScriptError::Io)?;
+        Self::parse(&script)
+    }
+
+    pub fn parse(script: &str) -> Result<Self, InputScriptError> {
+        let mut events = vec![];
+        for (number, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: String| InputScriptError::Invalid(number + 1, reason);
+
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let [frame, button, state] = fields[..] else {
+                return Err(invalid(format!(
+                    "expected `frame button state`, got {}",
+                    line
+                )));
+            };
+            let frame = frame
+                .parse::<u64>()
+                .map_err(|_| invalid(format!("invalid frame {}", frame)))?;
+            let button = button.parse::<Button>().map_err(invalid)?;
+            let pressed = match state.to_ascii_lowercase().as_str() {
+                "down" => true,
+                "up" => false,
+                _ => {
+                    return Err(invalid(format!(
+                        "invalid state {}, expected down or up",
+                        state
+                    )))
+                }
+            };
+            events.push((frame, button, pressed));
+        }
+        // Stable, changes of the same frame keep the order of the file
+        events.sort_by_key(|&(frame, _, _)| frame);
+        Ok(Self { events })
+    }
+
+    /// Press and release the buttons scheduled for `frame`
+    pub fn apply(&self, frame: u64, cpu: &mut CPU) {
+        let start = self.events.partition_point(|&(at, _, _)| at < frame);
+        for &(_, button, pressed) in self.events[start..]
+            .iter()
+            .take_while(|&&(at, _, _)| at == frame)
+        {
+            cpu.memory_bus.set_button(button, pressed);
+        }
+    }
+}
+
+/// Run `frames` frames as fast as possible, the output only depends on the cartridge,
+/// the save, `input` and the starting state: the RTC follows the emulated time from
+/// where the save left it instead of the host clock
+pub fn run<W: Write + Seek>(
+    cpu: &mut CPU,
+    frames: u64,
+    input: &InputScript,
+    mut audio_recorder: Option<&mut AudioRecorder<W>>,
+) -> io::Result<()> {
+    let start = cpu
+        .memory_bus
+        .mbc
+        .rtc_mut()
+        .map_or(0, |rtc| rtc.last_update);
+    let mut cycles = 0;
+    for frame in 0..frames {
+        if let Some(rtc) = cpu.memory_bus.mbc.rtc_mut() {
+            rtc.time_source = TimeSource::Manual(start + cycles / CLOCK_SPEED);
+        }
+        input.apply(frame, cpu);
+        cycles += cpu.run_frame();
+        if let Some(recorder) = &mut audio_recorder {
+            recorder.record(&mut cpu.memory_bus.audio_output)?;
+        }
+    }
+    if let Some(rtc) = cpu.memory_bus.mbc.rtc_mut() {
+        rtc.time_source = TimeSource::Manual(start + cycles / CLOCK_SPEED);
+    }
+    Ok(())
+}
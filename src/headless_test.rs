@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod headless_tests {
+    use std::io::Cursor;
+
+    use crate::{
+        bus::{audio::output::AudioOutput, Memory},
+        cartridge::{header::CartridgeHeader, Cartridge},
+        cpu::CPU,
+        headless::{self, InputScript, InputScriptError},
+        wav::{AudioRecorder, WavWriter},
+    };
+
+    /// CPU running `program` from 0x0100 of a 32 KiB cartridge, past the boot ROM
+    fn load_program(cartridge_type: u8, program: &[u8]) -> CPU {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+        rom[0x0134..0x0138].copy_from_slice(b"TEST");
+        rom[0x0147] = cartridge_type;
+        rom[0x014D] = CartridgeHeader::compute_header_checksum(&rom);
+        let global = CartridgeHeader::compute_global_checksum(&rom);
+        rom[0x014E..0x0150].copy_from_slice(&global.to_be_bytes());
+
+        let mut cpu = CPU::new();
+        cpu.memory_bus.load_rom(&Cartridge::new(rom).unwrap());
+        cpu.memory_bus.io.disable_boot_rom = 1;
+        cpu.program_counter = 0x0100;
+        cpu
+    }
+
+    #[test]
+    fn parse_input_script() {
+        let script =
+            InputScript::parse("# comment\n\n10 start down\n 5 A up \n5 b down\n").unwrap();
+        assert_eq!(
+            script,
+            InputScript::parse("5 a up\n5 b down\n10 start down").unwrap()
+        );
+
+        for (script, line) in [
+            ("1 start", 1),
+            ("1 start down\nx start down", 2),
+            ("1 turbo down", 1),
+            ("\n1 start pressed", 2),
+        ] {
+            match InputScript::parse(script) {
+                Err(InputScriptError::Invalid(number, _)) => assert_eq!(number, line),
+                result => panic!("{:?} parsed as {:?}", script, result),
+            }
+        }
+    }
+
+    #[test]
+    fn input_script_presses_buttons_before_the_frame() {
+        let mut cpu = load_program(0x00, &[0x18, 0xFE]);
+        let script = InputScript::parse("1 up down\n1 a down\n2 up up").unwrap();
+        script.apply(0, &mut cpu);
+        assert!(!cpu.memory_bus.io.joypad.buttons.up);
+
+        script.apply(1, &mut cpu);
+        assert!(cpu.memory_bus.io.joypad.buttons.up);
+        assert!(cpu.memory_bus.io.joypad.buttons.a);
+        assert!(cpu.memory_bus.interupt_flags.joypad);
+
+        script.apply(2, &mut cpu);
+        assert!(!cpu.memory_bus.io.joypad.buttons.up);
+        assert!(cpu.memory_bus.io.joypad.buttons.a);
+    }
+
+    /// WAV recorded over 30 frames of a ROM playing a tone, then noise once START is
+    /// pressed
+    fn record(script: &str) -> Vec<u8> {
+        let mut cpu = load_program(
+            0x00,
+            &[
+                0x3E, 0x80, 0xE0, 0x26, // Sound on
+                0x3E, 0x77, 0xE0, 0x24, // Full volume
+                0x3E, 0xFF, 0xE0, 0x25, // Every channel on both sides
+                0x3E, 0xF3, 0xE0, 0x12, // Channel 1 envelope
+                0x3E, 0x86, 0xE0, 0x14, // Channel 1 trigger
+                0x3E, 0x10, 0xE0, 0x00, // Select the buttons
+                0xF0, 0x00, 0xE6, 0x08, 0x20, 0xFA, // Wait for START
+                0x3E, 0xA1, 0xE0, 0x21, // Channel 4 envelope
+                0x3E, 0x80, 0xE0, 0x23, // Channel 4 trigger
+                0x18, 0xFE,
+            ],
+        );
+        cpu.memory_bus.audio_output = AudioOutput::new(44_100);
+        let input = InputScript::parse(script).unwrap();
+        let wav = WavWriter::new(Cursor::new(Vec::new()), 44_100).unwrap();
+        let mut recorder = AudioRecorder::new(wav);
+        headless::run(&mut cpu, 30, &input, Some(&mut recorder)).unwrap();
+        recorder.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn headless_runs_are_deterministic() {
+        let script = "10 start down\n12 start up";
+        let data = record(script);
+        assert_eq!(data, record(script));
+        assert!(data.len() > 44 + 20 * 44_100 / 60 * 4);
+
+        assert_ne!(data, record("20 start down"));
+        assert_ne!(data, record(""));
+    }
+
+    /// Seconds counted by the RTC of a MBC3 cartridge after `frames` frames
+    fn rtc_seconds(frames: u64) -> u8 {
+        // MBC3 with a timer, spinning on JR -2
+        let mut cpu = load_program(0x0F, &[0x18, 0xFE]);
+        let recorder: Option<&mut AudioRecorder<Cursor<Vec<u8>>>> = None;
+        headless::run(&mut cpu, frames, &InputScript::default(), recorder).unwrap();
+        cpu.memory_bus.write_byte(0x0000, 0x0A);
+        cpu.memory_bus.write_byte(0x4000, 0x08);
+        cpu.memory_bus.write_byte(0x6000, 0x00);
+        cpu.memory_bus.write_byte(0x6000, 0x01);
+        cpu.memory_bus.read_byte(0xA000)
+    }
+
+    #[test]
+    fn rtc_follows_the_emulated_time() {
+        // A second is a bit less than 60 frames
+        assert_eq!(rtc_seconds(59), 0);
+        assert_eq!(rtc_seconds(61), 1);
+    }
+}
@@ -1,6 +1,20 @@
 pub mod bus;
+pub mod cartridge;
+pub mod color;
+mod color_test;
 pub mod cpu;
 mod cpu_test;
+#[cfg(feature = "frontend")]
+pub mod frontend;
+pub mod headless;
+mod headless_test;
 // pub mod memory;
 pub mod opcodes;
+mod opcodes_test;
 pub mod ppu;
+mod ppu_test;
+pub mod save_state;
+mod save_state_test;
+mod sm83_test;
+pub mod wav;
+mod wav_test;
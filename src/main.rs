@@ -1,13 +1,31 @@
-use std::fs;
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+};
 
 use clap::Parser;
-use gb::{self, cpu::CPU};
+use gb::{
+    self,
+    bus::HardwareMode,
+    cartridge::{mbc3::TimeSource, save::SaveFile, Cartridge},
+    color::{ColorCorrection, DisplayConfig, OutputPalette},
+    cpu::CPU,
+    frontend::Frontend,
+    headless::{self, InputScript},
+    save_state,
+    wav::AudioRecorder,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, about, version, long_about = None, name = "gb")]
 struct Args {
-    #[arg(short, long, default_value = "assets/bootroms/dmg.bin")]
-    boot_rom: String,
+    /// Boot ROM, defaults to the dmg.bin or cgb.bin shipped in assets/bootroms
+    #[arg(short, long)]
+    boot_rom: Option<String>,
+
+    /// Run as a DMG or a CGB (dmg, cgb) instead of following the cartridge header
+    #[arg(short, long)]
+    mode: Option<HardwareMode>,
 
     #[arg(short, long, default_value = "tetris.gb")]
     rom: String,
@@ -20,6 +38,46 @@ struct Args {
 
     #[arg(short, long, default_value = "4194304")]
     cpu_speed: u64,
+
+    /// Save file for battery backed cartridges, defaults to <rom>.sav
+    #[arg(long)]
+    save: Option<String>,
+
+    /// Don't load nor write the save file
+    #[arg(long)]
+    no_save: bool,
+
+    /// Restore the machine from a quick save slot on startup
+    #[arg(long, value_name = "SLOT")]
+    load_state: Option<u8>,
+
+    /// Quick save slot written when the window is closed or the headless run ends
+    #[arg(long, value_name = "SLOT")]
+    save_state: Option<u8>,
+
+    /// DMG screen colors: green, pocket, grey or 4 hex colors from white to black
+    #[arg(long)]
+    palette: Option<OutputPalette>,
+
+    /// Mimic the CGB screen response instead of showing raw colors
+    #[arg(long)]
+    color_correction: bool,
+
+    /// Display settings file of `key = value` lines (palette, color_correction)
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Write the sound output to a 16 bits stereo WAV file
+    #[arg(long, value_name = "WAV")]
+    record_audio: Option<String>,
+
+    /// Run headless for this many frames instead of opening a window
+    #[arg(long)]
+    frames: Option<u64>,
+
+    /// Joypad input of the headless run, `frame button state` lines such as `120 start down`
+    #[arg(long, value_name = "FILE")]
+    input: Option<String>,
 }
 
 pub fn print_section_hex(data: Vec<u8>, start: u16, end: u16) {
@@ -35,22 +93,163 @@ fn run_gb() {
     let args = Args::parse();
     let mut cpu = CPU::new();
 
+    let rom = fs::read(&args.rom).unwrap();
+    let cartridge = match Cartridge::new(rom) {
+        Ok(cartridge) => cartridge,
+        Err(err) => {
+            eprintln!("Failed to load {}: {}", args.rom, err);
+            std::process::exit(1);
+        }
+    };
+    println!("Loaded {}: {}", args.rom, cartridge);
+    cpu.memory_bus.load_rom(&cartridge);
+    cpu.memory_bus.mode = args
+        .mode
+        .unwrap_or_else(|| HardwareMode::from_cartridge(&cartridge));
+
     // Load boot rom
-    let boot_rom = fs::read(args.boot_rom).unwrap();
+    let boot_rom_path = args.boot_rom.unwrap_or_else(|| {
+        match cpu.memory_bus.mode {
+            HardwareMode::DMG => "assets/bootroms/dmg.bin",
+            HardwareMode::CGB => "assets/bootroms/cgb.bin",
+        }
+        .to_string()
+    });
+    let boot_rom = fs::read(boot_rom_path).unwrap();
     cpu.memory_bus.load_boot_rom(&boot_rom);
     // print_section_hex(cpu.memory_bus.rom, 0x00, 0x100);
-    let rom = fs::read(args.rom).unwrap();
-    cpu.memory_bus.load_rom(&rom);
+
+    // Headless runs must not depend on the host clock, the RTC is moved by the emulated
+    // time once running
+    if args.frames.is_some() {
+        if let Some(rtc) = cpu.memory_bus.mbc.rtc_mut() {
+            rtc.time_source = TimeSource::Manual(0);
+        }
+    }
+
+    if !args.no_save {
+        let save_path = match &args.save {
+            Some(path) => path.into(),
+            None => SaveFile::default_path(&args.rom),
+        };
+        cpu.save_file = SaveFile::new(save_path, &cartridge);
+        if let Some(save_file) = &mut cpu.save_file {
+            match save_file.load(&mut cpu.memory_bus) {
+                Ok(true) => println!("Loaded save {}", save_file.path.display()),
+                Ok(false) => (),
+                Err(err) => {
+                    eprintln!("Failed to read {}: {}", save_file.path.display(), err);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    if let Some(slot) = args.load_state {
+        let path = save_state::slot_path(&args.rom, slot);
+        match fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|state| cpu.load_state(&state).map_err(|err| err.to_string()))
+        {
+            Ok(()) => println!("Loaded state {}", path.display()),
+            Err(err) => {
+                eprintln!("Failed to load state {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Command line settings take precedence over the config file
+    let config = match &args.config {
+        Some(path) => match DisplayConfig::load(path) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("Failed to read {}: {}", path, err);
+                std::process::exit(1);
+            }
+        },
+        None => DisplayConfig::default(),
+    };
+    if let Some(palette) = args.palette.or(config.palette) {
+        cpu.ppu.output_palette = palette;
+    }
+    if args.color_correction || config.color_correction.unwrap_or(false) {
+        cpu.ppu.color_correction = ColorCorrection::Cgb;
+    }
+
     if args.debug {
         cpu.debug = true;
     }
     if args.walk {
         cpu.walk = true;
     }
-    cpu.run(args.cpu_speed);
+
+    let audio_recorder = args.record_audio.as_ref().map(|path| {
+        let sample_rate = cpu.memory_bus.audio_output.sample_rate;
+        match AudioRecorder::create(path, sample_rate) {
+            Ok(recorder) => recorder,
+            Err(err) => {
+                eprintln!("Failed to create {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    });
+
+    if let Some(frames) = args.frames {
+        let input = match &args.input {
+            Some(path) => match InputScript::load(path) {
+                Ok(input) => input,
+                Err(err) => {
+                    eprintln!("Failed to read {}: {}", path, err);
+                    std::process::exit(1);
+                }
+            },
+            None => InputScript::default(),
+        };
+        run_headless(
+            cpu,
+            frames,
+            &input,
+            audio_recorder,
+            &args.rom,
+            args.save_state,
+        );
+        return;
+    }
+
+    let mut frontend = Frontend::new(cpu, args.cpu_speed, &args.rom);
+    frontend.exit_state_slot = args.save_state;
+    frontend.audio_recorder = audio_recorder;
+    frontend.run();
+}
+
+/// Run as fast as possible without a window nor audio device, for scripted tests
+fn run_headless(
+    mut cpu: CPU,
+    frames: u64,
+    input: &InputScript,
+    mut audio_recorder: Option<AudioRecorder<BufWriter<File>>>,
+    rom: &str,
+    save_state_slot: Option<u8>,
+) {
+    if let Err(err) = headless::run(&mut cpu, frames, input, audio_recorder.as_mut()) {
+        eprintln!("Failed to record audio: {}", err);
+        std::process::exit(1);
+    }
+    cpu.flush_save();
+    if let Some(recorder) = audio_recorder {
+        if let Err(err) = recorder.finish() {
+            eprintln!("Failed to record audio: {}", err);
+            std::process::exit(1);
+        }
+    }
+    if let Some(slot) = save_state_slot {
+        let path = save_state::slot_path(rom, slot);
+        if let Err(err) = fs::write(&path, cpu.save_state()) {
+            eprintln!("Failed to save state {}: {}", path.display(), err);
+        }
+    }
 }
 
 fn main() {
     run_gb();
-    println!("Hello, world!");
 }
@@ -0,0 +1,137 @@
+#[cfg(test)]
+mod opcodes_tests {
+    use crate::opcodes::OpcodeInfo;
+
+    // Published opcode tables, rows are the high nibble. Lengths in bytes, 0 for the
+    // unused opcodes. LD (C), A and LD A, (C) are 1 byte, some tables wrongly list 2
+    const LENGTHS: &str = "
+        1 3 1 1 1 1 2 1 3 1 1 1 1 1 2 1
+        2 3 1 1 1 1 2 1 2 1 1 1 1 1 2 1
+        2 3 1 1 1 1 2 1 2 1 1 1 1 1 2 1
+        2 3 1 1 1 1 2 1 2 1 1 1 1 1 2 1
+        1 1 1 1 1 1 1 1 1 1 1 1 1 1 1 1
+        1 1 1 1 1 1 1 1 1 1 1 1 1 1 1 1
+        1 1 1 1 1 1 1 1 1 1 1 1 1 1 1 1
+        1 1 1 1 1 1 1 1 1 1 1 1 1 1 1 1
+        1 1 1 1 1 1 1 1 1 1 1 1 1 1 1 1
+        1 1 1 1 1 1 1 1 1 1 1 1 1 1 1 1
+        1 1 1 1 1 1 1 1 1 1 1 1 1 1 1 1
+        1 1 1 1 1 1 1 1 1 1 1 1 1 1 1 1
+        1 1 3 3 3 1 2 1 1 1 3 1 3 3 2 1
+        1 1 3 0 3 1 2 1 1 1 3 0 3 0 2 1
+        2 1 1 0 0 1 2 1 2 1 3 0 0 0 2 1
+        2 1 1 1 0 1 2 1 2 1 3 1 0 0 2 1
+    ";
+
+    // M-cycles, conditional branches not taken
+    const CYCLES: &str = "
+        1 3 2 2 1 1 2 1 5 2 2 2 1 1 2 1
+        1 3 2 2 1 1 2 1 3 2 2 2 1 1 2 1
+        2 3 2 2 1 1 2 1 2 2 2 2 1 1 2 1
+        2 3 2 2 3 3 3 1 2 2 2 2 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        2 2 2 2 2 2 1 2 1 1 1 1 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        2 3 3 4 3 4 2 4 2 4 3 1 3 6 2 4
+        2 3 3 0 3 4 2 4 2 4 3 0 3 0 2 4
+        3 3 2 0 0 4 2 4 4 1 4 0 0 0 2 4
+        3 3 2 1 0 4 2 4 3 2 4 1 0 0 2 4
+    ";
+
+    // M-cycles, conditional branches taken
+    const BRANCH_CYCLES: &str = "
+        1 3 2 2 1 1 2 1 5 2 2 2 1 1 2 1
+        1 3 2 2 1 1 2 1 3 2 2 2 1 1 2 1
+        3 3 2 2 1 1 2 1 3 2 2 2 1 1 2 1
+        3 3 2 2 3 3 3 1 3 2 2 2 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        2 2 2 2 2 2 1 2 1 1 1 1 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        1 1 1 1 1 1 2 1 1 1 1 1 1 1 2 1
+        5 3 4 4 6 4 2 4 5 4 4 1 6 6 2 4
+        5 3 4 0 6 4 2 4 5 4 4 0 6 0 2 4
+        3 3 2 0 0 4 2 4 4 1 4 0 0 0 2 4
+        3 3 2 1 0 4 2 4 3 2 4 1 0 0 2 4
+    ";
+
+    // M-cycles of the CB prefixed instructions, all 2 bytes long
+    const PREFIXED_CYCLES: &str = "
+        2 2 2 2 2 2 4 2 2 2 2 2 2 2 4 2
+        2 2 2 2 2 2 4 2 2 2 2 2 2 2 4 2
+        2 2 2 2 2 2 4 2 2 2 2 2 2 2 4 2
+        2 2 2 2 2 2 4 2 2 2 2 2 2 2 4 2
+        2 2 2 2 2 2 3 2 2 2 2 2 2 2 3 2
+        2 2 2 2 2 2 3 2 2 2 2 2 2 2 3 2
+        2 2 2 2 2 2 3 2 2 2 2 2 2 2 3 2
+        2 2 2 2 2 2 3 2 2 2 2 2 2 2 3 2
+        2 2 2 2 2 2 4 2 2 2 2 2 2 2 4 2
+        2 2 2 2 2 2 4 2 2 2 2 2 2 2 4 2
+        2 2 2 2 2 2 4 2 2 2 2 2 2 2 4 2
+        2 2 2 2 2 2 4 2 2 2 2 2 2 2 4 2
+        2 2 2 2 2 2 4 2 2 2 2 2 2 2 4 2
+        2 2 2 2 2 2 4 2 2 2 2 2 2 2 4 2
+        2 2 2 2 2 2 4 2 2 2 2 2 2 2 4 2
+        2 2 2 2 2 2 4 2 2 2 2 2 2 2 4 2
+    ";
+
+    fn parse(table: &str) -> Vec<u8> {
+        let values: Vec<u8> = table
+            .split_whitespace()
+            .map(|value| value.parse().unwrap())
+            .collect();
+        assert_eq!(values.len(), 256);
+        values
+    }
+
+    #[test]
+    fn unprefixed_table_matches_published_timings() {
+        let lengths = parse(LENGTHS);
+        let cycles = parse(CYCLES);
+        let branch_cycles = parse(BRANCH_CYCLES);
+
+        for opcode in 0..=0xFF {
+            let info = OpcodeInfo::unprefixed(opcode);
+            let index = opcode as usize;
+            if lengths[index] == 0 {
+                // Unused, only fetched before the CPU locks up
+                assert_eq!(info.length, 1, "{:#04x}", opcode);
+                continue;
+            }
+            assert_eq!(info.length, lengths[index], "length of {:#04x}", opcode);
+            assert_eq!(info.cycles, cycles[index] * 4, "cycles of {:#04x}", opcode);
+            assert_eq!(
+                info.branch_cycles,
+                branch_cycles[index] * 4,
+                "taken cycles of {:#04x}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn prefixed_table_matches_published_timings() {
+        let cycles = parse(PREFIXED_CYCLES);
+
+        for opcode in 0..=0xFF {
+            let info = OpcodeInfo::prefixed(opcode);
+            let index = opcode as usize;
+            assert_eq!(info.length, 2, "length of CB {:#04x}", opcode);
+            assert_eq!(
+                info.cycles,
+                cycles[index] * 4,
+                "cycles of CB {:#04x}",
+                opcode
+            );
+            assert_eq!(info.branch_cycles, info.cycles, "CB {:#04x}", opcode);
+        }
+    }
+}
@@ -1,146 +1,296 @@
-use pixels::{Pixels, SurfaceTexture};
-use winit::{dpi::LogicalSize, event_loop::EventLoop, window::WindowBuilder};
-
-use crate::bus::{
-    lcd::SpriteSize,
-    oam::{self, ObjectAttribute},
-    Bus, MemoryLockOwner, MemoryRegion,
+use std::collections::VecDeque;
+
+use crate::{
+    bus::{
+        lcd::{BGTileMapArea, BGWindowTileDataArea, Color, SpriteSize, WindowTileMapArea},
+        oam::{DMGPalette, ObjectAttribute, ObjectAttributeFlags, VRAMBank},
+        Bus, HardwareMode, Memory, MemoryLockOwner, MemoryRegion,
+    },
+    color::{ColorCorrection, OutputPalette},
+    save_state::{SaveStateError, Snapshot, StateReader, StateWriter},
 };
 
+/// 2 bits color index of a pixel, before going through a palette
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PixelColor {
-    White,
-    LightGray,
-    DarkGray,
-    Black,
+    White = 0,
+    LightGray = 1,
+    DarkGray = 2,
+    Black = 3,
+}
+
+impl std::convert::From<u8> for PixelColor {
+    fn from(value: u8) -> Self {
+        match value & 0b11 {
+            0 => PixelColor::White,
+            1 => PixelColor::LightGray,
+            2 => PixelColor::DarkGray,
+            _ => PixelColor::Black,
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PixelPalette {
-    Palette0,
-    Palette1,
-    Palette2,
+    BGP,
+    OBP0,
+    OBP1,
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct Pixel {
     color: PixelColor,
     palette: PixelPalette,
+    // Palette number on CGB, from the BG map attributes or the object flags
+    cgb_palette: u8,
+    // OAM index of the object the pixel comes from
     priority: u8,
+    // Object drawn behind background colors 1-3, or on CGB a background tile drawn over
+    // the objects
     bg_priority: bool,
 }
 
-struct Object {
-    attributes: oam::ObjectAttribute,
-    pixels: [Pixel; 64],
+impl Pixel {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.color as u8);
+        writer.write_u8(match self.palette {
+            PixelPalette::BGP => 0,
+            PixelPalette::OBP0 => 1,
+            PixelPalette::OBP1 => 2,
+        });
+        writer.write_u8(self.cgb_palette);
+        writer.write_u8(self.priority);
+        writer.write_bool(self.bg_priority);
+    }
+
+    fn load_state(reader: &mut StateReader) -> Result<Self, SaveStateError> {
+        Ok(Self {
+            color: match reader.read_u8()? {
+                color @ 0..=3 => color.into(),
+                _ => return Err(SaveStateError::InvalidValue("pixel color")),
+            },
+            palette: match reader.read_u8()? {
+                0 => PixelPalette::BGP,
+                1 => PixelPalette::OBP0,
+                2 => PixelPalette::OBP1,
+                _ => return Err(SaveStateError::InvalidValue("pixel palette")),
+            },
+            cgb_palette: reader.read_u8()? & 0b111,
+            priority: reader.read_u8()?,
+            bg_priority: reader.read_bool()?,
+        })
+    }
+}
+
+/// Background and window tile fetcher, feeding `fifo_background` during mode 3
+#[derive(Default, Debug, Clone, Copy)]
+struct Fetcher {
+    // Dot within the current fetch: tile index, data low and data high take 2 dots
+    // each, then the fetcher waits on dot 6 until the FIFO is empty to push the tile
+    dot: u8,
+    // Column of the tile in the background or window map
+    tile_x: u8,
+    window: bool,
+    tile_index: u8,
+    // BG map attributes of the tile from VRAM bank 1, same layout as the object flags
+    attributes: ObjectAttributeFlags,
+    data_low: u8,
+    data_high: u8,
 }
 
+/// Object kept by the OAM scan for the current line
+#[derive(Debug, Clone, Copy)]
+struct BufferedObject {
+    attribute: ObjectAttribute,
+    // Row of the object on this line, flipped with the height used by the scan so a
+    // later change of LCDC bit 2 can't move it out of the object
+    row: u8,
+}
+
+/// Object being fetched, the pixel output is paused meanwhile
+#[derive(Debug, Clone, Copy)]
+struct ObjectFetch {
+    // Index in `objects_buffer`
+    object: u8,
+    // Dots left, the first ones wait for the background fetcher to finish its tile
+    dots: u8,
+}
+
+#[derive(Clone)]
 pub struct PPU {
     dot_counter: u16,
-    event_loop: EventLoop<()>,
-    window: winit::window::Window,
-    pixels: Pixels,
+    // RGBA output, written one pixel at a time during mode 3
+    pub frame: Box<[u8; FRAME_SIZE]>,
+    // Set when a full frame has been drawn, cleared by the frontend once displayed
+    pub frame_ready: bool,
 
-    objects_buffer: Vec<ObjectAttribute>,
-    fifo_background: Vec<Pixel>,
-    fifo_object: Vec<Pixel>,
+    objects_buffer: Vec<BufferedObject>,
+    fifo_background: VecDeque<Pixel>,
+    fifo_object: VecDeque<Pixel>,
 
     // Mode 2
     oam_progress: u8,
+
+    // Mode 3
+    fetcher: Fetcher,
+    object_fetch: Option<ObjectFetch>,
+    // Bit n set once the object n of `objects_buffer` has been fetched
+    fetched_objects: u16,
+    // X of the next pixel sent to the LCD
+    lcd_x: u8,
+    // Pixels dropped from the FIFO before the first one is shown, SCX fine scroll
+    discard: u8,
+    // Dots spent on the discarded first tile fetch at the start of the line
+    startup_dots: u8,
+    // The window has been drawn on this line
+    window_drawn: bool,
+
+    // Window internal line counter, only incremented on lines where the window is drawn
+    window_line: u8,
+    // Set once LY matched WY during the current frame
+    window_triggered: bool,
+
+    // STAT interrupt line, the interrupt is requested on its rising edge only
+    stat_line: bool,
+
+    // Last seen state of LCDC bit 7
+    lcd_on: bool,
+    // First frame after the LCD is turned on, it is not shown and its line 0 has no mode 2
+    first_frame: bool,
+
+    // Output settings of the host, not part of the machine state
+    pub output_palette: OutputPalette,
+    pub color_correction: ColorCorrection,
 }
 
-const WIDTH: u32 = 160;
-const HEIGHT: u32 = 144;
+pub const WIDTH: u32 = 160;
+pub const HEIGHT: u32 = 144;
+pub const FRAME_SIZE: usize = (WIDTH * HEIGHT * 4) as usize;
 
-impl PPU {
-    pub fn new() -> Self {
-        let event_loop = EventLoop::new();
-        let window = {
-            let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
-            WindowBuilder::new()
-                .with_title("Hello Pixels")
-                .with_inner_size(size)
-                .with_min_inner_size(size)
-                .with_resizable(false)
-                .build(&event_loop)
-                .unwrap()
-        };
+// The first tile of a line is fetched twice, the first fetch is thrown away
+const STARTUP_DOTS: u8 = 6;
+// Object fetch, not counting the wait for the background fetcher
+const OBJECT_FETCH_DOTS: u8 = 6;
 
-        let pixels = {
-            let window_size = window.inner_size();
-            let surface_texture =
-                SurfaceTexture::new(window_size.width, window_size.height, &window);
-            Pixels::new(WIDTH, HEIGHT, surface_texture).unwrap()
-        };
+impl Default for PPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+impl PPU {
+    pub fn new() -> Self {
         Self {
             dot_counter: 0,
-            event_loop,
-            window,
-            pixels,
+            frame: Box::new([0xFF; FRAME_SIZE]),
+            frame_ready: false,
             objects_buffer: Vec::with_capacity(10),
-            fifo_background: Vec::with_capacity(16),
-            fifo_object: Vec::with_capacity(16),
+            fifo_background: VecDeque::with_capacity(16),
+            fifo_object: VecDeque::with_capacity(16),
 
             oam_progress: 0,
+
+            fetcher: Fetcher::default(),
+            object_fetch: None,
+            fetched_objects: 0,
+            lcd_x: 0,
+            discard: 0,
+            startup_dots: 0,
+            window_drawn: false,
+
+            window_line: 0,
+            window_triggered: false,
+
+            stat_line: false,
+
+            lcd_on: true,
+            first_frame: false,
+
+            output_palette: OutputPalette::default(),
+            color_correction: ColorCorrection::default(),
         }
     }
 
     #[inline]
     fn update_lyc(memory: &mut Bus) {
         // Update LYC == LY
-        if memory.io.lcd.status.lyc == memory.io.lcd.status.ly {
-            memory.io.lcd.status.stat.lyc_ly = true;
-        } else {
-            memory.io.lcd.status.stat.lyc_ly = false;
-        }
+        memory.io.lcd.status.stat.lyc_ly = memory.io.lcd.status.lyc == memory.io.lcd.status.ly;
     }
 
-    fn update_stat_interupt(memory: &mut Bus) {
-        if memory.io.lcd.status.stat.lyc_ly && memory.io.lcd.status.stat.lyc_interupt {
+    /// Request the STAT interrupt when the line goes high. A source becoming active while
+    /// another one already holds the line up doesn't trigger a new interrupt (STAT blocking)
+    fn update_stat_interupt(&mut self, memory: &mut Bus) {
+        let status = &memory.io.lcd.status;
+        // Entering VBlank also raises the mode 2 source, like the start of any other line
+        let line = status.interrupt_line()
+            || (status.stat.mode_2_oam_interrupt && status.ly == 144 && self.dot_counter == 0);
+        if line && !self.stat_line {
             memory.interupt_flags.lcd_stat = true;
         }
+        self.stat_line = line;
     }
 
-    fn switch_to_mode0(memory: &mut Bus) {
+    fn switch_to_mode0(&mut self, memory: &mut Bus) {
+        if self.window_drawn {
+            self.window_line += 1;
+        }
         // Unlock VRAM
         memory.unlock(MemoryRegion::VRAM);
         // Unlock OAM
         memory.unlock(MemoryRegion::OAM);
+        memory.unlock(MemoryRegion::CGBPalette);
         memory.io.lcd.status.stat.ppu_mode = 0;
+        memory.io.hdma.hblank_started();
     }
 
     pub fn mode0(&mut self, memory: &mut Bus) {
         memory.io.lcd.status.stat.ppu_mode = 0;
+        // Line 0 after turning the LCD on stays in mode 0 instead of scanning OAM
+        if self.first_frame && memory.io.lcd.status.ly == 0 && self.dot_counter == 80 {
+            self.switch_to_mode3(memory);
+        }
     }
 
-    fn switch_to_mode1(memory: &mut Bus) {
+    fn switch_to_mode1(&mut self, memory: &mut Bus) {
+        self.first_frame = false;
+        self.frame_ready = true;
+        memory.interupt_flags.v_blank = true;
         memory.io.lcd.status.stat.ppu_mode = 1;
     }
 
-    pub fn mode1(&mut self, memory: &mut Bus) {
-        memory.interupt_flags.v_blank = true;
-    }
+    pub fn mode1(&mut self, _memory: &mut Bus) {}
 
     fn switch_to_mode2(&mut self, memory: &mut Bus) {
         self.objects_buffer.clear();
-        // push rendered pixels to screen
-        if let Err(err) = self.pixels.render() {
-            panic!("pixels.render failed: {}", err);
-        }
-        self.window.request_redraw();
 
         // Lock OAM
         memory.lock(MemoryRegion::OAM);
         memory.io.lcd.status.stat.ppu_mode = 2;
     }
 
+    /// Height in pixels of the objects selected by LCDC bit 2
+    fn object_height(memory: &Bus) -> u8 {
+        match memory.io.lcd.control.obj_size {
+            SpriteSize::Size8x8 => 8,
+            SpriteSize::Size8x16 => 16,
+        }
+    }
+
     pub fn mode2(&mut self, memory: &mut Bus) {
-        // Load OAM
-        if self.objects_buffer.len() < 10 && self.dot_counter % 2 == 0 {
-            let oa = memory.oam.data[(self.oam_progress) as usize];
-            self.objects_buffer.push(oa);
-            if memory.io.lcd.control.obj_size == SpriteSize::Size8x16 {
-                self.oam_progress += 1;
+        // Scan one OAM entry every 2 dots, keeping the first 10 ones on this line
+        if self.dot_counter.is_multiple_of(2) && (self.oam_progress as usize) < 40 {
+            let oa = memory.oam.data[self.oam_progress as usize];
+            // Y holds the object position plus 16
+            let line = memory.io.lcd.status.ly as u16 + 16;
+            let height = Self::object_height(memory) as u16;
+            if self.objects_buffer.len() < 10 && line >= oa.y as u16 && line < oa.y as u16 + height
+            {
+                let mut row = (line - oa.y as u16) as u8;
+                if oa.flags.y_flip {
+                    row = height as u8 - 1 - row;
+                }
                 self.objects_buffer
-                    .push(memory.oam.data[(self.oam_progress) as usize]);
+                    .push(BufferedObject { attribute: oa, row });
             }
             self.oam_progress += 1;
         }
@@ -154,34 +304,419 @@ impl PPU {
         self.oam_progress = 0;
         // Lock VRAM
         memory.lock(MemoryRegion::VRAM);
+        memory.lock(MemoryRegion::CGBPalette);
         memory.io.lcd.status.stat.ppu_mode = 3;
+
+        if memory.io.lcd.status.ly == memory.io.lcd.pos_scroll.wy {
+            self.window_triggered = true;
+        }
+        self.fifo_background.clear();
+        self.fifo_object.clear();
+        self.fetcher = Fetcher::default();
+        self.object_fetch = None;
+        self.fetched_objects = 0;
+        self.lcd_x = 0;
+        self.discard = memory.io.lcd.pos_scroll.scx % 8;
+        self.startup_dots = STARTUP_DOTS;
+        self.window_drawn = false;
     }
 
+    /// One dot of the pixel pipeline, mode 3 lasts until 160 pixels have been pushed
     pub fn mode3(&mut self, memory: &mut Bus) {
-        let frame = self.pixels.frame_mut();
-        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-            // let x = (i % WIDTH as usize) as i16;
-            // let y = (i / WIDTH as usize) as i16;
+        if self.startup_dots > 0 {
+            self.startup_dots -= 1;
+            return;
+        }
 
-            let rgba = [0x5e, 0x48, 0xe8, 0xff];
+        if let Some(mut fetch) = self.object_fetch {
+            if fetch.dots > OBJECT_FETCH_DOTS {
+                self.fetcher_step(memory);
+            }
+            fetch.dots -= 1;
+            if fetch.dots == 0 {
+                self.fetch_object(memory, fetch.object);
+                self.object_fetch = None;
+            } else {
+                self.object_fetch = Some(fetch);
+            }
+            return;
+        }
 
-            pixel.copy_from_slice(&rgba);
+        if let Some(object) = self.next_object(memory) {
+            // Wait for the background fetcher to be done with its tile
+            let wait = 5 - self.fetcher.dot.min(5);
+            self.object_fetch = Some(ObjectFetch {
+                object,
+                dots: wait + OBJECT_FETCH_DOTS,
+            });
+            return self.mode3(memory);
         }
-        if self.dot_counter == 252 {
-            Self::switch_to_mode0(memory);
+
+        if self.window_starts(memory) {
+            // Restart the fetcher on the window, the pixels already fetched are lost
+            self.fifo_background.clear();
+            self.fetcher = Fetcher {
+                window: true,
+                ..Default::default()
+            };
+            self.window_drawn = true;
+            // WX below 7 hides the leftmost columns of the window
+            if self.lcd_x == 0 {
+                self.discard = 7u8.saturating_sub(memory.io.lcd.pos_scroll.wx);
+            }
         }
+
+        self.fetcher_step(memory);
+        self.push_pixel(memory);
+
+        if self.lcd_x == WIDTH as u8 {
+            self.switch_to_mode0(memory);
+        }
+    }
+
+    /// Whether the window starts at the current pixel, LCDC, WY and WX are read live
+    fn window_starts(&self, memory: &Bus) -> bool {
+        let lcd = &memory.io.lcd;
+        !self.fetcher.window
+            && self.window_triggered
+            && lcd.control.window_enable
+            // On DMG, clearing LCDC bit 0 hides both the background and the window
+            && (lcd.control.bg_window_enable_priority
+                || Self::render_mode(memory) == HardwareMode::CGB)
+            // WX holds the window position plus 7
+            && self.lcd_x as u16 + 7 >= lcd.pos_scroll.wx as u16
+            && lcd.pos_scroll.wx <= 166
+    }
+
+    /// Index in `objects_buffer` of the next object to fetch at the current pixel
+    fn next_object(&self, memory: &Bus) -> Option<u8> {
+        if !memory.io.lcd.control.obj_enable || self.discard > 0 {
+            return None;
+        }
+        // X holds the object position plus 8, the smallest X goes first then OAM order
+        self.objects_buffer
+            .iter()
+            .enumerate()
+            .filter(|(i, object)| {
+                self.fetched_objects & (1 << i) == 0 && object.attribute.x <= self.lcd_x + 8
+            })
+            .min_by_key(|(_, object)| object.attribute.x)
+            .map(|(i, _)| i as u8)
+    }
+
+    fn vram(memory: &Bus, bank: VRAMBank) -> &[u8; 0x2000] {
+        match bank {
+            VRAMBank::Bank0 => &memory.vram.0,
+            VRAMBank::Bank1 => &memory.vram.1,
+        }
+    }
+
+    /// Address in VRAM of row `row` of the background/window tile `tile_index`
+    fn tile_row_address(memory: &Bus, tile_index: u8, row: u8) -> usize {
+        // 8000 addressing uses unsigned tile indexes, 8800 signed ones relative to 9000
+        let tile_address = match memory.io.lcd.control.bg_window_tile_data_area {
+            BGWindowTileDataArea::Area1 => 0x8000 + tile_index as usize * 16,
+            BGWindowTileDataArea::Area0 => (0x9000 + tile_index as i8 as isize * 16) as usize,
+        };
+        tile_address - 0x8000 + (row as usize % 8) * 2
+    }
+
+    /// Line of the background or window map the fetcher reads, SCY is read on every fetch
+    fn fetcher_line(&self, memory: &Bus) -> u8 {
+        let lcd = &memory.io.lcd;
+        if self.fetcher.window {
+            self.window_line
+        } else {
+            lcd.status.ly.wrapping_add(lcd.pos_scroll.scy)
+        }
+    }
+
+    fn fetcher_step(&mut self, memory: &Bus) {
+        let lcd = &memory.io.lcd;
+        let line = self.fetcher_line(memory);
+        let fetcher = &mut self.fetcher;
+        match fetcher.dot {
+            1 => {
+                let (map_address, column) = if fetcher.window {
+                    let map_address = match lcd.control.window_tile_map_area {
+                        WindowTileMapArea::Area0 => 0x9800,
+                        WindowTileMapArea::Area1 => 0x9C00,
+                    };
+                    (map_address, fetcher.tile_x)
+                } else {
+                    let map_address = match lcd.control.bg_tile_map_area {
+                        BGTileMapArea::Area0 => 0x9800,
+                        BGTileMapArea::Area1 => 0x9C00,
+                    };
+                    // SCX coarse scroll is read on every fetch
+                    (
+                        map_address,
+                        (lcd.pos_scroll.scx / 8).wrapping_add(fetcher.tile_x),
+                    )
+                };
+                let map_offset =
+                    map_address - 0x8000 + (line as usize / 8) * 32 + (column as usize % 32);
+                fetcher.tile_index = memory.vram.0[map_offset];
+                fetcher.attributes = match Self::render_mode(memory) {
+                    HardwareMode::DMG => ObjectAttributeFlags::default(),
+                    HardwareMode::CGB => memory.vram.1[map_offset].into(),
+                };
+            }
+            3 | 5 => {
+                let row = if fetcher.attributes.y_flip {
+                    7 - line % 8
+                } else {
+                    line % 8
+                };
+                let address = Self::tile_row_address(memory, fetcher.tile_index, row);
+                let vram = Self::vram(memory, fetcher.attributes.bank);
+                if fetcher.dot == 3 {
+                    fetcher.data_low = vram[address];
+                } else {
+                    fetcher.data_high = vram[address + 1];
+                }
+            }
+            6 => {
+                if !self.fifo_background.is_empty() {
+                    return;
+                }
+                for column in 0..8 {
+                    let bit = if fetcher.attributes.x_flip {
+                        column
+                    } else {
+                        7 - column
+                    };
+                    let color =
+                        ((fetcher.data_high >> bit) & 1) << 1 | ((fetcher.data_low >> bit) & 1);
+                    self.fifo_background.push_back(Pixel {
+                        color: color.into(),
+                        palette: PixelPalette::BGP,
+                        cgb_palette: fetcher.attributes.cgb_palette as u8,
+                        priority: 0,
+                        bg_priority: fetcher.attributes.priority,
+                    });
+                }
+                fetcher.tile_x = fetcher.tile_x.wrapping_add(1);
+                fetcher.dot = 0;
+                return;
+            }
+            _ => (),
+        }
+        fetcher.dot += 1;
+    }
+
+    /// Mix the pixels of the object `object` of `objects_buffer` into `fifo_object`
+    fn fetch_object(&mut self, memory: &Bus, object: u8) {
+        self.fetched_objects |= 1 << object;
+        let lcd = &memory.io.lcd;
+        let BufferedObject { attribute: oa, row } = self.objects_buffer[object as usize];
+        // In 8×16 mode the top tile has the index with bit 0 cleared
+        let tile_index = match lcd.control.obj_size {
+            SpriteSize::Size8x8 => oa.index,
+            SpriteSize::Size8x16 => oa.index & 0xFE,
+        };
+        let row_address = tile_index as usize * 16 + row as usize * 2;
+        let vram = match Self::render_mode(memory) {
+            HardwareMode::DMG => &memory.vram.0,
+            HardwareMode::CGB => Self::vram(memory, oa.flags.bank),
+        };
+        let low = vram[row_address];
+        let high = vram[row_address + 1];
+
+        for column in 0..8u8 {
+            // Columns left of the screen are skipped
+            let x = oa.x as i16 - 8 + column as i16;
+            if x < self.lcd_x as i16 {
+                continue;
+            }
+            let bit = if oa.flags.x_flip { column } else { 7 - column };
+            let pixel = Pixel {
+                color: (((high >> bit) & 1) << 1 | ((low >> bit) & 1)).into(),
+                palette: match oa.flags.dmg_palette {
+                    DMGPalette::OBP0 => PixelPalette::OBP0,
+                    DMGPalette::OBP1 => PixelPalette::OBP1,
+                },
+                cgb_palette: oa.flags.cgb_palette as u8,
+                priority: object,
+                bg_priority: oa.flags.priority,
+            };
+
+            let index = (x - self.lcd_x as i16) as usize;
+            while self.fifo_object.len() <= index {
+                self.fifo_object.push_back(Pixel {
+                    color: PixelColor::White,
+                    ..pixel
+                });
+            }
+            // On DMG objects fetched earlier have priority, they only lose to transparency.
+            // On CGB the lowest OAM index wins
+            let current = self.fifo_object[index];
+            let replace = match Self::render_mode(memory) {
+                HardwareMode::DMG => current.color == PixelColor::White,
+                HardwareMode::CGB => {
+                    current.color == PixelColor::White
+                        || (pixel.color != PixelColor::White && pixel.priority < current.priority)
+                }
+            };
+            if replace {
+                self.fifo_object[index] = pixel;
+            }
+        }
+    }
+
+    /// Shift one pixel out of the FIFOs to the LCD, palettes are read live
+    fn push_pixel(&mut self, memory: &Bus) {
+        let Some(bg) = self.fifo_background.pop_front() else {
+            return;
+        };
+        if self.discard > 0 {
+            self.discard -= 1;
+            return;
+        }
+        let object = self.fifo_object.pop_front();
+        let rgba = match memory.mode {
+            HardwareMode::DMG => self
+                .output_palette
+                .rgba(Self::dmg_color(memory, bg, object).1),
+            HardwareMode::CGB if memory.dmg_compatibility() => {
+                self.compatibility_color(memory, bg, object)
+            }
+            HardwareMode::CGB => self.cgb_color(memory, bg, object),
+        };
+
+        if !self.first_frame {
+            let ly = memory.io.lcd.status.ly as usize;
+            let offset = (ly * WIDTH as usize + self.lcd_x as usize) * 4;
+            self.frame[offset..offset + 4].copy_from_slice(&rgba);
+        }
+        self.lcd_x += 1;
+    }
+
+    /// Hardware the PPU draws as, DMG for a DMG cartridge on CGB
+    fn render_mode(memory: &Bus) -> HardwareMode {
+        if memory.dmg_compatibility() {
+            HardwareMode::DMG
+        } else {
+            memory.mode
+        }
+    }
+
+    /// Shade of the pixel and the palette it went through
+    fn dmg_color(memory: &Bus, bg: Pixel, object: Option<Pixel>) -> (PixelPalette, Color) {
+        let lcd = &memory.io.lcd;
+        let bg_color = if lcd.control.bg_window_enable_priority {
+            bg.color as u8
+        } else {
+            0
+        };
+        match object {
+            Some(object)
+                if lcd.control.obj_enable
+                    && object.color != PixelColor::White
+                    && !(object.bg_priority && bg_color != 0) =>
+            {
+                let palette = match object.palette {
+                    PixelPalette::OBP1 => lcd.palettes.obp1,
+                    _ => lcd.palettes.obp0,
+                };
+                (object.palette, palette.color(object.color as u8))
+            }
+            _ => (PixelPalette::BGP, lcd.palettes.bgp.color(bg_color)),
+        }
+    }
+
+    /// The DMG shades index BG palette 0, OBJ palette 0 for OBP0 and 1 for OBP1
+    fn compatibility_color(&self, memory: &Bus, bg: Pixel, object: Option<Pixel>) -> [u8; 4] {
+        let palettes = &memory.io.cgb_palettes;
+        let color = match Self::dmg_color(memory, bg, object) {
+            (PixelPalette::BGP, shade) => palettes.background.color(0, shade as u8),
+            (PixelPalette::OBP0, shade) => palettes.object.color(0, shade as u8),
+            (PixelPalette::OBP1, shade) => palettes.object.color(1, shade as u8),
+        };
+        self.color_correction.rgba(color)
+    }
+
+    /// On CGB, clearing LCDC bit 0 doesn't hide the background, objects just always go
+    /// over it. Otherwise background colors 1-3 win when either the BG map attributes
+    /// or the object ask for it
+    fn cgb_color(&self, memory: &Bus, bg: Pixel, object: Option<Pixel>) -> [u8; 4] {
+        let lcd = &memory.io.lcd;
+        let palettes = &memory.io.cgb_palettes;
+        let color = match object {
+            Some(object)
+                if lcd.control.obj_enable
+                    && object.color != PixelColor::White
+                    && !(lcd.control.bg_window_enable_priority
+                        && bg.color != PixelColor::White
+                        && (bg.bg_priority || object.bg_priority)) =>
+            {
+                palettes
+                    .object
+                    .color(object.cgb_palette, object.color as u8)
+            }
+            _ => palettes.background.color(bg.cgb_palette, bg.color as u8),
+        };
+        self.color_correction.rgba(color)
+    }
+
+    /// LCDC bit 7 cleared: LY and the mode drop to 0, VRAM and OAM are released and the
+    /// screen goes blank until the LCD is turned back on
+    fn turn_lcd_off(&mut self, memory: &mut Bus) {
+        self.lcd_on = false;
+        self.dot_counter = 0;
+        self.stat_line = false;
+        self.objects_buffer.clear();
+        self.fifo_background.clear();
+        self.fifo_object.clear();
+        self.object_fetch = None;
+        memory.io.lcd.status.ly = 0;
+        memory.io.lcd.status.stat.ppu_mode = 0;
+        memory.unlock(MemoryRegion::VRAM);
+        memory.unlock(MemoryRegion::OAM);
+        memory.unlock(MemoryRegion::CGBPalette);
+        let blank = match memory.mode {
+            HardwareMode::DMG => self.output_palette.rgba(Color::White),
+            HardwareMode::CGB => self.color_correction.rgba(0x7FFF),
+        };
+        for pixel in self.frame.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&blank);
+        }
+        self.frame_ready = true;
+    }
+
+    fn turn_lcd_on(&mut self) {
+        self.lcd_on = true;
+        self.first_frame = true;
+        self.dot_counter = 0;
     }
 
     pub fn step(&mut self, memory: &mut Bus) {
+        match (memory.io.lcd.control.lcd_enable, self.lcd_on) {
+            (false, true) => return self.turn_lcd_off(memory),
+            (false, false) => return,
+            (true, false) => self.turn_lcd_on(),
+            (true, true) => (),
+        }
         Self::update_lyc(memory);
-        Self::update_stat_interupt(memory);
 
-        if memory.io.lcd.status.ly == 144 && self.dot_counter == 0 {
-            Self::switch_to_mode1(memory);
-        }
-        if memory.io.lcd.status.ly == 153 {
-            memory.io.lcd.status.ly = 0;
-            self.switch_to_mode2(memory);
+        if self.dot_counter == 0 {
+            match memory.io.lcd.status.ly {
+                0 if self.first_frame => {
+                    self.window_line = 0;
+                    self.window_triggered = false;
+                    self.objects_buffer.clear();
+                    memory.io.lcd.status.stat.ppu_mode = 0;
+                }
+                0 => {
+                    // New frame
+                    self.window_line = 0;
+                    self.window_triggered = false;
+                    self.switch_to_mode2(memory);
+                }
+                1..=143 => self.switch_to_mode2(memory),
+                144 => self.switch_to_mode1(memory),
+                _ => (),
+            }
         }
 
         match memory.io.lcd.status.stat.ppu_mode {
@@ -191,23 +726,161 @@ impl PPU {
             3 => self.mode3(memory),
             _ => panic!("Invalid PPU mode"),
         }
+        self.update_stat_interupt(memory);
 
         // Advance dot counter
         self.dot_counter += 1;
         if self.dot_counter == 456 {
             self.dot_counter = 0;
-            memory.io.lcd.status.ly += 1;
+            memory.io.lcd.status.ly = (memory.io.lcd.status.ly + 1) % 154;
         }
     }
 
-    pub fn run_for(&mut self, memory: &mut Bus, cycles: u8) {
+    pub fn run_for(&mut self, memory: &mut Bus, cycles: u16) {
         memory.current_owner = MemoryLockOwner::PPU;
-        for _ in 0..cycles {
-            // Advance dot counter by 4 (4 dot per cycle in single speed mode)
-            self.step(memory);
-            self.step(memory);
-            self.step(memory);
+        // 4 dots per cycle in single speed mode, 2 in double speed
+        for _ in 0..cycles as u32 * memory.dots_per_cycle() as u32 {
             self.step(memory);
         }
     }
 }
+
+impl Snapshot for PPU {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.dot_counter);
+        writer.write_u8(self.oam_progress);
+        writer.write_u8(self.window_line);
+        writer.write_bool(self.window_triggered);
+        writer.write_u8(self.objects_buffer.len() as u8);
+        for object in self.objects_buffer.iter() {
+            for offset in 0..4 {
+                writer.write_u8(object.attribute.read_byte(offset));
+            }
+            writer.write_u8(object.row);
+        }
+        for fifo in [&self.fifo_background, &self.fifo_object] {
+            writer.write_u8(fifo.len() as u8);
+            for pixel in fifo.iter() {
+                pixel.save_state(writer);
+            }
+        }
+
+        let fetcher = &self.fetcher;
+        for value in [
+            fetcher.dot,
+            fetcher.tile_x,
+            fetcher.tile_index,
+            fetcher.attributes.into(),
+            fetcher.data_low,
+            fetcher.data_high,
+        ] {
+            writer.write_u8(value);
+        }
+        writer.write_bool(fetcher.window);
+        match self.object_fetch {
+            Some(fetch) => {
+                writer.write_bool(true);
+                writer.write_u8(fetch.object);
+                writer.write_u8(fetch.dots);
+            }
+            None => writer.write_bool(false),
+        }
+        writer.write_u16(self.fetched_objects);
+        writer.write_u8(self.lcd_x);
+        writer.write_u8(self.discard);
+        writer.write_u8(self.startup_dots);
+        writer.write_bool(self.window_drawn);
+        writer.write_bool(self.stat_line);
+        writer.write_bool(self.lcd_on);
+        writer.write_bool(self.first_frame);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        // Everything is read before being applied so a bad state leaves the PPU untouched
+        let dot_counter = reader.read_u16()?;
+        if dot_counter >= 456 {
+            return Err(SaveStateError::InvalidValue("dot counter"));
+        }
+        let oam_progress = reader.read_u8()?;
+        if oam_progress > 40 {
+            return Err(SaveStateError::InvalidValue("OAM scan"));
+        }
+        let window_line = reader.read_u8()?;
+        let window_triggered = reader.read_bool()?;
+        // At most 10 objects per line, `fetched_objects` keeps one bit for each
+        let objects = reader.read_u8()?;
+        if objects > 10 {
+            return Err(SaveStateError::InvalidValue("objects buffer"));
+        }
+        let mut objects_buffer = Vec::with_capacity(10);
+        for _ in 0..objects {
+            let mut attribute = ObjectAttribute::default();
+            for offset in 0..4 {
+                attribute.write_byte(offset, reader.read_u8()?);
+            }
+            let row = reader.read_u8()?;
+            if row >= 16 {
+                return Err(SaveStateError::InvalidValue("object row"));
+            }
+            objects_buffer.push(BufferedObject { attribute, row });
+        }
+        let mut fifos = [VecDeque::with_capacity(16), VecDeque::with_capacity(16)];
+        for fifo in fifos.iter_mut() {
+            for _ in 0..reader.read_u8()? {
+                fifo.push_back(Pixel::load_state(reader)?);
+            }
+        }
+
+        let fetcher = Fetcher {
+            dot: reader.read_u8()?,
+            tile_x: reader.read_u8()?,
+            tile_index: reader.read_u8()?,
+            attributes: reader.read_u8()?.into(),
+            data_low: reader.read_u8()?,
+            data_high: reader.read_u8()?,
+            window: reader.read_bool()?,
+        };
+        let object_fetch = if reader.read_bool()? {
+            Some(ObjectFetch {
+                object: reader.read_u8()?,
+                dots: reader.read_u8()?,
+            })
+        } else {
+            None
+        };
+        let fetched_objects = reader.read_u16()?;
+        let lcd_x = reader.read_u8()?;
+        let discard = reader.read_u8()?;
+        let startup_dots = reader.read_u8()?;
+        let window_drawn = reader.read_bool()?;
+        let stat_line = reader.read_bool()?;
+        let lcd_on = reader.read_bool()?;
+        let first_frame = reader.read_bool()?;
+        if lcd_x > WIDTH as u8 || fetcher.dot > 6 {
+            return Err(SaveStateError::InvalidValue("pixel fetcher"));
+        }
+        if matches!(object_fetch, Some(fetch) if fetch.object as usize >= objects_buffer.len()) {
+            return Err(SaveStateError::InvalidValue("object fetch"));
+        }
+
+        let [fifo_background, fifo_object] = fifos;
+        self.dot_counter = dot_counter;
+        self.oam_progress = oam_progress;
+        self.window_line = window_line;
+        self.window_triggered = window_triggered;
+        self.objects_buffer = objects_buffer;
+        self.fifo_background = fifo_background;
+        self.fifo_object = fifo_object;
+        self.fetcher = fetcher;
+        self.object_fetch = object_fetch;
+        self.fetched_objects = fetched_objects;
+        self.lcd_x = lcd_x;
+        self.discard = discard;
+        self.startup_dots = startup_dots;
+        self.window_drawn = window_drawn;
+        self.stat_line = stat_line;
+        self.lcd_on = lcd_on;
+        self.first_frame = first_frame;
+        Ok(())
+    }
+}
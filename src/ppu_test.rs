@@ -0,0 +1,671 @@
+#[cfg(test)]
+mod ppu_tests {
+    use crate::{
+        bus::{Bus, HardwareMode, Memory, MemoryLockOwner},
+        ppu::{PPU, WIDTH},
+    };
+
+    const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+    const LIGHT_GRAY: [u8; 4] = [0xAA, 0xAA, 0xAA, 0xFF];
+    const DARK_GRAY: [u8; 4] = [0x55, 0x55, 0x55, 0xFF];
+    const BLACK: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+    fn new_bus() -> Bus {
+        let mut bus = Bus::default();
+        bus.io.disable_boot_rom = 1;
+        // Identity palette
+        bus.write_byte(0xFF47, 0b1110_0100);
+        bus
+    }
+
+    // Fill the tile at `address` with the 2 bits color `color`
+    fn fill_tile(bus: &mut Bus, address: u16, color: u8) {
+        for row in 0..8 {
+            let row_address = address + row * 2;
+            bus.write_byte(row_address, if color & 1 != 0 { 0xFF } else { 0x00 });
+            bus.write_byte(row_address + 1, if color & 2 != 0 { 0xFF } else { 0x00 });
+        }
+    }
+
+    fn set_object(bus: &mut Bus, index: u16, y: u8, x: u8, tile: u8, flags: u8) {
+        let address = 0xFE00 + index * 4;
+        bus.write_byte(address, y);
+        bus.write_byte(address + 1, x);
+        bus.write_byte(address + 2, tile);
+        bus.write_byte(address + 3, flags);
+    }
+
+    fn render_frame(bus: &mut Bus) -> PPU {
+        let mut ppu = PPU::new();
+        while !ppu.frame_ready {
+            ppu.run_for(bus, 1);
+        }
+        ppu
+    }
+
+    fn pixel(ppu: &PPU, x: usize, y: usize) -> [u8; 4] {
+        let offset = (y * WIDTH as usize + x) * 4;
+        ppu.frame[offset..offset + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn background_unsigned_tiles() {
+        let mut bus = new_bus();
+        // LCD on, BG on, tile data at 8000, map at 9800
+        bus.write_byte(0xFF40, 0b1001_0001);
+        fill_tile(&mut bus, 0x8010, 3);
+        // Second tile of the first row uses tile 1
+        bus.write_byte(0x9801, 0x01);
+
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 7, 0), WHITE);
+        assert_eq!(pixel(&ppu, 8, 0), BLACK);
+        assert_eq!(pixel(&ppu, 15, 7), BLACK);
+        assert_eq!(pixel(&ppu, 16, 0), WHITE);
+        assert_eq!(pixel(&ppu, 8, 8), WHITE);
+    }
+
+    #[test]
+    fn background_signed_tiles_and_scroll() {
+        let mut bus = new_bus();
+        // LCD on, BG on, tile data at 8800, map at 9C00
+        bus.write_byte(0xFF40, 0b1000_1001);
+        // Tile -1 sits just below 9000, tile 0 at 9000
+        fill_tile(&mut bus, 0x8FF0, 2);
+        fill_tile(&mut bus, 0x9000, 1);
+        for i in 0..0x400 {
+            bus.write_byte(0x9C00 + i, 0xFF);
+        }
+        bus.write_byte(0x9C00, 0x00);
+        // Scroll by 4 pixels in both directions
+        bus.write_byte(0xFF42, 4);
+        bus.write_byte(0xFF43, 4);
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 0, 0), LIGHT_GRAY);
+        assert_eq!(pixel(&ppu, 3, 3), LIGHT_GRAY);
+        assert_eq!(pixel(&ppu, 4, 0), DARK_GRAY);
+        assert_eq!(pixel(&ppu, 0, 4), DARK_GRAY);
+    }
+
+    #[test]
+    fn bgp_palette_applied() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b1001_0001);
+        // Color 0 shown as black
+        bus.write_byte(0xFF47, 0b0000_0011);
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 0, 0), BLACK);
+        assert_eq!(pixel(&ppu, 159, 143), BLACK);
+    }
+
+    #[test]
+    fn window_uses_own_line_counter() {
+        let mut bus = new_bus();
+        // LCD on, window map at 9C00, window on, tile data at 8000, BG on
+        bus.write_byte(0xFF40, 0b1111_0001);
+        fill_tile(&mut bus, 0x8010, 3);
+        fill_tile(&mut bus, 0x8020, 1);
+        // First window row of tiles is black, the second one light gray
+        for i in 0..32 {
+            bus.write_byte(0x9C00 + i, 0x01);
+            bus.write_byte(0x9C20 + i, 0x02);
+        }
+        bus.write_byte(0xFF4A, 100);
+        bus.write_byte(0xFF4B, 7 + 80);
+
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 79, 100), WHITE);
+        assert_eq!(pixel(&ppu, 80, 99), WHITE);
+        assert_eq!(pixel(&ppu, 80, 100), BLACK);
+        assert_eq!(pixel(&ppu, 159, 107), BLACK);
+        assert_eq!(pixel(&ppu, 80, 108), LIGHT_GRAY);
+    }
+
+    #[test]
+    fn window_disabled_mid_frame_resumes_where_it_stopped() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b1111_0001);
+        fill_tile(&mut bus, 0x8010, 3);
+        fill_tile(&mut bus, 0x8020, 1);
+        for i in 0..32 {
+            bus.write_byte(0x9C00 + i, 0x01);
+            bus.write_byte(0x9C20 + i, 0x02);
+        }
+        bus.write_byte(0xFF4A, 0);
+        bus.write_byte(0xFF4B, 7);
+
+        let mut ppu = PPU::new();
+        // Draw 4 lines with the window, then 10 without
+        while bus.io.lcd.status.ly < 4 {
+            ppu.run_for(&mut bus, 1);
+        }
+        bus.write_byte(0xFF40, 0b1101_0001);
+        while bus.io.lcd.status.ly < 14 {
+            ppu.run_for(&mut bus, 1);
+        }
+        bus.write_byte(0xFF40, 0b1111_0001);
+        while !ppu.frame_ready {
+            ppu.run_for(&mut bus, 1);
+        }
+        // Line 14 shows window line 4, line 18 window line 8
+        assert_eq!(pixel(&ppu, 0, 17), BLACK);
+        assert_eq!(pixel(&ppu, 0, 18), LIGHT_GRAY);
+    }
+
+    #[test]
+    fn object_flip_and_palette() {
+        let mut bus = new_bus();
+        // LCD on, tile data at 8000, OBJ on, BG on
+        bus.write_byte(0xFF40, 0b1001_0011);
+        bus.write_byte(0xFF48, 0b1110_0100);
+        // OBP1 shows color 3 as light gray
+        bus.write_byte(0xFF49, 0b0100_0000);
+        // Tile 1: only the leftmost column is set with color 3
+        for row in 0..8 {
+            bus.write_byte(0x8010 + row * 2, 0x80);
+            bus.write_byte(0x8011 + row * 2, 0x80);
+        }
+        // Object at (10, 20) with OBP1 and X flip
+        set_object(&mut bus, 0, 20 + 16, 10 + 8, 0x01, 0b0011_0000);
+        // Same object at (30, 20) with OBP0 and no flip
+        set_object(&mut bus, 1, 20 + 16, 30 + 8, 0x01, 0b0000_0000);
+
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 10, 20), WHITE);
+        assert_eq!(pixel(&ppu, 17, 20), LIGHT_GRAY);
+        assert_eq!(pixel(&ppu, 17, 27), LIGHT_GRAY);
+        assert_eq!(pixel(&ppu, 17, 28), WHITE);
+        assert_eq!(pixel(&ppu, 30, 20), BLACK);
+        assert_eq!(pixel(&ppu, 31, 20), WHITE);
+    }
+
+    #[test]
+    fn object_behind_background() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b1001_0011);
+        bus.write_byte(0xFF48, 0b1110_0100);
+        fill_tile(&mut bus, 0x8010, 1);
+        fill_tile(&mut bus, 0x8020, 3);
+        // BG tile 1 on the first tile of the map only, the rest is color 0
+        bus.write_byte(0x9800, 0x01);
+        // Behind the BG, straddling the first and second BG tiles
+        set_object(&mut bus, 0, 16, 4 + 8, 0x02, 0b1000_0000);
+
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 7, 0), LIGHT_GRAY);
+        assert_eq!(pixel(&ppu, 8, 0), BLACK);
+    }
+
+    #[test]
+    fn object_priority_by_x_then_oam_index() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b1001_0011);
+        bus.write_byte(0xFF48, 0b1110_0100);
+        fill_tile(&mut bus, 0x8010, 1);
+        fill_tile(&mut bus, 0x8020, 2);
+        fill_tile(&mut bus, 0x8030, 3);
+        // Later in OAM but further left, it wins where both overlap
+        set_object(&mut bus, 0, 16, 12 + 8, 0x01, 0);
+        set_object(&mut bus, 1, 16, 8 + 8, 0x02, 0);
+        // Same X: the first in OAM wins
+        set_object(&mut bus, 2, 32, 40 + 8, 0x03, 0);
+        set_object(&mut bus, 3, 32, 40 + 8, 0x01, 0);
+
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 12, 0), DARK_GRAY);
+        assert_eq!(pixel(&ppu, 16, 0), LIGHT_GRAY);
+        assert_eq!(pixel(&ppu, 40, 16), BLACK);
+    }
+
+    #[test]
+    fn ten_objects_per_line_intersecting_ly() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b1001_0011);
+        bus.write_byte(0xFF48, 0b1110_0100);
+        fill_tile(&mut bus, 0x8010, 3);
+        // Objects on other lines don't use a slot
+        for i in 0..5 {
+            set_object(&mut bus, i, 100 + 16, 8 + i as u8 * 8, 0x01, 0);
+        }
+        // 11 objects on line 0, the last one is dropped
+        for i in 0..11 {
+            set_object(&mut bus, 5 + i, 16, 8 + i as u8 * 8, 0x01, 0);
+        }
+
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 72, 0), BLACK);
+        assert_eq!(pixel(&ppu, 80, 0), WHITE);
+        assert_eq!(pixel(&ppu, 32, 100), BLACK);
+    }
+
+    #[test]
+    fn tall_objects_ignore_tile_index_bit_0() {
+        let mut bus = new_bus();
+        // LCD on, tile data at 8000, 8x16 OBJ on, BG on
+        bus.write_byte(0xFF40, 0b1001_0111);
+        bus.write_byte(0xFF48, 0b1110_0100);
+        fill_tile(&mut bus, 0x8020, 1);
+        fill_tile(&mut bus, 0x8030, 3);
+        set_object(&mut bus, 0, 16, 8, 0x03, 0);
+        // Y flipped, the bottom tile is drawn first
+        set_object(&mut bus, 1, 16, 16, 0x03, 0b0100_0000);
+
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 0, 0), LIGHT_GRAY);
+        assert_eq!(pixel(&ppu, 0, 8), BLACK);
+        assert_eq!(pixel(&ppu, 0, 16), WHITE);
+        assert_eq!(pixel(&ppu, 8, 0), BLACK);
+        assert_eq!(pixel(&ppu, 8, 15), LIGHT_GRAY);
+    }
+
+    #[test]
+    fn object_size_switched_during_mode_3() {
+        let mut bus = new_bus();
+        // LCD on, tile data at 8000, 8x16 OBJ on, BG on
+        bus.write_byte(0xFF40, 0b1001_0111);
+        bus.write_byte(0xFF48, 0b1110_0100);
+        fill_tile(&mut bus, 0x8020, 1);
+        fill_tile(&mut bus, 0x8030, 3);
+        // Y flipped at (72, 0), covering lines 0 to 15
+        set_object(&mut bus, 0, 16, 72 + 8, 0x02, 0b0100_0000);
+
+        let mut ppu = PPU::new();
+        while bus.io.lcd.status.ly != 10 || bus.io.lcd.status.stat.ppu_mode != 3 {
+            ppu.step(&mut bus);
+        }
+        // 8x8 objects before the object is fetched, it keeps the row picked by the scan
+        bus.write_byte(0xFF40, 0b1001_0011);
+        while !ppu.frame_ready {
+            ppu.step(&mut bus);
+        }
+
+        assert_eq!(pixel(&ppu, 72, 3), BLACK);
+        assert_eq!(pixel(&ppu, 72, 10), LIGHT_GRAY);
+        assert_eq!(pixel(&ppu, 79, 10), LIGHT_GRAY);
+        // Too tall for the next scans
+        assert_eq!(pixel(&ppu, 72, 11), WHITE);
+    }
+
+    // Dots spent in mode 3 on line `line`
+    fn mode3_length(bus: &mut Bus, line: u8) -> u32 {
+        let mut ppu = PPU::new();
+        while bus.io.lcd.status.ly != line {
+            ppu.step(bus);
+        }
+        let mut dots = 0;
+        while bus.io.lcd.status.ly == line {
+            ppu.step(bus);
+            if bus.io.lcd.status.stat.ppu_mode == 3 {
+                dots += 1;
+            }
+        }
+        dots
+    }
+
+    #[test]
+    fn mode3_length_varies() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b1001_0011);
+        assert_eq!(mode3_length(&mut bus, 0), 172);
+
+        // Fine scroll discards pixels at the start of the line
+        bus.write_byte(0xFF43, 0x0B);
+        assert_eq!(mode3_length(&mut bus, 0), 175);
+        bus.write_byte(0xFF43, 0);
+
+        // Restarting the fetcher on the window
+        bus.write_byte(0xFF40, 0b1011_0011);
+        bus.write_byte(0xFF4A, 0);
+        bus.write_byte(0xFF4B, 7 + 80);
+        assert_eq!(mode3_length(&mut bus, 0), 178);
+        bus.write_byte(0xFF40, 0b1001_0011);
+
+        // An object costs 6 to 11 dots depending on the background fetcher
+        set_object(&mut bus, 0, 16, 8 + 40, 0, 0);
+        let length = mode3_length(&mut bus, 0);
+        assert!((178..=183).contains(&length), "{}", length);
+
+        // Objects only slow down the lines they are on
+        assert_eq!(mode3_length(&mut bus, 8), 172);
+
+        for i in 1..10 {
+            set_object(&mut bus, i, 16, 8 + 40, 0, 0);
+        }
+        assert!(mode3_length(&mut bus, 0) >= 172 + 60);
+    }
+
+    #[test]
+    fn mid_scanline_register_writes() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b1001_0001);
+        fill_tile(&mut bus, 0x8010, 3);
+        // Tiles 16 onwards of the first map row are black
+        for i in 16..32 {
+            bus.write_byte(0x9800 + i, 0x01);
+        }
+
+        let mut ppu = PPU::new();
+        // Mode 3 starts on dot 81, the first pixel comes out 12 dots later
+        for _ in 0..(81 + 12 + 40) {
+            ppu.step(&mut bus);
+        }
+        // Show color 0 as dark gray after 40 pixels
+        bus.write_byte(0xFF47, 0b1110_0110);
+        // Scroll by 64 pixels, only seen by the tiles fetched from now on
+        bus.write_byte(0xFF43, 64);
+        while !ppu.frame_ready {
+            ppu.step(&mut bus);
+        }
+
+        assert_eq!(pixel(&ppu, 39, 0), WHITE);
+        assert_eq!(pixel(&ppu, 63, 0), DARK_GRAY);
+        assert_eq!(pixel(&ppu, 64, 0), BLACK);
+        assert_eq!(pixel(&ppu, 159, 0), BLACK);
+        // Following lines use the new SCX from the start
+        assert_eq!(pixel(&ppu, 0, 1), DARK_GRAY);
+        assert_eq!(pixel(&ppu, 63, 1), DARK_GRAY);
+        assert_eq!(pixel(&ppu, 64, 1), BLACK);
+    }
+
+    // Interrupts of each kind requested during one frame, clearing IF as the CPU would
+    fn count_interrupts(bus: &mut Bus) -> (u32, u32) {
+        let mut ppu = PPU::new();
+        let (mut stat, mut v_blank) = (0, 0);
+        for _ in 0..154 * 456 {
+            ppu.step(bus);
+            if bus.interupt_flags.lcd_stat {
+                bus.interupt_flags.lcd_stat = false;
+                stat += 1;
+            }
+            if bus.interupt_flags.v_blank {
+                bus.interupt_flags.v_blank = false;
+                v_blank += 1;
+            }
+        }
+        (stat, v_blank)
+    }
+
+    #[test]
+    fn stat_interrupt_on_rising_edge() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b1001_0001);
+        bus.write_byte(0xFF45, 5);
+        // LYC source only
+        bus.write_byte(0xFF41, 0b0100_0000);
+        assert_eq!(count_interrupts(&mut bus), (1, 1));
+
+        // Mode 0 source only, once per visible line
+        bus.write_byte(0xFF41, 0b0000_1000);
+        assert_eq!(count_interrupts(&mut bus), (144, 1));
+
+        // Mode 1 source only, the line stays up during the whole VBlank
+        bus.write_byte(0xFF41, 0b0001_0000);
+        assert_eq!(count_interrupts(&mut bus), (1, 1));
+
+        // Mode 2 source only, every visible line and the start of line 144
+        bus.write_byte(0xFF41, 0b0010_0000);
+        assert_eq!(count_interrupts(&mut bus), (144 + 1, 1));
+    }
+
+    #[test]
+    fn lcd_off_drops_the_stat_line() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b1001_0001);
+        // LYC source, matching line 0
+        bus.write_byte(0xFF45, 0);
+        bus.write_byte(0xFF41, 0b0100_0000);
+        let mut ppu = PPU::new();
+        ppu.step(&mut bus);
+        assert!(bus.interupt_flags.lcd_stat);
+        bus.interupt_flags.lcd_stat = false;
+
+        bus.write_byte(0xFF40, 0b0001_0001);
+        ppu.step(&mut bus);
+        bus.write_byte(0xFF40, 0b1001_0001);
+        ppu.step(&mut bus);
+        assert!(bus.interupt_flags.lcd_stat);
+    }
+
+    #[test]
+    fn stat_blocking() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b1001_0001);
+        // LYC matches as the HBlank of line 4 ends and holds the line up until the end of
+        // line 5, so neither LYC nor the HBlank of line 5 request an interrupt
+        bus.write_byte(0xFF45, 5);
+        bus.write_byte(0xFF41, 0b0100_1000);
+        assert_eq!(count_interrupts(&mut bus), (143, 1));
+
+        // Mode 2 follows mode 0 without the line dropping, only the mode 2 of line 0
+        // after VBlank gets through
+        bus.write_byte(0xFF41, 0b0010_1000);
+        assert_eq!(count_interrupts(&mut bus), (144 + 1, 1));
+    }
+
+    #[test]
+    fn stat_and_ly_read_only_bits() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b1001_0001);
+        bus.write_byte(0xFF45, 0);
+        let mut ppu = PPU::new();
+        // Middle of mode 3 on line 0, LYC=LY
+        for _ in 0..100 {
+            ppu.step(&mut bus);
+        }
+        bus.write_byte(0xFF41, 0x00);
+        assert_eq!(bus.read_byte(0xFF41), 0b1000_0111);
+        bus.write_byte(0xFF41, 0xFF);
+        assert_eq!(bus.read_byte(0xFF41), 0b1111_1111);
+
+        bus.write_byte(0xFF44, 0x42);
+        assert_eq!(bus.read_byte(0xFF44), 0);
+    }
+
+    #[test]
+    fn lcd_off_resets_and_blanks() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b1001_0001);
+        // Color 0 shown as black
+        bus.write_byte(0xFF47, 0b0000_0011);
+        let mut ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 0, 0), BLACK);
+
+        // Stop in the middle of mode 3 of line 10
+        while bus.io.lcd.status.ly != 10 || bus.io.lcd.status.stat.ppu_mode != 3 {
+            ppu.step(&mut bus);
+        }
+        bus.write_byte(0xFF40, 0b0001_0001);
+        ppu.step(&mut bus);
+        assert_eq!(bus.read_byte(0xFF44), 0);
+        assert_eq!(bus.io.lcd.status.stat.ppu_mode, 0);
+        assert_eq!(pixel(&ppu, 0, 0), WHITE);
+        // VRAM is accessible to the CPU again
+        bus.current_owner = MemoryLockOwner::CPU;
+        bus.write_byte(0x8000, 0x12);
+        assert_eq!(bus.read_byte(0x8000), 0x12);
+
+        // Nothing moves while the LCD is off
+        bus.interupt_flags.v_blank = false;
+        for _ in 0..456 * 154 {
+            ppu.step(&mut bus);
+        }
+        assert_eq!(bus.read_byte(0xFF44), 0);
+        assert!(!bus.interupt_flags.v_blank);
+    }
+
+    #[test]
+    fn lcd_on_starts_with_a_blank_frame() {
+        let mut bus = new_bus();
+        bus.write_byte(0xFF40, 0b0001_0001);
+        bus.write_byte(0xFF47, 0b0000_0011);
+        let mut ppu = PPU::new();
+        ppu.step(&mut bus);
+
+        bus.write_byte(0xFF40, 0b1001_0001);
+        ppu.frame_ready = false;
+        // Line 0 skips the OAM scan
+        for _ in 0..80 {
+            ppu.step(&mut bus);
+            assert_eq!(bus.io.lcd.status.stat.ppu_mode, 0);
+        }
+        ppu.step(&mut bus);
+        assert_eq!(bus.io.lcd.status.stat.ppu_mode, 3);
+
+        while !ppu.frame_ready {
+            ppu.step(&mut bus);
+        }
+        assert_eq!(pixel(&ppu, 0, 0), WHITE);
+        assert_eq!(pixel(&ppu, 159, 143), WHITE);
+
+        // The next frame is drawn normally, starting with mode 2
+        ppu.frame_ready = false;
+        while bus.io.lcd.status.ly != 0 {
+            ppu.step(&mut bus);
+        }
+        ppu.step(&mut bus);
+        assert_eq!(bus.io.lcd.status.stat.ppu_mode, 2);
+        while !ppu.frame_ready {
+            ppu.step(&mut bus);
+        }
+        assert_eq!(pixel(&ppu, 0, 0), BLACK);
+        assert_eq!(pixel(&ppu, 159, 143), BLACK);
+    }
+
+    const RED: [u8; 4] = [0xFF, 0x00, 0x00, 0xFF];
+    const GREEN: [u8; 4] = [0x00, 0xFF, 0x00, 0xFF];
+    const BLUE: [u8; 4] = [0x00, 0x00, 0xFF, 0xFF];
+
+    fn new_cgb_bus() -> Bus {
+        let mut bus = new_bus();
+        bus.mode = HardwareMode::CGB;
+        bus
+    }
+
+    // Set the RGB555 color `color` of a background or object palette through BCPS/OCPS
+    fn set_cgb_color(bus: &mut Bus, object: bool, palette: u8, index: u8, color: u16) {
+        let specification = if object { 0xFF6A } else { 0xFF68 };
+        bus.write_byte(specification, 0x80 | (palette * 8 + index * 2));
+        let [low, high] = color.to_le_bytes();
+        bus.write_byte(specification + 1, low);
+        bus.write_byte(specification + 1, high);
+    }
+
+    #[test]
+    fn cgb_background_attributes_select_palette_and_bank() {
+        let mut bus = new_cgb_bus();
+        bus.write_byte(0xFF40, 0b1001_0001);
+        set_cgb_color(&mut bus, false, 0, 0, 0x03E0);
+        set_cgb_color(&mut bus, false, 2, 3, 0x001F);
+        bus.write_byte(0x9800, 0x01);
+        bus.write_byte(0xFF4F, 1);
+        fill_tile(&mut bus, 0x8010, 3);
+        // Palette 2, tile data from bank 1
+        bus.write_byte(0x9800, 0b0000_1010);
+        bus.write_byte(0xFF4F, 0);
+
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 0, 0), RED);
+        assert_eq!(pixel(&ppu, 7, 7), RED);
+        assert_eq!(pixel(&ppu, 8, 0), GREEN);
+        assert_eq!(pixel(&ppu, 0, 8), GREEN);
+    }
+
+    #[test]
+    fn cgb_background_attributes_flip_tiles() {
+        let mut bus = new_cgb_bus();
+        bus.write_byte(0xFF40, 0b1001_0001);
+        set_cgb_color(&mut bus, false, 0, 3, 0x001F);
+        // Only the left half of the first row is colored
+        bus.write_byte(0x8010, 0xF0);
+        bus.write_byte(0x8011, 0xF0);
+        bus.write_byte(0x9800, 0x01);
+        bus.write_byte(0xFF4F, 1);
+        bus.write_byte(0x9800, 0b0110_0000);
+        bus.write_byte(0xFF4F, 0);
+
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 0, 0), WHITE);
+        assert_eq!(pixel(&ppu, 4, 0), WHITE);
+        assert_eq!(pixel(&ppu, 3, 7), WHITE);
+        assert_eq!(pixel(&ppu, 4, 7), RED);
+        assert_eq!(pixel(&ppu, 7, 7), RED);
+    }
+
+    #[test]
+    fn cgb_background_priority_and_master_priority() {
+        let mut bus = new_cgb_bus();
+        bus.write_byte(0xFF40, 0b1001_0011);
+        set_cgb_color(&mut bus, false, 0, 1, 0x03E0);
+        set_cgb_color(&mut bus, true, 0, 3, 0x001F);
+        fill_tile(&mut bus, 0x8010, 1);
+        fill_tile(&mut bus, 0x8020, 3);
+        bus.write_byte(0x9800, 0x01);
+        // The first BG tile goes over objects
+        bus.write_byte(0xFF4F, 1);
+        bus.write_byte(0x9800, 0b1000_0000);
+        bus.write_byte(0xFF4F, 0);
+        set_object(&mut bus, 0, 16, 4 + 8, 0x02, 0);
+
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 0, 0), GREEN);
+        assert_eq!(pixel(&ppu, 4, 0), GREEN);
+        assert_eq!(pixel(&ppu, 8, 0), RED);
+
+        // LCDC bit 0 cleared: objects always on top, the background is still drawn
+        bus.write_byte(0xFF40, 0b1001_0010);
+        bus.io.lcd.status.ly = 0;
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 0, 0), GREEN);
+        assert_eq!(pixel(&ppu, 4, 0), RED);
+    }
+
+    #[test]
+    fn cgb_object_priority_by_oam_index() {
+        let mut bus = new_cgb_bus();
+        bus.write_byte(0xFF40, 0b1001_0011);
+        set_cgb_color(&mut bus, true, 0, 1, 0x03E0);
+        set_cgb_color(&mut bus, true, 1, 2, 0x7C00);
+        fill_tile(&mut bus, 0x8010, 1);
+        fill_tile(&mut bus, 0x8020, 2);
+        // Further left but later in OAM, it loses where both overlap
+        set_object(&mut bus, 0, 16, 12 + 8, 0x01, 0);
+        set_object(&mut bus, 1, 16, 8 + 8, 0x02, 0b0000_0001);
+
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 8, 0), BLUE);
+        assert_eq!(pixel(&ppu, 12, 0), GREEN);
+        assert_eq!(pixel(&ppu, 16, 0), GREEN);
+    }
+
+    #[test]
+    fn cgb_dmg_compatibility_uses_dmg_palettes() {
+        let mut bus = new_cgb_bus();
+        // Set by the boot ROM, locked once it is unmapped
+        bus.io.disable_boot_rom = 0;
+        bus.write_byte(0xFF4C, 0x04);
+        bus.write_byte(0xFF50, 0x01);
+        bus.write_byte(0xFF4C, 0x00);
+        assert!(bus.dmg_compatibility());
+
+        bus.write_byte(0xFF40, 0b1001_0011);
+        // OBP1 maps color 1 to shade 2
+        bus.write_byte(0xFF49, 0b0000_1000);
+        set_cgb_color(&mut bus, false, 0, 3, 0x03E0);
+        set_cgb_color(&mut bus, false, 2, 3, 0x7C00);
+        set_cgb_color(&mut bus, true, 1, 2, 0x001F);
+        set_cgb_color(&mut bus, true, 3, 1, 0x7C00);
+        fill_tile(&mut bus, 0x8010, 3);
+        fill_tile(&mut bus, 0x8020, 1);
+        bus.write_byte(0x9800, 0x01);
+        // BG attributes asking for palette 2 are ignored
+        bus.write_byte(0xFF4F, 1);
+        bus.write_byte(0x9800, 0b0000_0010);
+        bus.write_byte(0xFF4F, 0);
+        // OBP1 through attribute bit 4, the CGB palette bits are ignored
+        set_object(&mut bus, 0, 16, 8 + 8, 0x02, 0b0001_0011);
+
+        let ppu = render_frame(&mut bus);
+        assert_eq!(pixel(&ppu, 0, 0), GREEN);
+        assert_eq!(pixel(&ppu, 8, 0), RED);
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+const MAGIC: &[u8; 4] = b"GBSS";
+/// Bumped every time the layout of the snapshot changes
+pub const STATE_VERSION: u16 = 14;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaveStateError {
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    // The state was taken with another ROM
+    RomMismatch {
+        expected: (u8, u16),
+        actual: (u8, u16),
+    },
+    Truncated,
+    // Bytes left over once every section was read
+    TrailingData,
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::InvalidMagic => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported save state version {} (expected {})",
+                version, STATE_VERSION
+            ),
+            SaveStateError::RomMismatch { expected, actual } => write!(
+                f,
+                "save state belongs to another ROM (checksums {:#04X}/{:#06X}, loaded ROM {:#04X}/{:#06X})",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            SaveStateError::Truncated => write!(f, "truncated save state"),
+            SaveStateError::TrailingData => write!(f, "unexpected data after the save state"),
+            SaveStateError::InvalidValue(field) => write!(f, "invalid value for {}", field),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+#[derive(Default, Debug, Clone)]
+pub struct StateWriter {
+    pub data: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Start a snapshot for the ROM identified by its header and global checksums
+    pub fn new(rom_checksums: (u8, u16)) -> Self {
+        let mut writer = Self::default();
+        writer.write_bytes(MAGIC);
+        writer.write_u16(STATE_VERSION);
+        writer.write_u8(rom_checksums.0);
+        writer.write_u16(rom_checksums.1);
+        writer
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// Check the snapshot header against the version and the checksums of the loaded ROM
+    pub fn new(data: &'a [u8], rom_checksums: (u8, u16)) -> Result<Self, SaveStateError> {
+        let mut reader = Self { data, position: 0 };
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            return Err(SaveStateError::InvalidMagic);
+        }
+        let version = reader.read_u16()?;
+        if version != STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        let checksums = (reader.read_u8()?, reader.read_u16()?);
+        if checksums != rom_checksums {
+            return Err(SaveStateError::RomMismatch {
+                expected: checksums,
+                actual: rom_checksums,
+            });
+        }
+        Ok(reader)
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], SaveStateError> {
+        let end = self.position + length;
+        if end > self.data.len() {
+            return Err(SaveStateError::Truncated);
+        }
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn read_into(&mut self, buffer: &mut [u8]) -> Result<(), SaveStateError> {
+        buffer.copy_from_slice(self.read_bytes(buffer.len())?);
+        Ok(())
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SaveStateError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position == self.data.len()
+    }
+}
+
+/// Machine state that can be written to and restored from a save state
+pub trait Snapshot {
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError>;
+}
+
+/// Quick save slot `slot` of the ROM at `rom_path`, `<rom>.ss<slot>`
+pub fn slot_path(rom_path: impl AsRef<Path>, slot: u8) -> PathBuf {
+    rom_path.as_ref().with_extension(format!("ss{}", slot))
+}
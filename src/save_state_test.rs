@@ -0,0 +1,167 @@
+#[cfg(test)]
+mod save_state_tests {
+    use crate::{
+        bus::{oam::Oam, Bus, Memory},
+        cartridge::{header::CartridgeHeader, Cartridge},
+        cpu::CPU,
+        ppu::PPU,
+        save_state::{SaveStateError, Snapshot, StateReader, StateWriter, STATE_VERSION},
+    };
+
+    fn load_rom(title: &[u8]) -> Bus {
+        // MBC1 + RAM + battery, 128 KiB ROM, 32 KiB RAM
+        let mut rom = vec![0; 0x20000];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        rom[0x0147] = 0x03;
+        rom[0x0148] = 0x02;
+        rom[0x0149] = 0x03;
+        rom[0x014D] = CartridgeHeader::compute_header_checksum(&rom);
+        let global = CartridgeHeader::compute_global_checksum(&rom);
+        rom[0x014E..0x0150].copy_from_slice(&global.to_be_bytes());
+
+        let mut bus = Bus::default();
+        bus.load_rom(&Cartridge::new(rom).unwrap());
+        bus
+    }
+
+    fn save(bus: &Bus) -> Vec<u8> {
+        let mut writer = StateWriter::new(bus.rom_checksums());
+        bus.save_state(&mut writer);
+        writer.data
+    }
+
+    fn load(bus: &mut Bus, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(data, bus.rom_checksums())?;
+        bus.load_state(&mut reader)
+    }
+
+    #[test]
+    fn bus_roundtrip() {
+        let mut bus = load_rom(b"ROUNDTRIP");
+        bus.io.disable_boot_rom = 1;
+        bus.write_byte(0x0000, 0x0A);
+        bus.write_byte(0x2000, 0x03);
+        bus.write_byte(0x6000, 0x01);
+        bus.write_byte(0x4000, 0x02);
+        bus.write_byte(0xA000, 0x42);
+        bus.write_byte(0x8010, 0x11);
+        bus.write_byte(0xC020, 0x22);
+        bus.write_byte(0xD030, 0x33);
+        bus.write_byte(0xFE04, 0x44);
+        bus.write_byte(0xFF80, 0x55);
+        bus.write_byte(0xFF42, 0x66);
+        bus.write_byte(0xFF06, 0x77);
+        bus.write_byte(0xFFFF, 0x1F);
+        let state = save(&bus);
+
+        let mut restored = load_rom(b"ROUNDTRIP");
+        load(&mut restored, &state).unwrap();
+        for address in [
+            0x4000, 0xA000, 0x8010, 0xC020, 0xD030, 0xFE04, 0xFF80, 0xFF42, 0xFF06, 0xFF50, 0xFFFF,
+        ] {
+            assert_eq!(
+                restored.read_byte(address),
+                bus.read_byte(address),
+                "{:04X}",
+                address
+            );
+        }
+        assert_eq!(restored.external_ram[2][0], 0x42);
+        assert_eq!(save(&restored), state);
+    }
+
+    #[test]
+    fn other_rom_rejected() {
+        let bus = load_rom(b"FIRST");
+        let state = save(&bus);
+
+        let mut other = load_rom(b"SECOND");
+        other.write_byte(0xC000, 0x12);
+        assert!(matches!(
+            load(&mut other, &state),
+            Err(SaveStateError::RomMismatch { .. })
+        ));
+        assert_eq!(other.read_byte(0xC000), 0x12);
+    }
+
+    #[test]
+    fn incompatible_version_rejected() {
+        let mut bus = load_rom(b"VERSION");
+        let mut state = save(&bus);
+        state[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            load(&mut bus, &state),
+            Err(SaveStateError::UnsupportedVersion(STATE_VERSION + 1))
+        );
+        assert_eq!(
+            load(&mut bus, b"not a state"),
+            Err(SaveStateError::InvalidMagic)
+        );
+    }
+
+    #[test]
+    fn truncated_state_rejected() {
+        let mut bus = load_rom(b"TRUNCATED");
+        let state = save(&bus);
+        assert_eq!(
+            load(&mut bus, &state[..state.len() / 2]),
+            Err(SaveStateError::Truncated)
+        );
+    }
+
+    #[test]
+    fn trailing_data_rejected() {
+        let mut cpu = CPU::new();
+        cpu.memory_bus = load_rom(b"TRAILING");
+        let mut state = cpu.save_state();
+        state.push(0x00);
+
+        cpu.program_counter = 0x1234;
+        cpu.memory_bus.write_byte(0xC000, 0x12);
+        assert_eq!(cpu.load_state(&state), Err(SaveStateError::TrailingData));
+        assert_eq!(cpu.program_counter, 0x1234);
+        assert_eq!(cpu.memory_bus.read_byte(0xC000), 0x12);
+
+        state.pop();
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.program_counter, 0x0000);
+    }
+
+    /// Load `component` after patching its saved fields at `offset`, counted from the
+    /// start of its own state
+    fn load_patched<T: Snapshot>(
+        component: &mut T,
+        offset: usize,
+        bytes: &[u8],
+    ) -> Result<(), SaveStateError> {
+        let header = StateWriter::new((0, 0)).data.len();
+        let mut writer = StateWriter::new((0, 0));
+        component.save_state(&mut writer);
+        let mut state = writer.data;
+        state[header + offset..header + offset + bytes.len()].copy_from_slice(bytes);
+        let mut reader = StateReader::new(&state, (0, 0))?;
+        component.load_state(&mut reader)
+    }
+
+    #[test]
+    fn out_of_range_counters_rejected() {
+        let mut ppu = PPU::new();
+        // Dot counter, OAM scan progress, window line and trigger, then the object count
+        for (offset, bytes) in [(0, &456u16.to_le_bytes()[..]), (2, &[41]), (5, &[11])] {
+            assert!(matches!(
+                load_patched(&mut ppu, offset, bytes),
+                Err(SaveStateError::InvalidValue(_))
+            ));
+        }
+        load_patched(&mut ppu, 0, &455u16.to_le_bytes()).unwrap();
+        load_patched(&mut ppu, 2, &[40]).unwrap();
+
+        // DMA transfer flag, then its progress
+        let mut oam = Oam::default();
+        assert!(matches!(
+            load_patched(&mut oam, 1, &160u16.to_le_bytes()),
+            Err(SaveStateError::InvalidValue(_))
+        ));
+        load_patched(&mut oam, 1, &159u16.to_le_bytes()).unwrap();
+    }
+}
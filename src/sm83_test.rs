@@ -0,0 +1,355 @@
+#[cfg(test)]
+mod sm83_tests {
+    use std::{fs, path::Path};
+
+    use serde_json::Value;
+
+    use crate::{
+        bus::{Bus, Memory},
+        cpu::{BusAccess, CPU},
+    };
+
+    // Directory holding the SM83 single step test vectors, one `xx.json` or `cb xx.json`
+    // file per opcode. Overridden by the SM83_TESTS_DIR environment variable, the suite
+    // isn't part of the repository and runs with
+    // `SM83_TESTS_DIR=path/to/sm83/v1 cargo test single_step_vectors -- --ignored`
+    const TESTS_DIR: &str = "tests/sm83";
+
+    // Vectors in the same format for the instructions that used to be wrong, so the
+    // harness itself runs without the full suite on disk
+    const INLINE_TESTS: &str = r#"[
+        {
+            "name": "22 ld (hl+),a",
+            "initial": {"pc": 49152, "sp": 65534, "a": 66, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 0, "h": 208, "l": 255, "ime": 0, "ram": [[49152, 34]]},
+            "final": {"pc": 49153, "sp": 65534, "a": 66, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 0, "h": 209, "l": 0, "ime": 0, "ram": [[49152, 34], [53503, 66]]},
+            "cycles": [[49152, 34, "r-m"], [53503, 66, "-wm"]]
+        },
+        {
+            "name": "3a ld a,(hl-)",
+            "initial": {"pc": 49152, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 0, "h": 208, "l": 0, "ime": 0, "ram": [[49152, 58], [53248, 153]]},
+            "final": {"pc": 49153, "sp": 65534, "a": 153, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 0, "h": 207, "l": 255, "ime": 0, "ram": [[49152, 58], [53248, 153]]},
+            "cycles": [[49152, 58, "r-m"], [53248, 153, "r-m"]]
+        },
+        {
+            "name": "08 ld (a16),sp",
+            "initial": {"pc": 49152, "sp": 4660, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 8], [49153, 0], [49154, 208]]},
+            "final": {"pc": 49155, "sp": 4660, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 0, "h": 0, "l": 0, "ime": 0,
+                "ram": [[49152, 8], [49153, 0], [49154, 208], [53248, 52], [53249, 18]]},
+            "cycles": [[49152, 8, "r-m"], [49153, 0, "r-m"], [49154, 208, "r-m"],
+                [53248, 52, "-wm"], [53249, 18, "-wm"]]
+        },
+        {
+            "name": "e8 add sp,r8",
+            "initial": {"pc": 49152, "sp": 255, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 128, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 232], [49153, 1]]},
+            "final": {"pc": 49154, "sp": 256, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 48, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 232], [49153, 1]]},
+            "cycles": [[49152, 232, "r-m"], [49153, 1, "r-m"], null, null]
+        },
+        {
+            "name": "f8 ld hl,sp+r8",
+            "initial": {"pc": 49152, "sp": 65528, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 248], [49153, 2]]},
+            "final": {"pc": 49154, "sp": 65528, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 0, "h": 255, "l": 250, "ime": 0, "ram": [[49152, 248], [49153, 2]]},
+            "cycles": [[49152, 248, "r-m"], [49153, 2, "r-m"], null]
+        },
+        {
+            "name": "cb 11 rl c",
+            "initial": {"pc": 49152, "sp": 65534, "a": 0, "b": 0, "c": 128, "d": 0, "e": 0,
+                "f": 16, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 203], [49153, 17]]},
+            "final": {"pc": 49154, "sp": 65534, "a": 0, "b": 0, "c": 1, "d": 0, "e": 0,
+                "f": 16, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 203], [49153, 17]]},
+            "cycles": [[49152, 203, "r-m"], [49153, 17, "r-m"]]
+        },
+        {
+            "name": "cb 46 bit 0,(hl)",
+            "initial": {"pc": 49152, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 16, "h": 208, "l": 0, "ime": 0, "ram": [[49152, 203], [49153, 70]]},
+            "final": {"pc": 49154, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 176, "h": 208, "l": 0, "ime": 0, "ram": [[49152, 203], [49153, 70]]},
+            "cycles": [[49152, 203, "r-m"], [49153, 70, "r-m"], [53248, 0, "r-m"]]
+        },
+        {
+            "name": "27 daa",
+            "initial": {"pc": 49152, "sp": 65534, "a": 125, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 39]]},
+            "final": {"pc": 49153, "sp": 65534, "a": 131, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 39]]},
+            "cycles": [[49152, 39, "r-m"]]
+        },
+        {
+            "name": "98 sbc a,b",
+            "initial": {"pc": 49152, "sp": 65534, "a": 16, "b": 15, "c": 0, "d": 0, "e": 0,
+                "f": 16, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 152]]},
+            "final": {"pc": 49153, "sp": 65534, "a": 0, "b": 15, "c": 0, "d": 0, "e": 0,
+                "f": 224, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 152]]},
+            "cycles": [[49152, 152, "r-m"]]
+        },
+        {
+            "name": "20 jr nz,r8",
+            "initial": {"pc": 49152, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 32], [49153, 254]]},
+            "final": {"pc": 49152, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 32], [49153, 254]]},
+            "cycles": [[49152, 32, "r-m"], [49153, 254, "r-m"], null]
+        },
+        {
+            "name": "c0 ret nz",
+            "initial": {"pc": 49152, "sp": 65532, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 128, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 192]]},
+            "final": {"pc": 49153, "sp": 65532, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                "f": 128, "h": 0, "l": 0, "ime": 0, "ram": [[49152, 192]]},
+            "cycles": [[49152, 192, "r-m"], null]
+        }
+    ]"#;
+
+    fn register(state: &Value, name: &str) -> u16 {
+        state[name]
+            .as_u64()
+            .unwrap_or_else(|| panic!("Missing register {}", name)) as u16
+    }
+
+    fn ram(state: &Value) -> impl Iterator<Item = (u16, u8)> + '_ {
+        state["ram"].as_array().unwrap().iter().map(|entry| {
+            (
+                entry[0].as_u64().unwrap() as u16,
+                entry[1].as_u64().unwrap() as u8,
+            )
+        })
+    }
+
+    /// Bus activity expected for each M-cycle: `[address, value, "r-m"]` for a read,
+    /// `[address, value, "-wm"]` for a write, null or `"---"` when the CPU works internally
+    fn cycles(test: &Value) -> Vec<Option<BusAccess>> {
+        test["cycles"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|cycle| {
+                let kind = cycle[2].as_str().unwrap_or("---");
+                let address = cycle[0].as_u64()? as u16;
+                let value = cycle[1].as_u64()? as u8;
+                if kind.contains('r') {
+                    Some(BusAccess::Read(address, value))
+                } else if kind.contains('w') {
+                    Some(BusAccess::Write(address, value))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Store `value` behind `address`, straight into the ROM below 0x8000
+    fn poke(bus: &mut Bus, address: u16, value: u8) {
+        match address {
+            0x0000..=0x3FFF => bus.rom[address as usize] = value,
+            0x4000..=0x7FFF => bus.banked_rom[0][address as usize - 0x4000] = value,
+            _ => bus.write_byte(address, value),
+        }
+    }
+
+    /// Whether the vector only touches plain memory: the registers and the unusable area
+    /// don't behave as RAM on the bus, and the ROM ignores writes
+    fn plain_memory(test: &Value) -> bool {
+        let register = |address: u16| matches!(address, 0xFEA0..=0xFF7F | 0xFFFF);
+        let ram_ok = ram(&test["initial"])
+            .chain(ram(&test["final"]))
+            .all(|(address, _)| !register(address));
+        let cycles_ok = cycles(test)
+            .into_iter()
+            .flatten()
+            .all(|access| match access {
+                BusAccess::Read(address, _) => !register(address),
+                BusAccess::Write(address, _) => !register(address) && address >= 0x8000,
+            });
+        ram_ok && cycles_ok
+    }
+
+    fn load(cpu: &mut CPU, state: &Value) {
+        cpu.registers.a = register(state, "a") as u8;
+        cpu.registers.b = register(state, "b") as u8;
+        cpu.registers.c = register(state, "c") as u8;
+        cpu.registers.d = register(state, "d") as u8;
+        cpu.registers.e = register(state, "e") as u8;
+        cpu.registers.f = (register(state, "f") as u8).into();
+        cpu.registers.h = register(state, "h") as u8;
+        cpu.registers.l = register(state, "l") as u8;
+        cpu.program_counter = register(state, "pc");
+        cpu.stack_pointer = register(state, "sp");
+        cpu.interupt_master_enable = register(state, "ime") != 0;
+        cpu.is_halted = false;
+        cpu.is_stopped = false;
+        cpu.ei_pending = false;
+        cpu.halt_bug = false;
+        cpu.is_locked = false;
+        for (address, value) in ram(state) {
+            poke(&mut cpu.memory_bus, address, value);
+        }
+    }
+
+    /// Differences between the CPU and the expected state, empty when they match
+    fn compare(cpu: &CPU, state: &Value) -> Vec<String> {
+        let registers = [
+            ("a", cpu.registers.a as u16),
+            ("b", cpu.registers.b as u16),
+            ("c", cpu.registers.c as u16),
+            ("d", cpu.registers.d as u16),
+            ("e", cpu.registers.e as u16),
+            ("f", u8::from(cpu.registers.f) as u16),
+            ("h", cpu.registers.h as u16),
+            ("l", cpu.registers.l as u16),
+            ("pc", cpu.program_counter),
+            ("sp", cpu.stack_pointer),
+            // The vectors end right after EI, before the delay runs out
+            ("ime", (cpu.interupt_master_enable || cpu.ei_pending) as u16),
+        ];
+        let mut errors = Vec::new();
+        for (name, actual) in registers {
+            let expected = register(state, name);
+            if actual != expected {
+                errors.push(format!(
+                    "{} = {:#x}, expected {:#x}",
+                    name, actual, expected
+                ));
+            }
+        }
+        for (address, expected) in ram(state) {
+            let actual = cpu.memory_bus.read_byte(address);
+            if actual != expected {
+                errors.push(format!(
+                    "({:#06x}) = {:#04x}, expected {:#04x}",
+                    address, actual, expected
+                ));
+            }
+        }
+        errors
+    }
+
+    /// Run every vector of a JSON array on a CPU with a ROM only cartridge and the LCD off,
+    /// returns one message per failing vector. Vectors reaching beyond plain memory are
+    /// skipped
+    fn run_tests(json: &str) -> Vec<String> {
+        let tests: Value = serde_json::from_str(json).unwrap();
+        let mut cpu = CPU::new();
+        cpu.memory_bus.io.disable_boot_rom = 1;
+        // Keeps the PPU from locking VRAM and OAM
+        cpu.memory_bus.write_byte(0xFF40, 0x00);
+
+        let mut failures = Vec::new();
+        for test in tests.as_array().unwrap() {
+            if !plain_memory(test) {
+                continue;
+            }
+            load(&mut cpu, &test["initial"]);
+            cpu.bus_trace = Some(Vec::new());
+            cpu.step();
+            let trace = cpu.bus_trace.take().unwrap();
+
+            let mut errors = compare(&cpu, &test["final"]);
+            let expected = cycles(test);
+            if trace.len() != expected.len() {
+                errors.push(format!(
+                    "took {} M-cycles, expected {}",
+                    trace.len(),
+                    expected.len()
+                ));
+            }
+            if let Some((cycle, (actual, expected))) = trace
+                .iter()
+                .zip(&expected)
+                .enumerate()
+                .find(|(_, (actual, expected))| actual != expected)
+            {
+                errors.push(format!(
+                    "M-cycle {}: {:?}, expected {:?}",
+                    cycle, actual, expected
+                ));
+            }
+            if !errors.is_empty() {
+                failures.push(format!("{}: {}", test["name"], errors.join(", ")));
+            }
+
+            // Leave the memory clean for the next vector
+            for (address, _) in ram(&test["initial"]).chain(ram(&test["final"])) {
+                poke(&mut cpu.memory_bus, address, 0);
+            }
+        }
+        failures
+    }
+
+    #[test]
+    fn inline_vectors() {
+        let failures = run_tests(INLINE_TESTS);
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    #[ignore = "needs the SM83 test vectors, see TESTS_DIR"]
+    fn single_step_vectors() {
+        let dir = std::env::var("SM83_TESTS_DIR").unwrap_or_else(|_| TESTS_DIR.to_string());
+        let entries = fs::read_dir(Path::new(&dir))
+            .unwrap_or_else(|err| panic!("No SM83 test vectors in {}: {}", dir, err));
+        let mut files: Vec<_> = entries
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .collect();
+        assert!(!files.is_empty(), "No SM83 test vectors in {}", dir);
+        files.sort();
+
+        let mut failures = Vec::new();
+        for file in files {
+            // STOP depends on KEY1 and the joypad, it has its own tests
+            if file.file_stem().is_some_and(|stem| stem == "10") {
+                continue;
+            }
+            let json = fs::read_to_string(&file).unwrap();
+            let file_failures = run_tests(&json);
+            if !file_failures.is_empty() {
+                failures.push(format!(
+                    "{}: {} failures, first {}",
+                    file.display(),
+                    file_failures.len(),
+                    file_failures[0]
+                ));
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn illegal_opcode_locks_the_cpu() {
+        let mut cpu = CPU::new();
+        cpu.memory_bus.io.disable_boot_rom = 1;
+        cpu.program_counter = 0x0000;
+        cpu.memory_bus.rom[0x0000] = 0xD3;
+        cpu.memory_bus.rom[0x0001] = 0x04;
+        cpu.interupt_master_enable = true;
+        cpu.memory_bus.interupt_enable.v_blank = true;
+
+        assert_eq!(cpu.step(), 1);
+        assert!(cpu.is_locked);
+        assert_eq!(cpu.program_counter, 0x0001);
+
+        // Neither the next instruction nor an interrupt runs, time keeps going
+        cpu.memory_bus.interupt_flags.v_blank = true;
+        let div = cpu.memory_bus.io.timer_divider.system_counter();
+        for _ in 0..100 {
+            assert_eq!(cpu.step(), 1);
+        }
+        assert_eq!(cpu.program_counter, 0x0001);
+        assert_eq!(cpu.registers.b, 0);
+        assert_eq!(cpu.stack_pointer, 0xFFFE);
+        assert_ne!(cpu.memory_bus.io.timer_divider.system_counter(), div);
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::bus::audio::output::AudioOutput;
+
+const HEADER_SIZE: u32 = 44;
+
+/// 16 bits stereo PCM WAV file, the sizes in the header are filled in by `finish`
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    // Bytes of samples written so far
+    data_size: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        let channels = 2u16;
+        let block_align = channels * 2;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            data_size: 0,
+        })
+    }
+
+    /// Append interleaved left and right samples
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    /// Write the final sizes in the header, returns the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Drains the APU output into a WAV file after each frame
+pub struct AudioRecorder<W: Write + Seek> {
+    wav: WavWriter<W>,
+    buffer: Vec<i16>,
+}
+
+impl AudioRecorder<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> io::Result<Self> {
+        Ok(Self::new(WavWriter::create(path, sample_rate)?))
+    }
+}
+
+impl<W: Write + Seek> AudioRecorder<W> {
+    pub fn new(wav: WavWriter<W>) -> Self {
+        Self {
+            wav,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Write every sample available in `output`
+    pub fn record(&mut self, output: &mut AudioOutput) -> io::Result<()> {
+        self.buffer.resize(output.available() * 2, 0);
+        let frames = output.drain(&mut self.buffer);
+        self.wav.write_samples(&self.buffer[..frames * 2])
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        self.wav.finish()
+    }
+}